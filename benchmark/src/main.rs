@@ -31,6 +31,11 @@ struct Args {
     /// Maximum length of unknown words.
     #[clap(short = 'M', long)]
     max_grouping_len: Option<usize>,
+
+    /// Concatenates every given number of input lines into a sentence
+    /// to measure the speed on long inputs.
+    #[clap(long, default_value = "1")]
+    concat_lines: usize,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -49,6 +54,10 @@ fn main() -> Result<(), Box<dyn Error>> {
         .lines()
         .map(|l| l.unwrap())
         .collect();
+    let lines: Vec<_> = lines
+        .chunks(args.concat_lines.max(1))
+        .map(|chunk| chunk.concat())
+        .collect();
 
     let mut measure = |t: &mut Timer| {
         let mut n_words = 0;
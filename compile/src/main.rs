@@ -3,7 +3,7 @@ use std::fs::File;
 use std::path::PathBuf;
 use std::time::Instant;
 
-use vibrato::dictionary::SystemDictionaryBuilder;
+use vibrato::dictionary::{CostWidth, Folding, SourceEncoding, SystemDictionaryBuilder};
 
 use clap::{error::ErrorKind, CommandFactory, Parser};
 
@@ -32,6 +32,10 @@ struct Args {
     #[clap(short = 'c', long)]
     char_in: PathBuf,
 
+    /// Definition file of unknown-word patterns (pattern.def, in UTF-8).
+    #[clap(short = 'p', long)]
+    unk_patterns_in: Option<PathBuf>,
+
     /// File to which the binary dictionary is output (in zstd).
     #[clap(short = 'o', long)]
     sysdic_out: PathBuf,
@@ -53,6 +57,52 @@ struct Args {
     /// This option is enabled when bi-gram information is specified.
     #[clap(long)]
     dual_connector: bool,
+
+    /// Maximum number of cells of the matrix of --dual-connector.
+    ///
+    /// Feature templates are moved from the matrix to the raw scorer until the matrix is
+    /// no larger than this size. If not specified, the matrix is minimized.
+    #[clap(long, requires = "dual_connector")]
+    dual_max_matrix_size: Option<usize>,
+
+    /// Feature columns to be stored, separated by commas (e.g., `0,7`).
+    ///
+    /// Column 0 indicates the first feature column, i.e., the fifth column of lex.csv and unk.def.
+    /// Features are rebuilt from the given columns in the given order.
+    #[clap(long, value_delimiter = ',')]
+    feature_columns: Option<Vec<usize>>,
+
+    /// Drops all features to build a segmentation-only dictionary.
+    #[clap(long, conflicts_with = "feature_columns")]
+    no_features: bool,
+
+    /// Character encoding of the input files. Choices are utf-8, euc-jp, and shift_jis.
+    #[clap(long, default_value = "utf-8")]
+    encoding: SourceEncoding,
+
+    /// Indexes surfaces with hiragana folded into katakana for kana-insensitive matching.
+    #[clap(long)]
+    fold_kana: bool,
+
+    /// Indexes surfaces with fullwidth ASCII and halfwidth katakana folded
+    /// for width-insensitive matching.
+    #[clap(long)]
+    fold_width: bool,
+
+    /// Builds an index for typo-tolerant matching, which enlarges the dictionary.
+    #[clap(long)]
+    typo_index: bool,
+
+    /// Compresses the matrix given with --matrix-in into 8 bits per cost,
+    /// allowing the given maximum absolute error of each connection cost.
+    #[clap(long, requires = "matrix_in", conflicts_with = "cost_width")]
+    compress_matrix: Option<u16>,
+
+    /// Width of the costs stored in the dictionary in bits. Choices are 16 and 32.
+    ///
+    /// Specify 32 if the matrix or the lexicons contain costs out of the 16-bit range.
+    #[clap(long, default_value = "16")]
+    cost_width: CostWidth,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -60,8 +110,28 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     eprintln!("Compiling the system dictionary...");
     let start = Instant::now();
+    let feature_columns = if args.no_features {
+        Some(vec![])
+    } else {
+        args.feature_columns
+    };
+    let builder = SystemDictionaryBuilder::new()
+        .feature_columns(feature_columns)
+        .encoding(args.encoding)
+        .folding(Folding::new().kana(args.fold_kana).width(args.fold_width))
+        .typo_index(args.typo_index)
+        .cost_width(args.cost_width)
+        .compress_matrix(args.compress_matrix)
+        .dual_connector_max_matrix_size(args.dual_max_matrix_size)
+        .dual_connector_progress(|removal| {
+            eprintln!(
+                "Removed feature template: #{}, matrix size: {}",
+                removal.template,
+                removal.matrix_size()
+            );
+        });
     let dict = if let Some(matrix_in) = args.matrix_in {
-        SystemDictionaryBuilder::from_readers(
+        builder.build_from_readers(
             File::open(args.lexicon_in)?,
             File::open(matrix_in)?,
             File::open(args.char_in)?,
@@ -72,7 +142,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         args.bigram_left_in,
         args.bigram_cost_in,
     ) {
-        SystemDictionaryBuilder::from_readers_with_bigram_info(
+        builder.build_from_readers_with_bigram_info(
             File::open(args.lexicon_in)?,
             File::open(bigram_right_in)?,
             File::open(bigram_left_in)?,
@@ -89,7 +159,19 @@ fn main() -> Result<(), Box<dyn Error>> {
             )
             .exit();
     };
+    let dict = if let Some(unk_patterns_in) = args.unk_patterns_in {
+        dict.reset_unk_patterns_from_reader(Some(File::open(unk_patterns_in)?))?
+    } else {
+        dict
+    };
     eprintln!("{} seconds", start.elapsed().as_secs_f64());
+    if args.compress_matrix.is_some() {
+        eprintln!(
+            "Compressed the matrix into {} bytes with a maximum error of {}",
+            dict.memory_usage().connector.matrix,
+            dict.connection_cost_error()
+        );
+    }
 
     eprintln!(
         "Writing the system dictionary in zstd...: {:?}",
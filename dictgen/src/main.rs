@@ -2,6 +2,7 @@ use std::fs::File;
 use std::path::PathBuf;
 
 use clap::Parser;
+use vibrato::dictionary::{CostWidth, SourceEncoding};
 use vibrato::trainer::Model;
 
 #[derive(Parser, Debug)]
@@ -28,6 +29,11 @@ struct Args {
     #[clap(long)]
     user_lexicon_in: Option<PathBuf>,
 
+    /// Character encoding of the user-defined lexicon file.
+    /// Choices are utf-8, euc-jp, and shift_jis.
+    #[clap(long, default_value = "utf-8")]
+    user_lexicon_encoding: SourceEncoding,
+
     /// A file to which the user-defined lexicon is output.
     #[clap(long)]
     user_lexicon_out: Option<PathBuf>,
@@ -38,6 +44,11 @@ struct Args {
     /// The file names are suffixed with `.left`, `.right`, and `.cost`.
     #[clap(long)]
     conn_id_info_out: Option<PathBuf>,
+
+    /// Number of bits of the output costs, 16 or 32. Weights are scaled to fill the range,
+    /// and a 32-bit dictionary must be compiled with `--cost-width 32`.
+    #[clap(long, default_value = "16")]
+    cost_width: CostWidth,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -46,10 +57,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let model_rdr = zstd::Decoder::new(File::open(args.model_in)?)?;
 
     let mut model = Model::read_model(model_rdr)?;
+    model.set_cost_width(args.cost_width);
 
     if let Some(path) = args.user_lexicon_in {
         let rdr = File::open(path)?;
-        model.read_user_lexicon(rdr)?;
+        model.read_user_lexicon_with_encoding(rdr, args.user_lexicon_encoding)?;
     }
 
     let lexicon_wtr = File::create(args.lexicon_out)?;
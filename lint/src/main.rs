@@ -0,0 +1,73 @@
+use std::error::Error;
+use std::fs::File;
+use std::path::PathBuf;
+
+use vibrato::dictionary::{CostWidth, SourceEncoding};
+use vibrato::lint::Linter;
+
+use clap::Parser;
+
+#[derive(Parser, Debug)]
+#[clap(
+    name = "lint",
+    about = "A program to check the sources of the system dictionary."
+)]
+struct Args {
+    /// System lexicon file (lex.csv).
+    #[clap(short = 'l', long)]
+    lexicon_in: PathBuf,
+
+    /// Matrix definition file (matrix.def).
+    #[clap(short = 'm', long)]
+    matrix_in: PathBuf,
+
+    /// Unknown word definition file (unk.def).
+    #[clap(short = 'u', long)]
+    unk_in: PathBuf,
+
+    /// Character definition file (char.def).
+    #[clap(short = 'c', long)]
+    char_in: PathBuf,
+
+    /// Character encoding of the sources. Choices are utf-8, euc-jp, and shift_jis.
+    #[clap(long, default_value = "utf-8")]
+    encoding: SourceEncoding,
+
+    /// Number of bits of costs with which the dictionary is compiled, 16 or 32.
+    #[clap(long, default_value = "16")]
+    cost_width: CostWidth,
+
+    /// Word and connection costs whose absolute values are no less than this value
+    /// are reported as suspicious. The default is close to the limit of the cost width.
+    #[clap(long)]
+    cost_threshold: Option<i32>,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    eprintln!("Linting the dictionary sources...");
+    let mut linter = Linter::new()
+        .encoding(args.encoding)
+        .cost_width(args.cost_width);
+    if let Some(cost_threshold) = args.cost_threshold {
+        linter = linter.cost_threshold(cost_threshold);
+    }
+    let diagnostics = linter.lint_from_readers(
+        File::open(args.lexicon_in)?,
+        File::open(args.matrix_in)?,
+        File::open(args.char_in)?,
+        File::open(args.unk_in)?,
+    )?;
+
+    for d in &diagnostics {
+        println!("{d}");
+    }
+    eprintln!("{} problems found", diagnostics.len());
+
+    if !diagnostics.is_empty() {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
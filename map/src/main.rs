@@ -3,15 +3,16 @@ use std::fs::File;
 use std::io::{prelude::*, BufReader};
 use std::path::PathBuf;
 
-use vibrato::dictionary::Dictionary;
+use vibrato::dictionary::{Dictionary, HOT_COVERAGE};
 
-use clap::Parser;
+use clap::{ArgGroup, Parser};
 
 #[derive(Parser, Debug)]
 #[clap(
     name = "map",
     about = "A program to edit connection ids with the reordered mapping."
 )]
+#[clap(group(ArgGroup::new("mapping").required(true).args(&["mapping_in", "corpus_in"])))]
 struct Args {
     /// System dictionary in binary to be edited (in zstd).
     #[clap(short = 'i', long)]
@@ -20,7 +21,12 @@ struct Args {
     /// Basename of files of the reordered mappings.
     /// Two files *.lmap and *.rmap will be input.
     #[clap(short = 'm', long)]
-    mapping_in: PathBuf,
+    mapping_in: Option<PathBuf>,
+
+    /// Corpus of sentences, one per line, from which the mapping is computed in one step
+    /// instead of --mapping-in.
+    #[clap(long)]
+    corpus_in: Option<PathBuf>,
 
     /// File to which the edited dictionary is output (in zstd).
     #[clap(short = 'o', long)]
@@ -34,18 +40,51 @@ fn main() -> Result<(), Box<dyn Error>> {
     let reader = zstd::Decoder::new(File::open(args.sysdic_in)?)?;
     let dict = Dictionary::read(reader)?;
 
-    eprintln!("Loading and doing the mapping...");
-    let lmap = {
-        let mut filename = args.mapping_in.clone();
-        filename.set_extension("lmap");
-        load_mapping(File::open(filename)?)?
-    };
-    let rmap = {
-        let mut filename = args.mapping_in.clone();
-        filename.set_extension("rmap");
-        load_mapping(File::open(filename)?)?
+    let dict = if let Some(corpus_in) = args.corpus_in {
+        eprintln!("Reordering connection ids with the corpus...");
+        let reader = BufReader::new(File::open(corpus_in)?);
+        // Streams the lines, stopping at the first I/O error, which is reported afterward.
+        let mut io_error = None;
+        let lines = reader
+            .lines()
+            .map_while(|line| line.map_err(|e| io_error = Some(e)).ok());
+        let result = dict.reorder_connection_ids_by_corpus(lines);
+        if let Some(e) = io_error {
+            return Err(e.into());
+        }
+        let (dict, report) = result?;
+        eprintln!("Sentences: {}", report.num_sentences);
+        for (side, locality) in [("Left", report.left), ("Right", report.right)] {
+            eprintln!(
+                "{side} ids covering {:.0}% of lookups: {}, spanning {} -> {} ids",
+                HOT_COVERAGE * 100.,
+                locality.hot_ids,
+                locality.span_before,
+                locality.span_after
+            );
+        }
+        eprintln!(
+            "Hot matrix cells: {} -> {} ({:.1}x denser)",
+            report.hot_cells_before(),
+            report.hot_cells_after(),
+            report.improvement()
+        );
+        dict
+    } else {
+        let mapping_in = args.mapping_in.unwrap();
+        eprintln!("Loading and doing the mapping...");
+        let lmap = {
+            let mut filename = mapping_in.clone();
+            filename.set_extension("lmap");
+            load_mapping(File::open(filename)?)?
+        };
+        let rmap = {
+            let mut filename = mapping_in;
+            filename.set_extension("rmap");
+            load_mapping(File::open(filename)?)?
+        };
+        dict.map_connection_ids_from_iter(lmap, rmap)?
     };
-    let dict = dict.map_connection_ids_from_iter(lmap, rmap)?;
 
     eprintln!(
         "Writing the mapped system dictionary...: {:?}",
@@ -0,0 +1,70 @@
+use std::error::Error;
+use std::fs::File;
+use std::path::PathBuf;
+
+use vibrato::dictionary::{ConnectorKind, Dictionary};
+
+use clap::Parser;
+
+#[derive(Parser, Debug)]
+#[clap(
+    name = "stats",
+    about = "A program to print statistics and the memory usage of a dictionary."
+)]
+struct Args {
+    /// System dictionary (in zstd).
+    #[clap(short = 'i', long)]
+    sysdic: PathBuf,
+
+    /// User lexicon file (in csv).
+    #[clap(short = 'u', long)]
+    userlex_csv: Option<PathBuf>,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    eprintln!("Loading the dictionary...");
+    let reader = zstd::Decoder::new(File::open(args.sysdic)?)?;
+    let mut dict = Dictionary::read(reader)?;
+
+    if let Some(userlex_csv) = args.userlex_csv {
+        eprintln!("Loading the user lexicon...");
+        dict = dict.reset_user_lexicon_from_reader(Some(File::open(userlex_csv)?))?;
+    }
+
+    println!("[Counts]");
+    println!("system entries:\t{}", dict.num_system_entries());
+    println!("user entries:\t{}", dict.num_user_entries());
+    println!("unk entries:\t{}", dict.num_unk_entries());
+    println!("unk patterns:\t{}", dict.num_unk_patterns());
+    println!("left ids:\t{}", dict.num_left_ids());
+    println!("right ids:\t{}", dict.num_right_ids());
+    println!("categories:\t{}", dict.num_categories());
+    println!("cost bits:\t{}", dict.cost_width().bits());
+
+    let usage = dict.memory_usage();
+    let kind = match usage.connector.kind {
+        ConnectorKind::Matrix => "matrix",
+        ConnectorKind::Raw => "raw",
+        ConnectorKind::Dual => "dual",
+        ConnectorKind::CompressedMatrix => "compressed matrix",
+        ConnectorKind::Custom => "custom",
+    };
+    println!();
+    println!("[Memory usage in bytes]");
+    println!("trie:\t{}", usage.trie);
+    println!("postings:\t{}", usage.postings);
+    println!("params:\t{}", usage.params);
+    println!("features:\t{}", usage.features);
+    println!("connector ({kind}):\t{}", usage.connector.total());
+    println!("  matrix:\t{}", usage.connector.matrix);
+    println!("  feature ids:\t{}", usage.connector.feature_ids);
+    println!("  scorer:\t{}", usage.connector.scorer);
+    println!("char property:\t{}", usage.char_prop);
+    println!("unk handler:\t{}", usage.unk_handler);
+    println!("mapper:\t{}", usage.mapper);
+    println!("total:\t{}", usage.total());
+
+    Ok(())
+}
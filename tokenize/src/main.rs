@@ -4,7 +4,12 @@ use std::io::{BufRead, BufWriter, Write};
 use std::path::PathBuf;
 use std::str::FromStr;
 
-use vibrato::dictionary::Dictionary;
+use vibrato::dictionary::{Dictionary, Folding};
+use vibrato::normalizer::Normalizer;
+use vibrato::token::TokenIter;
+use vibrato::tokenizer::beam::BeamPruning;
+use vibrato::tokenizer::stream::{TokenStream, DEFAULT_CHUNK_LEN};
+use vibrato::tokenizer::typo::TypoTolerance;
 use vibrato::Tokenizer;
 
 use clap::Parser;
@@ -28,6 +33,23 @@ impl FromStr for OutputMode {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FoldKind {
+    Kana,
+    Width,
+}
+
+impl FromStr for FoldKind {
+    type Err = &'static str;
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind {
+            "kana" => Ok(Self::Kana),
+            "width" => Ok(Self::Width),
+            _ => Err("Could not parse a folding"),
+        }
+    }
+}
+
 #[derive(Parser, Debug)]
 #[clap(name = "tokenize", about = "Predicts morphemes")]
 struct Args {
@@ -39,6 +61,17 @@ struct Args {
     #[clap(short = 'u', long)]
     userlex_csv: Option<PathBuf>,
 
+    /// Character definition file (char.def) overriding that in the system dictionary.
+    ///
+    /// Only categories defined in the system dictionary can be used.
+    #[clap(long)]
+    char_in: Option<PathBuf>,
+
+    /// Character definition entries (in the char.def format) patching those in the system
+    /// dictionary, applied after --char-in.
+    #[clap(long)]
+    char_patch: Option<PathBuf>,
+
     /// Output mode. Choices are mecab, wakati, and detail.
     #[clap(short = 'O', long, default_value = "mecab")]
     output_mode: OutputMode,
@@ -47,9 +80,139 @@ struct Args {
     #[clap(short = 'S', long)]
     ignore_space: bool,
 
+    /// Never splits extended grapheme clusters, such as emoji sequences, into tokens.
+    #[clap(long)]
+    respect_grapheme_clusters: bool,
+
+    /// Additionally matches words in the lexicons after folding, separated by commas:
+    /// kana (hiragana and katakana) and/or width (fullwidth and halfwidth forms),
+    /// which requires a dictionary compiled with the same folding, e.g., --fold-kana.
+    #[clap(long, value_delimiter = ',')]
+    folded_matching: Vec<FoldKind>,
+
+    /// Matches words in the lexicons within one edit or after collapsing repeated characters,
+    /// which requires a dictionary compiled with --typo-index.
+    #[clap(long)]
+    typo_tolerance: bool,
+
+    /// Additional cost of words matched with --typo-tolerance.
+    #[clap(long, default_value = "3000", requires = "typo_tolerance")]
+    typo_penalty: i16,
+
+    /// Applies NFKC to input strings before tokenization.
+    ///
+    /// Surfaces are output as they are in the input strings.
+    #[clap(long)]
+    nfkc: bool,
+
+    /// Folds fullwidth ASCII and halfwidth katakana in input strings before tokenization.
+    #[clap(long)]
+    fold_width: bool,
+
+    /// Converts input strings into lowercase before tokenization.
+    #[clap(long)]
+    lowercase: bool,
+
+    /// Removes soft hyphens in input strings before tokenization.
+    #[clap(long)]
+    remove_soft_hyphens: bool,
+
     /// Maximum length of unknown words.
     #[clap(short = 'M', long)]
     max_grouping_len: Option<usize>,
+
+    /// Number of connection costs cached for dictionaries compiled with bigram information.
+    ///
+    /// The hit rate of the cache is printed at the end.
+    #[clap(long)]
+    connection_cache: Option<usize>,
+
+    /// Maximum number of nodes kept at each position of the lattice (beam pruning).
+    ///
+    /// This bounds the time and memory per character for very long inputs
+    /// at the risk of a slightly different result.
+    #[clap(long)]
+    beam_width: Option<usize>,
+
+    /// Maximum difference of the accumulated cost of a node from the best one at each position
+    /// of the lattice (beam pruning).
+    #[clap(long)]
+    beam_threshold: Option<u32>,
+
+    /// Compares the results of beam pruning with those of the exact search,
+    /// printing the number of changed sentences at the end.
+    #[clap(long)]
+    beam_verify: bool,
+
+    /// Tokenizes long lines in chunks cut at points where the result never changes,
+    /// so that the memory does not grow with the length of a line.
+    #[clap(long)]
+    stream: bool,
+
+    /// Number of characters tokenized at once with --stream.
+    #[clap(long, default_value_t = DEFAULT_CHUNK_LEN, requires = "stream")]
+    chunk_len: usize,
+}
+
+/// Writes tokens, where `first` indicates whether they start a line.
+fn write_tokens<W>(
+    out: &mut W,
+    tokens: TokenIter,
+    mode: &OutputMode,
+    first: bool,
+) -> Result<(), Box<dyn Error>>
+where
+    W: Write,
+{
+    match mode {
+        OutputMode::Mecab => {
+            for t in tokens {
+                out.write_all(t.surface_original().as_bytes())?;
+                out.write_all(b"\t")?;
+                out.write_all(t.feature_expanded().as_bytes())?;
+                out.write_all(b"\n")?;
+            }
+        }
+        OutputMode::Wakati => {
+            for (i, t) in tokens.enumerate() {
+                if i != 0 || !first {
+                    out.write_all(b" ")?;
+                }
+                out.write_all(t.surface_original().as_bytes())?;
+            }
+        }
+        OutputMode::Detail => {
+            for t in tokens {
+                writeln!(
+                    out,
+                    "{}\t{}\tlex_type={:?}\tleft_id={}\tright_id={}\tword_cost={}\ttotal_cost={}",
+                    t.surface_original(),
+                    t.feature_expanded(),
+                    t.lex_type(),
+                    t.left_id(),
+                    t.right_id(),
+                    t.word_cost(),
+                    t.total_cost(),
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes the end of a line.
+fn write_line_end<W>(out: &mut W, mode: &OutputMode, is_tty: bool) -> Result<(), Box<dyn Error>>
+where
+    W: Write,
+{
+    match mode {
+        OutputMode::Mecab | OutputMode::Detail => out.write_all(b"EOS\n")?,
+        OutputMode::Wakati => out.write_all(b"\n")?,
+    }
+    if is_tty {
+        out.flush()?;
+    }
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -59,13 +222,48 @@ fn main() -> Result<(), Box<dyn Error>> {
     let reader = zstd::Decoder::new(File::open(args.sysdic)?)?;
     let mut dict = Dictionary::read(reader)?;
 
+    if let Some(char_in) = args.char_in {
+        dict = dict.reset_char_prop_from_reader(File::open(char_in)?)?;
+    }
+
+    if let Some(char_patch) = args.char_patch {
+        dict = dict.patch_char_prop_from_reader(File::open(char_patch)?)?;
+    }
+
     if let Some(userlex_csv) = args.userlex_csv {
         dict = dict.reset_user_lexicon_from_reader(Some(File::open(userlex_csv)?))?;
     }
 
+    let normalizer = (args.nfkc || args.fold_width || args.lowercase || args.remove_soft_hyphens)
+        .then(|| {
+            Normalizer::new()
+                .nfkc(args.nfkc)
+                .fold_width(args.fold_width)
+                .lowercase(args.lowercase)
+                .remove_soft_hyphens(args.remove_soft_hyphens)
+        });
+    let use_beam = args.beam_width.is_some() || args.beam_threshold.is_some();
     let tokenizer = Tokenizer::new(dict)
+        .normalizer(normalizer)
         .ignore_space(args.ignore_space)?
-        .max_grouping_len(args.max_grouping_len.unwrap_or(0));
+        .max_grouping_len(args.max_grouping_len.unwrap_or(0))
+        .connection_cache(args.connection_cache.unwrap_or(0))
+        .beam_pruning(use_beam.then(|| {
+            BeamPruning::new()
+                .width(args.beam_width)
+                .threshold(args.beam_threshold)
+                .verify(args.beam_verify)
+        }))
+        .respect_grapheme_clusters(args.respect_grapheme_clusters)
+        .folded_matching(
+            Folding::new()
+                .kana(args.folded_matching.contains(&FoldKind::Kana))
+                .width(args.folded_matching.contains(&FoldKind::Width)),
+        )?
+        .typo_tolerance(
+            args.typo_tolerance
+                .then(|| TypoTolerance::new().penalty(args.typo_penalty)),
+        )?;
     let mut worker = tokenizer.new_worker();
 
     eprintln!("Ready to tokenize");
@@ -74,58 +272,52 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let out = std::io::stdout();
     let mut out = BufWriter::new(out.lock());
-    let lines = std::io::stdin().lock().lines();
-    for line in lines {
-        let line = line?;
-        worker.reset_sentence(line);
-        worker.tokenize();
-        match args.output_mode {
-            OutputMode::Mecab => {
-                for i in 0..worker.num_tokens() {
-                    let t = worker.token(i);
-                    out.write_all(t.surface().as_bytes())?;
-                    out.write_all(b"\t")?;
-                    out.write_all(t.feature().as_bytes())?;
-                    out.write_all(b"\n")?;
-                }
-                out.write_all(b"EOS\n")?;
-                if is_tty {
-                    out.flush()?;
-                }
-            }
-            OutputMode::Wakati => {
-                for i in 0..worker.num_tokens() {
-                    if i != 0 {
-                        out.write_all(b" ")?;
-                    }
-                    out.write_all(worker.token(i).surface().as_bytes())?;
-                }
-                out.write_all(b"\n")?;
-                if is_tty {
-                    out.flush()?;
-                }
-            }
-            OutputMode::Detail => {
-                for i in 0..worker.num_tokens() {
-                    let t = worker.token(i);
-                    writeln!(
-                        &mut out,
-                        "{}\t{}\tlex_type={:?}\tleft_id={}\tright_id={}\tword_cost={}\ttotal_cost={}",
-                        t.surface(),
-                        t.feature(),
-                        t.lex_type(),
-                        t.left_id(),
-                        t.right_id(),
-                        t.word_cost(),
-                        t.total_cost(),
-                    )?;
-                }
-                out.write_all(b"EOS\n")?;
-                if is_tty {
-                    out.flush()?;
-                }
+    if args.stream {
+        let mut stream =
+            TokenStream::new(worker, std::io::stdin().lock()).chunk_len(args.chunk_len);
+        let mut first = true;
+        while stream.next_chunk()? {
+            write_tokens(&mut out, stream.token_iter(), &args.output_mode, first)?;
+            first = stream.ends_line();
+            if first {
+                write_line_end(&mut out, &args.output_mode, is_tty)?;
             }
         }
+        if stream.num_forced_cuts() != 0 {
+            eprintln!(
+                "Forced cuts at token boundaries: {}",
+                stream.num_forced_cuts()
+            );
+        }
+        worker = stream.into_worker();
+    } else {
+        for line in std::io::stdin().lock().lines() {
+            worker.reset_sentence(line?);
+            worker.try_tokenize()?;
+            write_tokens(&mut out, worker.token_iter(), &args.output_mode, true)?;
+            write_line_end(&mut out, &args.output_mode, is_tty)?;
+        }
+    }
+    out.flush()?;
+
+    if let Some(stats) = worker.connection_cache_stats() {
+        eprintln!(
+            "Connection cache: hits={}, misses={}, hit_rate={:.4}",
+            stats.hits,
+            stats.misses,
+            stats.hit_rate()
+        );
+    }
+    if use_beam {
+        let stats = worker.pruning_stats();
+        eprint!(
+            "Beam pruning: sentences={}, pruned_sentences={}, pruned_nodes={}",
+            stats.sentences, stats.pruned_sentences, stats.pruned_nodes
+        );
+        if args.beam_verify {
+            eprint!(", changed_sentences={}", stats.changed_sentences);
+        }
+        eprintln!();
     }
 
     Ok(())
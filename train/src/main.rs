@@ -2,6 +2,7 @@ use std::fs::File;
 use std::path::PathBuf;
 
 use clap::Parser;
+use vibrato::dictionary::SourceEncoding;
 use vibrato::trainer::{Corpus, Trainer, TrainerConfig};
 
 #[derive(Parser, Debug)]
@@ -51,6 +52,12 @@ struct Args {
     /// Number of threads.
     #[clap(long, default_value = "1")]
     num_threads: usize,
+
+    /// Character encoding of the lexicon, unknown word, character, feature, and rewrite
+    /// definition files. Choices are utf-8, euc-jp, and shift_jis.
+    /// The corpus must be in UTF-8.
+    #[clap(long, default_value = "utf-8")]
+    encoding: SourceEncoding,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -61,12 +68,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let unk_handler_rdr = File::open(args.seed_unk)?;
     let feature_templates_rdr = File::open(args.feature_def)?;
     let rewrite_rules_rdr = File::open(args.rewrite_def)?;
-    let config = TrainerConfig::from_readers(
+    let config = TrainerConfig::from_readers_with_encoding(
         lexicon_rdr,
         char_prop_rdr,
         unk_handler_rdr,
         feature_templates_rdr,
         rewrite_rules_rdr,
+        args.encoding,
     )?;
 
     let trainer = Trainer::new(config)?
@@ -8,11 +8,13 @@ pub const fn bincode_config() -> config::Configuration<LittleEndian, Fixint> {
         .with_fixed_int_encoding()
 }
 
-/// The maximam length of an input sentence.
+/// The maximam length of an input sentence in characters.
 ///
-/// Note that the value must be represented with u16 so that
-/// an (exclusive) end position can be represented in 16 bits.
-pub const MAX_SENTENCE_LENGTH: usize = usize::MAX;
+/// Note that the value must be represented with u32 so that
+/// an (exclusive) end position can be represented in 32 bits.
+/// Longer sentences are rejected by
+/// [`Worker::try_tokenize()`](crate::tokenizer::worker::Worker::try_tokenize).
+pub const MAX_SENTENCE_LENGTH: usize = u32::MAX as usize;
 
 /// The fixed connection id of BOS/EOS.
 pub const BOS_EOS_CONNECTION_ID: u16 = 0;
@@ -2,6 +2,9 @@
 pub mod builder;
 pub(crate) mod character;
 pub(crate) mod connector;
+pub(crate) mod cost_width;
+pub(crate) mod encoding;
+pub(crate) mod folding;
 pub(crate) mod lexicon;
 pub(crate) mod mapper;
 pub(crate) mod unknown;
@@ -13,22 +16,29 @@ use bincode::{Decode, Encode};
 
 use crate::common;
 use crate::dictionary::character::CharProperty;
-use crate::dictionary::connector::{Connector, ConnectorWrapper};
+use crate::dictionary::connector::{BoxedConnector, Connector, ConnectorWrapper};
 use crate::dictionary::lexicon::Lexicon;
 use crate::dictionary::mapper::ConnIdMapper;
 use crate::dictionary::unknown::UnkHandler;
 use crate::errors::{Result, VibratoError};
+use crate::Tokenizer;
 
 pub use crate::dictionary::builder::SystemDictionaryBuilder;
+pub use crate::dictionary::connector::{CustomConnector, TemplateRemoval};
+pub use crate::dictionary::cost_width::CostWidth;
+pub use crate::dictionary::encoding::SourceEncoding;
+pub use crate::dictionary::folding::Folding;
 pub use crate::dictionary::word_idx::WordIdx;
 
-pub(crate) use crate::dictionary::lexicon::WordParam;
+pub use crate::dictionary::lexicon::WordParam;
+pub use crate::dictionary::mapper::{IdLocality, ReorderReport, HOT_COVERAGE};
 
-const MODEL_MAGIC: &[u8] = b"VibratoTokenizer 0.5\n";
+const MODEL_MAGIC: &[u8] = b"VibratoTokenizer 0.6\n";
 
 /// Type of a lexicon that contains the word.
 #[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, Decode, Encode)]
 #[repr(u8)]
+#[non_exhaustive]
 pub enum LexType {
     /// System lexicon.
     #[default]
@@ -37,6 +47,82 @@ pub enum LexType {
     User,
     /// Unknown words.
     Unknown,
+    /// Words generated by [`UnkGenerator`](crate::tokenizer::generator::UnkGenerator)s
+    /// added to the tokenizer.
+    Generated,
+}
+
+/// Type of a connector stored in the dictionary.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Hash)]
+pub enum ConnectorKind {
+    /// Connector with a matrix of connection costs.
+    Matrix,
+    /// Connector with raw bi-gram feature weights.
+    Raw,
+    /// Connector combining a matrix and raw bi-gram feature weights.
+    Dual,
+    /// Connector with a matrix of connection costs quantized into 8 bits.
+    CompressedMatrix,
+    /// Connector supplied by users.
+    Custom,
+}
+
+/// Breakdown of the heap memory used by a connector, in bytes.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct ConnectorMemoryUsage {
+    /// Type of the connector.
+    pub kind: ConnectorKind,
+    /// Connection cost matrix, including maps from connection ids to its rows/columns.
+    pub matrix: usize,
+    /// Feature ids assigned to connection ids.
+    pub feature_ids: usize,
+    /// Scorer of bi-gram feature weights.
+    pub scorer: usize,
+}
+
+impl ConnectorMemoryUsage {
+    /// Returns the total number of bytes.
+    pub const fn total(&self) -> usize {
+        self.matrix + self.feature_ids + self.scorer
+    }
+}
+
+/// Breakdown of the heap memory used by a [`Dictionary`], in bytes.
+///
+/// The values are computed from the lengths of the internal buffers
+/// and do not include unused capacity or allocator overhead.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct MemoryUsage {
+    /// Tries of the system and user lexicons.
+    pub trie: usize,
+    /// Posting lists of the system and user lexicons.
+    pub postings: usize,
+    /// Word parameters of the system and user lexicons.
+    pub params: usize,
+    /// Feature strings of the system and user lexicons.
+    pub features: usize,
+    /// Connector.
+    pub connector: ConnectorMemoryUsage,
+    /// Character property.
+    pub char_prop: usize,
+    /// Handler of unknown words.
+    pub unk_handler: usize,
+    /// Mapper of connection ids.
+    pub mapper: usize,
+}
+
+impl MemoryUsage {
+    /// Returns the total number of bytes.
+    pub const fn total(&self) -> usize {
+        self.trie
+            + self.postings
+            + self.params
+            + self.features
+            + self.connector.total()
+            + self.char_prop
+            + self.unk_handler
+            + self.mapper
+    }
 }
 
 /// Inner data of [`Dictionary`].
@@ -50,6 +136,31 @@ pub(crate) struct DictionaryInner {
     unk_handler: UnkHandler,
 }
 
+impl DictionaryInner {
+    /// Checks that the costs of all the components are stored in the same width.
+    fn verify_cost_width(&self) -> Result<()> {
+        let width = self.system_lexicon.cost_width();
+        let connector_width = match &self.connector {
+            ConnectorWrapper::Matrix(_) | ConnectorWrapper::CompressedMatrix(_) => {
+                Some(CostWidth::Bits16)
+            }
+            ConnectorWrapper::WideMatrix(_) => Some(CostWidth::Bits32),
+            _ => None,
+        };
+        let user_width = self.user_lexicon.as_ref().map(Lexicon::cost_width);
+        if connector_width.is_some_and(|w| w != width) || user_width.is_some_and(|w| w != width) {
+            return Err(VibratoError::invalid_format(
+                "rdr",
+                format!(
+                    "The dictionary records {}-bit costs, but its components disagree.",
+                    width.bits()
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
 /// Dictionary for tokenization.
 pub struct Dictionary {
     pub(crate) data: DictionaryInner,
@@ -75,7 +186,6 @@ impl Dictionary {
     }
 
     /// Gets the reference to the mapper for connection ids.
-    #[allow(dead_code)]
     #[inline(always)]
     pub(crate) const fn mapper(&self) -> Option<&ConnIdMapper> {
         self.data.mapper.as_ref()
@@ -93,26 +203,156 @@ impl Dictionary {
         &self.data.unk_handler
     }
 
-    /// Gets the word parameter.
+    /// Gets the word parameter, or `None` for [`LexType::Generated`] because such words are
+    /// not stored in the dictionary.
+    #[inline(always)]
+    pub(crate) fn word_param(&self, word_idx: WordIdx) -> Option<WordParam> {
+        match word_idx.lex_type {
+            LexType::System => Some(self.system_lexicon().word_param(word_idx)),
+            LexType::User => Some(self.user_lexicon().unwrap().word_param(word_idx)),
+            LexType::Unknown => Some(self.unk_handler().word_param(word_idx)),
+            LexType::Generated => None,
+        }
+    }
+
+    /// Gets the surface of the word, which is available only for words in the lexicons
+    /// of a dictionary built with
+    /// [`SystemDictionaryBuilder::typo_index()`](crate::SystemDictionaryBuilder::typo_index)
+    /// or [`SystemDictionaryBuilder::folding()`](crate::SystemDictionaryBuilder::folding).
     #[inline(always)]
-    pub(crate) fn word_param(&self, word_idx: WordIdx) -> WordParam {
+    pub fn word_surface(&self, word_idx: WordIdx) -> Option<&str> {
         match word_idx.lex_type {
-            LexType::System => self.system_lexicon().word_param(word_idx),
-            LexType::User => self.user_lexicon().unwrap().word_param(word_idx),
-            LexType::Unknown => self.unk_handler().word_param(word_idx),
+            LexType::System => self.system_lexicon().word_surface(word_idx),
+            LexType::User => self.user_lexicon().unwrap().word_surface(word_idx),
+            LexType::Unknown | LexType::Generated => None,
         }
     }
 
     /// Gets the reference to the feature string.
+    ///
+    /// `None` is returned for [`LexType::Generated`] because such words are not stored in
+    /// the dictionary. Use [`Tokenizer::word_feature()`](crate::Tokenizer::word_feature)
+    /// or [`Token::feature()`](crate::token::Token::feature) instead.
     #[inline(always)]
-    pub fn word_feature(&self, word_idx: WordIdx) -> &str {
+    pub fn word_feature(&self, word_idx: WordIdx) -> Option<&str> {
         match word_idx.lex_type {
-            LexType::System => self.system_lexicon().word_feature(word_idx),
-            LexType::User => self.user_lexicon().unwrap().word_feature(word_idx),
-            LexType::Unknown => self.unk_handler().word_feature(word_idx),
+            LexType::System => Some(self.system_lexicon().word_feature(word_idx)),
+            LexType::User => Some(self.user_lexicon().unwrap().word_feature(word_idx)),
+            LexType::Unknown => Some(self.unk_handler().word_feature(word_idx)),
+            LexType::Generated => None,
+        }
+    }
+
+    /// Gets the number of entries in the system lexicon.
+    #[inline(always)]
+    pub fn num_system_entries(&self) -> usize {
+        self.system_lexicon().len()
+    }
+
+    /// Gets the number of entries in the user lexicon.
+    #[inline(always)]
+    pub fn num_user_entries(&self) -> usize {
+        self.user_lexicon().map_or(0, Lexicon::len)
+    }
+
+    /// Gets the number of entries for unknown words.
+    #[inline(always)]
+    pub fn num_unk_entries(&self) -> usize {
+        self.unk_handler().len()
+    }
+
+    /// Gets the number of left connection ids.
+    #[inline(always)]
+    pub fn num_left_ids(&self) -> usize {
+        self.connector().num_left()
+    }
+
+    /// Gets the number of right connection ids.
+    #[inline(always)]
+    pub fn num_right_ids(&self) -> usize {
+        self.connector().num_right()
+    }
+
+    /// Gets the number of character categories.
+    #[inline(always)]
+    pub fn num_categories(&self) -> usize {
+        self.char_prop().num_categories()
+    }
+
+    /// Gets the maximum absolute error of connection costs introduced by
+    /// [`SystemDictionaryBuilder::compress_matrix()`](crate::SystemDictionaryBuilder::compress_matrix),
+    /// or `0` if the connector is not compressed.
+    pub fn connection_cost_error(&self) -> u16 {
+        match self.connector() {
+            ConnectorWrapper::CompressedMatrix(c) => c.max_error(),
+            _ => 0,
         }
     }
 
+    /// Gets the width of the costs chosen with
+    /// [`SystemDictionaryBuilder::cost_width()`](crate::SystemDictionaryBuilder::cost_width).
+    #[inline(always)]
+    pub fn cost_width(&self) -> CostWidth {
+        self.system_lexicon().cost_width()
+    }
+
+    /// Gets the folding of surfaces indexed for
+    /// [`Tokenizer::folded_matching()`](crate::Tokenizer::folded_matching).
+    ///
+    /// User lexicons are indexed with the same folding.
+    #[inline(always)]
+    pub const fn folding(&self) -> Folding {
+        self.system_lexicon().folding()
+    }
+
+    /// Checks if the dictionary is built with
+    /// [`SystemDictionaryBuilder::typo_index()`](crate::SystemDictionaryBuilder::typo_index).
+    #[inline(always)]
+    pub const fn has_typo_index(&self) -> bool {
+        self.system_lexicon().has_typo_map()
+    }
+
+    /// Computes the breakdown of the heap memory used by the dictionary.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use std::fs::File;
+    ///
+    /// use vibrato::SystemDictionaryBuilder;
+    ///
+    /// let dict = SystemDictionaryBuilder::from_readers(
+    ///     File::open("src/tests/resources/lex.csv")?,
+    ///     File::open("src/tests/resources/matrix.def")?,
+    ///     File::open("src/tests/resources/char.def")?,
+    ///     File::open("src/tests/resources/unk.def")?,
+    /// )?;
+    ///
+    /// let usage = dict.memory_usage();
+    /// assert!(usage.trie > 0);
+    /// assert_eq!(usage.mapper, 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage {
+            trie: 0,
+            postings: 0,
+            params: 0,
+            features: 0,
+            connector: self.connector().memory_usage(),
+            char_prop: self.char_prop().memory_usage(),
+            unk_handler: self.unk_handler().memory_usage(),
+            mapper: self.mapper().map_or(0, ConnIdMapper::memory_usage),
+        };
+        self.system_lexicon().add_memory_usage(&mut usage);
+        if let Some(user_lexicon) = self.user_lexicon() {
+            user_lexicon.add_memory_usage(&mut usage);
+        }
+        usage
+    }
+
     /// Exports the dictionary data.
     ///
     /// # Examples
@@ -139,10 +379,17 @@ impl Dictionary {
     /// # Errors
     ///
     /// When bincode generates an error, it will be returned as is.
+    /// A dictionary with a [`CustomConnector`] cannot be exported.
     pub fn write<W>(&self, mut wtr: W) -> Result<usize>
     where
         W: Write,
     {
+        if matches!(self.connector(), ConnectorWrapper::Custom(_)) {
+            return Err(bincode::error::EncodeError::Other(
+                "custom connectors cannot be serialized",
+            )
+            .into());
+        }
         wtr.write_all(MODEL_MAGIC)?;
         let config = common::bincode_config();
         let num_bytes = bincode::encode_into_std_write(&self.data, &mut wtr, config)?;
@@ -192,12 +439,23 @@ impl Dictionary {
             ));
         }
         let config = common::bincode_config();
-        let data = bincode::decode_from_std_read(&mut rdr, config)?;
+        let data: DictionaryInner = bincode::decode_from_std_read(&mut rdr, config)?;
+        data.verify_cost_width()?;
+        if !data.char_prop.verify() {
+            return Err(VibratoError::invalid_format(
+                "rdr",
+                "The character property of the dictionary is broken.",
+            ));
+        }
         Ok(data)
     }
 
     /// Resets the user dictionary from a reader.
     ///
+    /// The feature columns selected with
+    /// [`SystemDictionaryBuilder::feature_columns()`](crate::SystemDictionaryBuilder::feature_columns)
+    /// are also applied to the user entries.
+    ///
     /// # Arguments
     ///
     ///  - `user_lexicon_rdr`: A reader of a lexicon file `*.csv` in the MeCab format.
@@ -211,7 +469,16 @@ impl Dictionary {
         R: Read,
     {
         if let Some(user_lexicon_rdr) = user_lexicon_rdr {
-            let mut user_lexicon = Lexicon::from_reader(user_lexicon_rdr, LexType::User)?;
+            let mut user_lexicon = Lexicon::from_reader(
+                user_lexicon_rdr,
+                LexType::User,
+                self.folding(),
+                self.has_typo_index(),
+                self.cost_width(),
+            )?;
+            if let Some(columns) = self.data.unk_handler.feature_columns() {
+                user_lexicon.select_feature_columns(columns);
+            }
             if let Some(mapper) = self.data.mapper.as_ref() {
                 user_lexicon.map_connection_ids(mapper);
             }
@@ -228,12 +495,180 @@ impl Dictionary {
         Ok(self)
     }
 
+    /// Replaces the connector with a custom one.
+    ///
+    /// The connector is given connection ids as in the sources of the dictionary;
+    /// if the ids have been edited with [`Self::map_connection_ids_from_iter()`],
+    /// [`CustomConnector::map_connection_ids()`] is called with the same mappings.
+    /// See [`CustomConnector`] for the invariants.
+    /// Note that the resulting dictionary cannot be exported with [`Self::write()`].
+    ///
+    /// # Arguments
+    ///
+    ///  - `connector`: A model of connection costs.
+    ///
+    /// # Errors
+    ///
+    /// [`VibratoError`] is returned when the connector does not cover the connection ids
+    /// of the lexicons or the unknown-word entries.
+    pub fn reset_connector<C>(mut self, connector: C) -> Result<Self>
+    where
+        C: CustomConnector + 'static,
+    {
+        let mut connector = ConnectorWrapper::Custom(BoxedConnector::new(connector));
+        let mappable = self.mapper().is_none_or(|mapper| {
+            connector.num_left() == mapper.num_left() && connector.num_right() == mapper.num_right()
+        });
+        if !mappable
+            || !self.system_lexicon().verify(&connector)
+            || !self.unk_handler().verify(&connector)
+            || self
+                .user_lexicon()
+                .is_some_and(|user_lexicon| !user_lexicon.verify(&connector))
+        {
+            return Err(VibratoError::invalid_argument(
+                "connector",
+                "The numbers of connection ids are inconsistent with the dictionary.",
+            ));
+        }
+        if let Some(mapper) = self.data.mapper.as_ref() {
+            connector.map_connection_ids(mapper);
+        }
+        self.data.connector = connector;
+        Ok(self)
+    }
+
+    /// Resets the character property from a reader of `char.def`.
+    ///
+    /// This allows you to try a different setup of character categories, e.g., adding
+    /// characters to a category, without recompiling the dictionary.
+    /// The input can define only categories in the current dictionary because the handler of
+    /// unknown words is built for them. Categories can be omitted unless unknown words or
+    /// `SPACE` are defined for them.
+    ///
+    /// # Arguments
+    ///
+    ///  - `char_prop_rdr`: A reader of a character definition file `char.def`.
+    ///
+    /// # Errors
+    ///
+    /// [`VibratoError`] is returned when an input format is invalid,
+    /// the input defines a category not in the current dictionary,
+    /// or the input omits a category required by the current dictionary.
+    pub fn reset_char_prop_from_reader<R>(mut self, char_prop_rdr: R) -> Result<Self>
+    where
+        R: Read,
+    {
+        let char_prop = &self.data.char_prop;
+        let space_cate_id = char_prop.cate_id("SPACE");
+        let required: Vec<_> = (0..u32::try_from(char_prop.num_categories()).unwrap())
+            .filter(|&cate_id| {
+                self.data.unk_handler.has_entries(cate_id) || Some(cate_id) == space_cate_id
+            })
+            .collect();
+        self.data.char_prop = CharProperty::from_reader_with_categories(
+            char_prop_rdr,
+            char_prop.categories(),
+            &required,
+        )?;
+        Ok(self)
+    }
+
+    /// Patches the character property with a reader of `char.def`.
+    ///
+    /// Unlike [`Self::reset_char_prop_from_reader()`], the input can consist of only
+    /// the lines to be changed, e.g., a character range assigned to other categories.
+    /// Characters not in the input are mapped as before.
+    /// A category redefined in the input applies to all the characters whose first category
+    /// it is, and the input can define only categories in the current dictionary.
+    ///
+    /// # Arguments
+    ///
+    ///  - `char_prop_rdr`: A reader of a character definition file `char.def`.
+    ///
+    /// # Errors
+    ///
+    /// [`VibratoError`] is returned when an input format is invalid or
+    /// the input defines a category not in the current dictionary.
+    pub fn patch_char_prop_from_reader<R>(mut self, char_prop_rdr: R) -> Result<Self>
+    where
+        R: Read,
+    {
+        self.data.char_prop = self.data.char_prop.patch_from_reader(char_prop_rdr)?;
+        Ok(self)
+    }
+
+    /// Resets the patterns of unknown words from a reader.
+    ///
+    /// Each line of the definition file consists of a regular expression followed by
+    /// left/right ids, a word cost, and a feature, in the same CSV format as `unk.def`.
+    /// A regular expression containing commas must be enclosed in double quotes.
+    /// For example, the following line recognizes numbers with thousands separators:
+    ///
+    /// ```text
+    /// "[0-9]{1,3}(,[0-9]{3})+(\.[0-9]+)?",8,8,-1000,名詞,数詞,*,*,*,*,*
+    /// ```
+    ///
+    /// Every pattern is matched against the prefix of the text at each position,
+    /// and each pattern matching a non-empty prefix gives a candidate of an unknown word,
+    /// so patterns earlier in the file do not shadow later ones.
+    /// Within a pattern, the match follows the leftmost-first semantics of the `regex` crate,
+    /// i.e., the first alternative that matches is taken even if a later one matches longer,
+    /// e.g., `[0-9]+|[0-9]+\.[0-9]+` matches only `1` in `1.5`. A matched pattern suppresses the generation of unknown words
+    /// for categories whose `INVOKE` is `0`, in the same manner as a lexicon entry.
+    /// Only the first 256 characters at each position are matched.
+    ///
+    /// The features are selected with the columns given to
+    /// [`SystemDictionaryBuilder::feature_columns()`](crate::SystemDictionaryBuilder::feature_columns).
+    ///
+    /// # Arguments
+    ///
+    ///  - `pattern_rdr`: A reader of the definition file of patterns.
+    ///    If `None`, clear the current patterns.
+    ///
+    /// # Errors
+    ///
+    /// [`VibratoError`] is returned when an input format is invalid.
+    pub fn reset_unk_patterns_from_reader<R>(mut self, pattern_rdr: Option<R>) -> Result<Self>
+    where
+        R: Read,
+    {
+        if let Some(pattern_rdr) = pattern_rdr {
+            self.data.unk_handler.reset_patterns_from_reader(
+                pattern_rdr,
+                self.data.mapper.as_ref(),
+                self.cost_width(),
+            )?;
+            if !self.data.unk_handler.verify(&self.data.connector) {
+                return Err(VibratoError::invalid_argument(
+                    "pattern_rdr",
+                    "includes invalid connection ids.",
+                ));
+            }
+        } else {
+            self.data.unk_handler.clear_patterns();
+        }
+        Ok(self)
+    }
+
+    /// Gets the number of patterns of unknown words.
+    #[inline(always)]
+    pub fn num_unk_patterns(&self) -> usize {
+        self.unk_handler().num_patterns()
+    }
+
     /// Edits connection ids with the given mappings.
     ///
+    /// The mappings are applied to the current ids, so if the dictionary has already been
+    /// edited, e.g., by a previous call or [`Self::reorder_connection_ids_by_corpus()`],
+    /// they compose with the previous ones. The composed mapping from the original ids
+    /// in the lexicon files is kept, so that user lexicons and unknown-word patterns
+    /// set afterward are still written with the original ids.
+    ///
     /// # Arguments
     ///
     ///  - `lmap/rmap`: An iterator of mappings of left/right ids, where
-    ///    the `i`-th item (1-origin) indicates a new id mapped from id `i`.
+    ///    the `i`-th item (1-origin) indicates a new id mapped from the current id `i`.
     ///
     /// # Errors
     ///
@@ -254,7 +689,87 @@ impl Dictionary {
         }
         self.data.connector.map_connection_ids(&mapper);
         self.data.unk_handler.map_connection_ids(&mapper);
+        // Keeps the mapping from the original ids, which are used in user lexicons.
+        let mapper = match self.data.mapper.as_ref() {
+            Some(prev) => prev.compose(&mapper),
+            None => mapper,
+        };
         self.data.mapper = Some(mapper);
         Ok(self)
     }
+
+    /// Reorders connection ids so that ids frequently looked up in tokenizing
+    /// the given sentences are packed, improving the locality of reference.
+    ///
+    /// This counts the connection ids in the lattices of the sentences, sorts the ids
+    /// in descending order of the frequencies, and edits the dictionary in the same manner as
+    /// [`Self::map_connection_ids_from_iter()`]. Empty sentences are skipped.
+    ///
+    /// # Arguments
+    ///
+    ///  - `sentences`: Sentences of a corpus, such as training data.
+    ///
+    /// # Errors
+    ///
+    /// [`VibratoError`] is returned when no connection is counted in the sentences.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use std::fs::File;
+    ///
+    /// use vibrato::SystemDictionaryBuilder;
+    ///
+    /// let dict = SystemDictionaryBuilder::from_readers(
+    ///     File::open("src/tests/resources/lex.csv")?,
+    ///     File::open("src/tests/resources/matrix.def")?,
+    ///     File::open("src/tests/resources/char.def")?,
+    ///     File::open("src/tests/resources/unk.def")?,
+    /// )?;
+    /// let (dict, report) = dict.reorder_connection_ids_by_corpus(["東京都に行く", "京都"])?;
+    /// assert_eq!(report.num_sentences, 2);
+    /// assert!(report.hot_cells_after() <= report.hot_cells_before());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn reorder_connection_ids_by_corpus<I, S>(
+        self,
+        sentences: I,
+    ) -> Result<(Self, ReorderReport)>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let tokenizer = Tokenizer::new(self);
+        let mut worker = tokenizer.new_worker();
+        worker.init_connid_counter();
+        let mut num_sentences = 0;
+        for sentence in sentences {
+            let sentence = sentence.as_ref();
+            if sentence.is_empty() {
+                continue;
+            }
+            worker.reset_sentence(sentence);
+            worker.tokenize();
+            worker.update_connid_counts();
+            num_sentences += 1;
+        }
+        let (lid_probs, rid_probs) = worker.compute_connid_probs();
+        drop(worker);
+        let dict = tokenizer.into_dictionary();
+
+        if num_sentences == 0 {
+            return Err(VibratoError::invalid_argument(
+                "sentences",
+                "No connection is counted in the sentences.",
+            ));
+        }
+        let report = ReorderReport::new(num_sentences, &lid_probs, &rid_probs);
+        let dict = dict.map_connection_ids_from_iter(
+            lid_probs.iter().map(|&(id, _)| u16::try_from(id).unwrap()),
+            rid_probs.iter().map(|&(id, _)| u16::try_from(id).unwrap()),
+        )?;
+        Ok((dict, report))
+    }
 }
@@ -1,25 +1,226 @@
 //! Builders for [`Dictionary`].
 use std::io::Read;
+use std::sync::Arc;
 
-use crate::dictionary::connector::{DualConnector, MatrixConnector, RawConnector};
+use crate::dictionary::connector::{
+    BoxedConnector, CompressedMatrixConnector, CustomConnector, DualConnector, MatrixConnector,
+    RawConnector, TemplateRemoval,
+};
 use crate::dictionary::{
-    CharProperty, ConnectorWrapper, Dictionary, DictionaryInner, LexType, Lexicon, UnkHandler,
+    CharProperty, ConnectorWrapper, CostWidth, Dictionary, DictionaryInner, Folding, LexType,
+    Lexicon, SourceEncoding, UnkHandler,
 };
 use crate::errors::{Result, VibratoError};
+use crate::utils;
 
 use super::lexicon::RawWordEntry;
 
 /// Builder for [`Dictionary`] from system lexicon entries.
-pub struct SystemDictionaryBuilder {}
+///
+/// The associated functions [`from_readers()`](Self::from_readers) and
+/// [`from_readers_with_bigram_info()`](Self::from_readers_with_bigram_info)
+/// build a dictionary with the default settings.
+/// To change the settings, create a builder with [`new()`](Self::new),
+/// specify options, and call [`build_from_readers()`](Self::build_from_readers) or
+/// [`build_from_readers_with_bigram_info()`](Self::build_from_readers_with_bigram_info).
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use std::fs::File;
+///
+/// use vibrato::{SystemDictionaryBuilder, Tokenizer};
+///
+/// // Keeps only the reading (7th) and the POS (1st) columns in this order.
+/// let dict = SystemDictionaryBuilder::new()
+///     .feature_columns(Some(vec![7, 1]))
+///     .build_from_readers(
+///         File::open("src/tests/resources/lex.csv")?,
+///         File::open("src/tests/resources/matrix.def")?,
+///         File::open("src/tests/resources/char.def")?,
+///         File::open("src/tests/resources/unk.def")?,
+///     )?;
+///
+/// let tokenizer = Tokenizer::new(dict);
+/// let mut worker = tokenizer.new_worker();
+/// worker.reset_sentence("京都");
+/// worker.tokenize();
+/// assert_eq!(worker.token(0).feature(), "キョウト,名詞");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default, Clone, Debug)]
+pub struct SystemDictionaryBuilder {
+    feature_columns: Option<Vec<usize>>,
+    encoding: SourceEncoding,
+    folding: Folding,
+    typo_index: bool,
+    cost_width: CostWidth,
+    compressed_matrix_error: Option<u16>,
+    dual_max_matrix_size: Option<usize>,
+    dual_progress: Option<ProgressCallback>,
+}
+
+/// Callback receiving the progress of building a dual connector.
+#[derive(Clone)]
+struct ProgressCallback(Arc<dyn Fn(&TemplateRemoval) + Send + Sync>);
+
+impl std::fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
 
 impl SystemDictionaryBuilder {
-    pub(crate) fn build(
+    /// Creates a new builder with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Specifies feature columns to be stored in the dictionary.
+    ///
+    /// Features of the system lexicon and unknown words are rebuilt from the given columns,
+    /// in the given order. Column `0` indicates the first feature column,
+    /// i.e., the fifth column of a row in `lex.csv` or `unk.def`.
+    /// A column missing in a row is filled with `*`.
+    /// An empty list drops all features, which is useful for segmentation-only dictionaries.
+    ///
+    /// If `None`, the full feature strings are stored (default).
+    ///
+    /// The columns are recorded in the dictionary and also applied to user lexicons given to
+    /// [`Dictionary::reset_user_lexicon_from_reader()`] and unknown-word patterns given to
+    /// [`Dictionary::reset_unk_patterns_from_reader()`].
+    ///
+    /// # Arguments
+    ///
+    ///  - `columns`: Indices of the feature columns to be kept.
+    pub fn feature_columns(mut self, columns: Option<Vec<usize>>) -> Self {
+        self.feature_columns = columns;
+        self
+    }
+
+    /// Specifies the character encoding of the input sources.
+    ///
+    /// All the sources are transcoded into UTF-8 on read.
+    /// The default is [`SourceEncoding::Utf8`].
+    ///
+    /// # Arguments
+    ///
+    ///  - `encoding`: Character encoding of the sources.
+    pub const fn encoding(mut self, encoding: SourceEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Specifies the folding of surfaces indexed for
+    /// [`Tokenizer::folded_matching()`](crate::Tokenizer::folded_matching).
+    ///
+    /// The lexicons are additionally indexed with the folded surfaces, and the surfaces are
+    /// stored to verify matches, which enlarges the dictionary.
+    /// The default is [`Folding::new()`], which folds nothing.
+    ///
+    /// # Arguments
+    ///
+    ///  - `folding`: Folding of surfaces.
+    pub const fn folding(mut self, folding: Folding) -> Self {
+        self.folding = folding;
+        self
+    }
+
+    /// Builds an index of surfaces for
+    /// [`Tokenizer::typo_tolerance()`](crate::Tokenizer::typo_tolerance).
+    ///
+    /// The index stores the surfaces and their variants deleting one character,
+    /// which enlarges the dictionary several times as much as the trie.
+    /// User lexicons are indexed in the same manner.
+    /// By default, this option is disabled.
+    pub const fn typo_index(mut self, yes: bool) -> Self {
+        self.typo_index = yes;
+        self
+    }
+
+    /// Specifies the width of the connection costs in `matrix.def` and the word costs
+    /// stored in the dictionary.
+    ///
+    /// [`CostWidth::Bits32`] accepts costs out of the 16-bit range, as produced by some
+    /// trained models, at the cost of doubling the memory of the costs.
+    /// A source with a cost overflowing the width is rejected with its location.
+    /// The width is recorded in the dictionary and also applied to user lexicons and
+    /// unknown-word patterns given later.
+    /// The default is [`CostWidth::Bits16`].
+    ///
+    /// # Arguments
+    ///
+    ///  - `width`: Width of the costs.
+    pub const fn cost_width(mut self, width: CostWidth) -> Self {
+        self.cost_width = width;
+        self
+    }
+
+    /// Compresses the connection matrix given to [`Self::build_from_readers()`].
+    ///
+    /// Each row of the matrix is quantized into 8 bits with its own scale,
+    /// which reduces the memory to about half, unless the error of a cost exceeds
+    /// `max_error`; such rows are stored as is.
+    /// The actual error is available via [`Dictionary::connection_cost_error()`].
+    /// If `None`, the matrix is stored as is (default).
+    ///
+    /// # Arguments
+    ///
+    ///  - `max_error`: Maximum absolute error allowed for each connection cost.
+    pub const fn compress_matrix(mut self, max_error: Option<u16>) -> Self {
+        self.compressed_matrix_error = max_error;
+        self
+    }
+
+    /// Specifies the maximum number of cells of the matrix of a dual connector built with
+    /// [`Self::build_from_readers_with_bigram_info()`].
+    ///
+    /// Feature templates are greedily moved from the matrix to the raw scorer,
+    /// which can hold up to eight templates, until the matrix has no more cells than this size.
+    /// A smaller size saves memory at the cost of speed, although the size can be
+    /// unreachable even after moving eight templates.
+    /// If `None`, eight templates are moved to minimize the matrix (default).
+    ///
+    /// # Arguments
+    ///
+    ///  - `size`: Maximum number of cells of the matrix.
+    pub const fn dual_connector_max_matrix_size(mut self, size: Option<usize>) -> Self {
+        self.dual_max_matrix_size = size;
+        self
+    }
+
+    /// Specifies a callback called whenever a feature template is moved from the matrix
+    /// while building a dual connector.
+    ///
+    /// # Arguments
+    ///
+    ///  - `callback`: Function receiving the removed template and the resulting matrix size.
+    pub fn dual_connector_progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&TemplateRemoval) + Send + Sync + 'static,
+    {
+        self.dual_progress = Some(ProgressCallback(Arc::new(callback)));
+        self
+    }
+
+    pub(crate) fn build_from_entries(
         system_word_entries: &[RawWordEntry],
         connector: ConnectorWrapper,
         char_prop: CharProperty,
         unk_handler: UnkHandler,
+        folding: Folding,
+        typo_index: bool,
+        cost_width: CostWidth,
     ) -> Result<Dictionary> {
-        let system_lexicon = Lexicon::from_entries(system_word_entries, LexType::System)?;
+        let system_lexicon = Lexicon::from_entries(
+            system_word_entries,
+            LexType::System,
+            folding,
+            typo_index,
+            cost_width,
+        )?;
 
         if !system_lexicon.verify(&connector) {
             return Err(VibratoError::invalid_argument(
@@ -46,10 +247,66 @@ impl SystemDictionaryBuilder {
         })
     }
 
+    fn read_char_prop<P>(&self, char_prop_rdr: P) -> Result<CharProperty>
+    where
+        P: Read,
+    {
+        let buf = self.encoding.read_to_utf8(char_prop_rdr, "char.def")?;
+        CharProperty::from_reader(buf.as_slice())
+    }
+
+    fn read_unk_handler<U>(
+        &self,
+        unk_handler_rdr: U,
+        char_prop: &CharProperty,
+    ) -> Result<UnkHandler>
+    where
+        U: Read,
+    {
+        let buf = self.encoding.read_to_utf8(unk_handler_rdr, "unk.def")?;
+        UnkHandler::from_reader(buf.as_slice(), char_prop, self.cost_width)
+    }
+
+    /// Builds a dictionary from raw components, applying the settings of this builder.
+    fn build(
+        &self,
+        system_lexicon_buf: &[u8],
+        connector: ConnectorWrapper,
+        char_prop: CharProperty,
+        mut unk_handler: UnkHandler,
+    ) -> Result<Dictionary> {
+        let (mut system_word_entries, _) =
+            Lexicon::parse_csv(system_lexicon_buf, "lex.csv", self.cost_width)?;
+
+        let selected_features: Vec<_>;
+        if let Some(columns) = self.feature_columns.as_ref() {
+            selected_features = system_word_entries
+                .iter()
+                .map(|e| utils::select_csv_cells(e.feature, columns))
+                .collect();
+            for (e, feature) in system_word_entries.iter_mut().zip(&selected_features) {
+                e.feature = feature;
+            }
+            unk_handler.select_feature_columns(columns);
+        }
+
+        Self::build_from_entries(
+            &system_word_entries,
+            connector,
+            char_prop,
+            unk_handler,
+            self.folding,
+            self.typo_index,
+            self.cost_width,
+        )
+    }
+
     /// Creates a new [`Dictionary`] from readers of system entries in the MeCab format.
     ///
+    /// This function uses the default settings.
     /// Consider to use [`from_readers_with_bigram_info()`](Self::from_readers_with_bigram_info)
     /// if you want to store a dictionary with smaller memory.
+    /// Entries with empty surfaces are skipped; [`Linter`](crate::lint::Linter) reports them.
     ///
     /// # Arguments
     ///
@@ -62,7 +319,7 @@ impl SystemDictionaryBuilder {
     ///
     /// [`VibratoError`] is returned when an input format is invalid.
     pub fn from_readers<S, C, P, U>(
-        mut system_lexicon_rdr: S,
+        system_lexicon_rdr: S,
         connector_rdr: C,
         char_prop_rdr: P,
         unk_handler_rdr: U,
@@ -73,27 +330,70 @@ impl SystemDictionaryBuilder {
         P: Read,
         U: Read,
     {
-        let mut system_lexicon_buf = vec![];
-        system_lexicon_rdr.read_to_end(&mut system_lexicon_buf)?;
-        let system_word_entries = Lexicon::parse_csv(&system_lexicon_buf, "lex.csv")?;
-        let connector = MatrixConnector::from_reader(connector_rdr)?;
-        let char_prop = CharProperty::from_reader(char_prop_rdr)?;
-        let unk_handler = UnkHandler::from_reader(unk_handler_rdr, &char_prop)?;
-
-        Self::build(
-            &system_word_entries,
-            ConnectorWrapper::Matrix(connector),
-            char_prop,
-            unk_handler,
+        Self::new().build_from_readers(
+            system_lexicon_rdr,
+            connector_rdr,
+            char_prop_rdr,
+            unk_handler_rdr,
         )
     }
 
+    /// Creates a new [`Dictionary`] from readers of system entries in the MeCab format,
+    /// using the settings of this builder.
+    ///
+    /// See [`from_readers()`](Self::from_readers) for the arguments.
+    ///
+    /// # Errors
+    ///
+    /// [`VibratoError`] is returned when an input format is invalid.
+    pub fn build_from_readers<S, C, P, U>(
+        &self,
+        system_lexicon_rdr: S,
+        connector_rdr: C,
+        char_prop_rdr: P,
+        unk_handler_rdr: U,
+    ) -> Result<Dictionary>
+    where
+        S: Read,
+        C: Read,
+        P: Read,
+        U: Read,
+    {
+        let system_lexicon_buf = self.encoding.read_to_utf8(system_lexicon_rdr, "lex.csv")?;
+        let connector_buf = self.encoding.read_to_utf8(connector_rdr, "matrix.def")?;
+        let connector = match (self.cost_width, self.compressed_matrix_error) {
+            (CostWidth::Bits16, max_error) => {
+                let connector = MatrixConnector::from_reader(connector_buf.as_slice())?;
+                match max_error {
+                    Some(max_error) => ConnectorWrapper::CompressedMatrix(
+                        CompressedMatrixConnector::from_matrix(&connector, max_error),
+                    ),
+                    None => ConnectorWrapper::Matrix(connector),
+                }
+            }
+            (CostWidth::Bits32, None) => ConnectorWrapper::WideMatrix(
+                MatrixConnector::from_reader(connector_buf.as_slice())?,
+            ),
+            (CostWidth::Bits32, Some(_)) => {
+                return Err(VibratoError::invalid_argument(
+                    "compress_matrix",
+                    "A matrix with 32-bit costs cannot be compressed.",
+                ))
+            }
+        };
+        let char_prop = self.read_char_prop(char_prop_rdr)?;
+        let unk_handler = self.read_unk_handler(unk_handler_rdr, &char_prop)?;
+
+        self.build(&system_lexicon_buf, connector, char_prop, unk_handler)
+    }
+
     /// Creates a new memory-efficient [`Dictionary`] from readers of system entries.
     ///
     /// This function implements a matrix of connection costs in a compact format.
     /// Compared to a dictionary generated by [`from_readers()`](Self::from_readers),
     /// one generated by this function can save memory consumption,
     /// although analysis speed can be slower.
+    /// This function uses the default settings.
     ///
     /// # Arguments
     ///
@@ -109,7 +409,7 @@ impl SystemDictionaryBuilder {
     ///
     /// [`VibratoError`] is returned when an input format is invalid.
     pub fn from_readers_with_bigram_info<S, R, L, C, P, U>(
-        mut system_lexicon_rdr: S,
+        system_lexicon_rdr: S,
         bigram_right_rdr: R,
         bigram_left_rdr: L,
         bigram_cost_rdr: C,
@@ -125,26 +425,116 @@ impl SystemDictionaryBuilder {
         P: Read,
         U: Read,
     {
-        let mut system_lexicon_buf = vec![];
-        system_lexicon_rdr.read_to_end(&mut system_lexicon_buf)?;
-        let system_word_entries = Lexicon::parse_csv(&system_lexicon_buf, "lex.csv")?;
+        Self::new().build_from_readers_with_bigram_info(
+            system_lexicon_rdr,
+            bigram_right_rdr,
+            bigram_left_rdr,
+            bigram_cost_rdr,
+            char_prop_rdr,
+            unk_handler_rdr,
+            dual_connector,
+        )
+    }
+
+    /// Creates a new memory-efficient [`Dictionary`] from readers of system entries,
+    /// using the settings of this builder.
+    ///
+    /// See [`from_readers_with_bigram_info()`](Self::from_readers_with_bigram_info)
+    /// for the arguments.
+    ///
+    /// # Errors
+    ///
+    /// [`VibratoError`] is returned when an input format is invalid.
+    #[allow(clippy::too_many_arguments)]
+    pub fn build_from_readers_with_bigram_info<S, R, L, C, P, U>(
+        &self,
+        system_lexicon_rdr: S,
+        bigram_right_rdr: R,
+        bigram_left_rdr: L,
+        bigram_cost_rdr: C,
+        char_prop_rdr: P,
+        unk_handler_rdr: U,
+        dual_connector: bool,
+    ) -> Result<Dictionary>
+    where
+        S: Read,
+        R: Read,
+        L: Read,
+        C: Read,
+        P: Read,
+        U: Read,
+    {
+        let system_lexicon_buf = self.encoding.read_to_utf8(system_lexicon_rdr, "lex.csv")?;
+        let bigram_right_buf = self
+            .encoding
+            .read_to_utf8(bigram_right_rdr, "bigram.right")?;
+        let bigram_left_buf = self.encoding.read_to_utf8(bigram_left_rdr, "bigram.left")?;
+        let bigram_cost_buf = self.encoding.read_to_utf8(bigram_cost_rdr, "bigram.cost")?;
         let connector = if dual_connector {
             ConnectorWrapper::Dual(DualConnector::from_readers(
-                bigram_right_rdr,
-                bigram_left_rdr,
-                bigram_cost_rdr,
+                bigram_right_buf.as_slice(),
+                bigram_left_buf.as_slice(),
+                bigram_cost_buf.as_slice(),
+                self.dual_max_matrix_size,
+                |removal| {
+                    if let Some(ProgressCallback(callback)) = self.dual_progress.as_ref() {
+                        callback(removal);
+                    }
+                },
             )?)
         } else {
             ConnectorWrapper::Raw(RawConnector::from_readers(
-                bigram_right_rdr,
-                bigram_left_rdr,
-                bigram_cost_rdr,
+                bigram_right_buf.as_slice(),
+                bigram_left_buf.as_slice(),
+                bigram_cost_buf.as_slice(),
             )?)
         };
-        let char_prop = CharProperty::from_reader(char_prop_rdr)?;
-        let unk_handler = UnkHandler::from_reader(unk_handler_rdr, &char_prop)?;
+        let char_prop = self.read_char_prop(char_prop_rdr)?;
+        let unk_handler = self.read_unk_handler(unk_handler_rdr, &char_prop)?;
 
-        Self::build(&system_word_entries, connector, char_prop, unk_handler)
+        self.build(&system_lexicon_buf, connector, char_prop, unk_handler)
+    }
+
+    /// Creates a new [`Dictionary`] from readers of system entries and a custom connector,
+    /// using the settings of this builder.
+    ///
+    /// The connection ids of the lexicon and the unknown-word entries are given to
+    /// the connector as they are. See [`CustomConnector`] for the invariants.
+    ///
+    /// # Arguments
+    ///
+    ///  - `system_lexicon_rdr`: A reader of a lexicon file `*.csv`.
+    ///  - `connector`: A model of connection costs.
+    ///  - `char_prop_rdr`: A reader of character definition file `char.def`.
+    ///  - `unk_handler`: A reader of unknown definition file `unk.def`.
+    ///
+    /// # Errors
+    ///
+    /// [`VibratoError`] is returned when an input format is invalid or
+    /// the connector does not cover the connection ids.
+    pub fn build_from_readers_with_connector<S, C, P, U>(
+        &self,
+        system_lexicon_rdr: S,
+        connector: C,
+        char_prop_rdr: P,
+        unk_handler_rdr: U,
+    ) -> Result<Dictionary>
+    where
+        S: Read,
+        C: CustomConnector + 'static,
+        P: Read,
+        U: Read,
+    {
+        let system_lexicon_buf = self.encoding.read_to_utf8(system_lexicon_rdr, "lex.csv")?;
+        let char_prop = self.read_char_prop(char_prop_rdr)?;
+        let unk_handler = self.read_unk_handler(unk_handler_rdr, &char_prop)?;
+
+        self.build(
+            &system_lexicon_buf,
+            ConnectorWrapper::Custom(BoxedConnector::new(connector)),
+            char_prop,
+            unk_handler,
+        )
     }
 }
 
@@ -152,6 +542,8 @@ impl SystemDictionaryBuilder {
 mod tests {
     use super::*;
 
+    use crate::dictionary::{ConnectorKind, WordIdx};
+
     #[test]
     fn test_oor_lex() {
         let lexicon_csv = "自然,1,1,0";
@@ -185,4 +577,141 @@ mod tests {
 
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_feature_columns() {
+        let lexicon_csv = "自然,0,0,1,名詞,\"自然,天然\",シゼン";
+        let matrix_def = "1 1\n0 0 0";
+        let char_def = "DEFAULT 0 1 0";
+        let unk_def = "DEFAULT,0,0,100,補助記号,*";
+
+        let dict = SystemDictionaryBuilder::new()
+            .feature_columns(Some(vec![2, 1, 3]))
+            .build_from_readers(
+                lexicon_csv.as_bytes(),
+                matrix_def.as_bytes(),
+                char_def.as_bytes(),
+                unk_def.as_bytes(),
+            )
+            .unwrap()
+            .reset_unk_patterns_from_reader(Some("[0-9]+,0,0,0,名詞,数詞,スウシ".as_bytes()))
+            .unwrap()
+            .reset_user_lexicon_from_reader(Some("言語,0,0,1,名詞,言語,ゲンゴ".as_bytes()))
+            .unwrap();
+
+        assert_eq!(
+            dict.system_lexicon()
+                .word_feature(WordIdx::new(LexType::System, 0)),
+            "シゼン,\"自然,天然\",*"
+        );
+        assert_eq!(
+            dict.unk_handler()
+                .word_feature(WordIdx::new(LexType::Unknown, 0)),
+            "*,*,*"
+        );
+        assert_eq!(
+            dict.unk_handler()
+                .word_feature(WordIdx::new(LexType::Unknown, 1)),
+            "スウシ,数詞,*"
+        );
+        assert_eq!(
+            dict.word_feature(WordIdx::new(LexType::User, 0)),
+            Some("ゲンゴ,言語,*")
+        );
+    }
+
+    #[test]
+    fn test_feature_columns_empty() {
+        let lexicon_csv = "自然,0,0,1,名詞,シゼン";
+        let matrix_def = "1 1\n0 0 0";
+        let char_def = "DEFAULT 0 1 0";
+        let unk_def = "DEFAULT,0,0,100,補助記号";
+
+        let dict = SystemDictionaryBuilder::new()
+            .feature_columns(Some(vec![]))
+            .build_from_readers(
+                lexicon_csv.as_bytes(),
+                matrix_def.as_bytes(),
+                char_def.as_bytes(),
+                unk_def.as_bytes(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            dict.system_lexicon()
+                .word_feature(WordIdx::new(LexType::System, 0)),
+            ""
+        );
+        assert_eq!(
+            dict.unk_handler()
+                .word_feature(WordIdx::new(LexType::Unknown, 0)),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        let lexicon_csv = "自然,1,2,1,名詞\n言語,2,1,2,名詞";
+        let matrix_def = "3 3\n0 0 0\n0 1 0\n0 2 0";
+        let char_def = "DEFAULT 0 1 0\nKANJI 0 0 2\n0x4E00..0x9FFF KANJI";
+        let unk_def = "DEFAULT,0,0,100,*\nKANJI,0,0,100,*";
+
+        let dict = SystemDictionaryBuilder::from_readers(
+            lexicon_csv.as_bytes(),
+            matrix_def.as_bytes(),
+            char_def.as_bytes(),
+            unk_def.as_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(dict.num_system_entries(), 2);
+        assert_eq!(dict.num_user_entries(), 0);
+        assert_eq!(dict.num_unk_entries(), 2);
+        assert_eq!(dict.num_left_ids(), 3);
+        assert_eq!(dict.num_right_ids(), 3);
+        assert_eq!(dict.num_categories(), 2);
+
+        let usage = dict.memory_usage();
+        assert_eq!(usage.connector.kind, ConnectorKind::Matrix);
+        assert_eq!(usage.connector.matrix, 9 * std::mem::size_of::<i16>());
+        assert_eq!(usage.connector.feature_ids, 0);
+        assert_eq!(usage.connector.scorer, 0);
+        // Left/right ids and a 16-bit cost for each entry.
+        assert_eq!(usage.params, 2 * 3 * std::mem::size_of::<u16>());
+        assert_eq!(usage.mapper, 0);
+        assert!(usage.trie > 0);
+        assert!(usage.total() > usage.connector.total());
+    }
+
+    #[test]
+    fn test_encoding() {
+        // "自然,0,0,1,名詞" in EUC-JP
+        let lexicon_csv = b"\xbc\xab\xc1\xb3,0,0,1,\xcc\xbe\xbb\xec";
+        let matrix_def = "1 1\n0 0 0";
+        let char_def = "DEFAULT 0 1 0";
+        let unk_def = "DEFAULT,0,0,100,*";
+
+        let dict = SystemDictionaryBuilder::new()
+            .encoding(SourceEncoding::EucJp)
+            .build_from_readers(
+                &lexicon_csv[..],
+                matrix_def.as_bytes(),
+                char_def.as_bytes(),
+                unk_def.as_bytes(),
+            )
+            .unwrap();
+        assert_eq!(
+            dict.system_lexicon()
+                .word_feature(WordIdx::new(LexType::System, 0)),
+            "名詞"
+        );
+
+        let result = SystemDictionaryBuilder::new().build_from_readers(
+            &lexicon_csv[..],
+            matrix_def.as_bytes(),
+            char_def.as_bytes(),
+            unk_def.as_bytes(),
+        );
+        assert!(result.is_err());
+    }
 }
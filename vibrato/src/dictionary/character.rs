@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BinaryHeap, HashMap};
 use std::fmt;
 use std::io::{BufRead, BufReader, Read};
 
@@ -7,27 +7,34 @@ use bincode::{Decode, Encode};
 use crate::errors::{Result, VibratoError};
 use crate::utils::FromU32;
 
-const CATE_IDSET_BITS: usize = 18;
-const CATE_IDSET_MASK: u32 = (1 << CATE_IDSET_BITS) - 1;
-const BASE_ID_BITS: usize = 8;
+const BASE_ID_BITS: usize = 16;
 const BASE_ID_MASK: u32 = (1 << BASE_ID_BITS) - 1;
 const LENGTH_BITS: usize = 4;
 
-/// Information of a character defined in `char.def`.
+/// The maximum number of categories that can be defined in `char.def`, including `DEFAULT`.
+pub const MAX_CATEGORIES: usize = 1 << BASE_ID_BITS;
+
+/// The maximum number of distinct pairs of information and category sets of characters.
+const MAX_CHAR_INFOS: usize = 1 << u16::BITS;
+
+/// The first code point out of the Basic Multilingual Plane (BMP).
+const ASTRAL_START: u32 = 0x10000;
+/// The end (exclusive) of code points.
+const CODE_POINT_END: u32 = 0x110000;
+
+/// Information of a character category defined in `char.def`.
 ///
 /// The memory layout is
-///   cate_idset = 18 bits
-///      base_id =  8 bits
-///       invoke =  1 bit
-///        group =  1 bit
-///       length =  4 bits
-#[derive(Default, Clone, Copy, Decode, Encode)]
+///   base_id = 16 bits
+///    invoke =  1 bit
+///     group =  1 bit
+///    length =  4 bits
+#[derive(Default, Clone, Copy, Eq, PartialEq, Hash, Decode, Encode)]
 pub struct CharInfo(u32);
 
 impl fmt::Debug for CharInfo {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("CharInfo")
-            .field("cate_idset", &self.cate_idset())
             .field("base_id", &self.base_id())
             .field("invoke", &self.invoke())
             .field("group", &self.group())
@@ -37,16 +44,7 @@ impl fmt::Debug for CharInfo {
 }
 
 impl CharInfo {
-    pub fn new(
-        cate_idset: u32,
-        base_id: u32,
-        invoke: bool,
-        group: bool,
-        length: u16,
-    ) -> Option<Self> {
-        if cate_idset >> CATE_IDSET_BITS != 0 {
-            return None;
-        }
+    pub fn new(base_id: u32, invoke: bool, group: bool, length: u16) -> Option<Self> {
         if base_id >> BASE_ID_BITS != 0 {
             return None;
         }
@@ -54,43 +52,31 @@ impl CharInfo {
             return None;
         }
         Some(Self(
-            cate_idset
-                | (base_id << CATE_IDSET_BITS)
-                | (u32::from(invoke) << (CATE_IDSET_BITS + BASE_ID_BITS))
-                | (u32::from(group) << (CATE_IDSET_BITS + BASE_ID_BITS + 1))
-                | ((u32::from(length)) << (CATE_IDSET_BITS + BASE_ID_BITS + 2)),
+            base_id
+                | (u32::from(invoke) << BASE_ID_BITS)
+                | (u32::from(group) << (BASE_ID_BITS + 1))
+                | (u32::from(length) << (BASE_ID_BITS + 2)),
         ))
     }
 
-    #[inline(always)]
-    pub const fn reset_cate_idset(&mut self, cate_idset: u32) {
-        self.0 &= !CATE_IDSET_MASK;
-        self.0 |= cate_idset;
-    }
-
-    #[inline(always)]
-    pub const fn cate_idset(&self) -> u32 {
-        self.0 & CATE_IDSET_MASK
-    }
-
     #[inline(always)]
     pub const fn base_id(&self) -> u32 {
-        (self.0 >> CATE_IDSET_BITS) & BASE_ID_MASK
+        self.0 & BASE_ID_MASK
     }
 
     #[inline(always)]
     pub const fn invoke(&self) -> bool {
-        (self.0 >> (CATE_IDSET_BITS + BASE_ID_BITS)) & 1 != 0
+        (self.0 >> BASE_ID_BITS) & 1 != 0
     }
 
     #[inline(always)]
     pub const fn group(&self) -> bool {
-        (self.0 >> (CATE_IDSET_BITS + BASE_ID_BITS + 1)) & 1 != 0
+        (self.0 >> (BASE_ID_BITS + 1)) & 1 != 0
     }
 
     #[inline(always)]
     pub const fn length(&self) -> u16 {
-        (self.0 >> (CATE_IDSET_BITS + BASE_ID_BITS + 2)) as u16
+        (self.0 >> (BASE_ID_BITS + 2)) as u16
     }
 }
 
@@ -98,21 +84,111 @@ struct CharRange {
     start: usize,
     end: usize,
     categories: Vec<String>,
+    line_no: usize,
+}
+
+/// Builder of the deduplicated table of character information and category sets.
+struct CharInfoTable {
+    infos: Vec<CharInfo>,
+    cate_idsets: Vec<u64>,
+    cate_idset_len: usize,
+    ids: HashMap<(CharInfo, Vec<u64>), u16>,
+}
+
+impl CharInfoTable {
+    fn new(num_categories: usize) -> Self {
+        Self {
+            infos: vec![],
+            cate_idsets: vec![],
+            cate_idset_len: num_categories.div_ceil(64),
+            ids: HashMap::new(),
+        }
+    }
+
+    /// Gets the id of the pair, adding it to the table if not found.
+    fn id(&mut self, cinfo: CharInfo, cate_idset: Vec<u64>) -> Option<u16> {
+        debug_assert_eq!(cate_idset.len(), self.cate_idset_len);
+        if let Some(&id) = self.ids.get(&(cinfo, cate_idset.clone())) {
+            return Some(id);
+        }
+        let id = u16::try_from(self.infos.len()).ok()?;
+        self.infos.push(cinfo);
+        self.cate_idsets.extend_from_slice(&cate_idset);
+        self.ids.insert((cinfo, cate_idset), id);
+        Some(id)
+    }
 }
 
 /// Mapping from characters to their information.
+///
+/// Characters are mapped to 16-bit ids of a deduplicated table of pairs of
+/// the information and the set of categories, so that the number of categories is not
+/// limited by the size of the mapping.
+/// Characters in the BMP are mapped with a direct table,
+/// and those in the other planes are mapped with a sorted list of ranges
+/// because they are sparsely defined in practice.
 #[derive(Decode, Encode)]
 pub struct CharProperty {
-    chr2inf: Vec<CharInfo>,
+    chr2inf: Vec<u16>,       // info ids indexed by code points in the BMP
+    astral_starts: Vec<u32>, // start code points of ranges covering the other planes
+    astral_infos: Vec<u16>,  // info ids indexed by range ids
+    infos: Vec<CharInfo>,    // indexed by info ids
+    cate_idsets: Vec<u64>, // bitsets of category ids of `cate_idset_len` words, indexed by info ids
+    cate_idset_len: u32,
     categories: Vec<String>, // indexed by category id
 }
 
 impl CharProperty {
+    /// Gets the id of the information of the character.
+    #[inline(always)]
+    pub fn char_info_id(&self, c: char) -> u16 {
+        let c = u32::from(c);
+        if let Some(&info_id) = self.chr2inf.get(usize::from_u32(c)) {
+            return info_id;
+        }
+        // astral_starts[0] is always ASTRAL_START (checked by `verify()` on deserialization),
+        // so the index never underflows.
+        let i = self.astral_starts.partition_point(|&start| start <= c) - 1;
+        self.astral_infos[i]
+    }
+
     #[inline(always)]
     pub fn char_info(&self, c: char) -> CharInfo {
-        self.chr2inf
-            .get(usize::from_u32(u32::from(c)))
-            .map_or_else(|| self.chr2inf[0], |cinfo| *cinfo)
+        self.info(self.char_info_id(c))
+    }
+
+    /// Gets the information of the id.
+    #[inline(always)]
+    pub fn info(&self, info_id: u16) -> CharInfo {
+        self.infos[usize::from(info_id)]
+    }
+
+    #[inline(always)]
+    fn cate_idset(&self, info_id: u16) -> &[u64] {
+        let len = usize::from_u32(self.cate_idset_len);
+        let start = usize::from(info_id) * len;
+        &self.cate_idsets[start..start + len]
+    }
+
+    /// Checks if the characters of the id belong to the category.
+    #[inline(always)]
+    pub fn has_category(&self, info_id: u16, cate_id: u32) -> bool {
+        let cate_id = usize::from_u32(cate_id);
+        self.cate_idset(info_id)
+            .get(cate_id / 64)
+            .is_some_and(|&bits| (bits >> (cate_id % 64)) & 1 != 0)
+    }
+
+    /// Checks if the characters of the two ids share a category.
+    #[inline(always)]
+    pub fn shares_category(&self, lhs: u16, rhs: u16) -> bool {
+        // Every set contains the base category, so the same ids always share it.
+        lhs == rhs
+            || self
+                .cate_idset(lhs)
+                .iter()
+                .zip(self.cate_idset(rhs))
+                .any(|(&l, &r)| l & r != 0)
     }
 
     #[inline(always)]
@@ -136,86 +212,321 @@ impl CharProperty {
         self.categories.len()
     }
 
+    /// Gets the category names indexed by category ids.
+    #[inline(always)]
+    pub fn categories(&self) -> &[String] {
+        &self.categories
+    }
+
+    /// Returns the number of bytes of the heap memory.
+    pub fn memory_usage(&self) -> usize {
+        (self.chr2inf.len() + self.astral_infos.len()) * std::mem::size_of::<u16>()
+            + self.astral_starts.len() * std::mem::size_of::<u32>()
+            + self.infos.len() * std::mem::size_of::<CharInfo>()
+            + self.cate_idsets.len() * std::mem::size_of::<u64>()
+            + self
+                .categories
+                .iter()
+                .map(|c| std::mem::size_of::<String>() + c.len())
+                .sum::<usize>()
+    }
+
     /// Creates a new instance from `char.def`.
     pub fn from_reader<R>(rdr: R) -> Result<Self>
     where
         R: Read,
     {
-        let mut cate2info = HashMap::new();
-        let mut cate_map = HashMap::new(); // Name -> Id
-        let mut char_ranges = vec![];
-
-        cate_map.insert("DEFAULT".to_string(), 0);
-
-        let reader = BufReader::new(rdr);
-        for line in reader.lines() {
-            let line = line?;
-            let line = line.trim();
-
-            if line.is_empty() || line.starts_with('#') {
-                continue;
-            }
+        Self::from_def(CharDef::from_reader(rdr, None)?)
+    }
 
-            if !line.starts_with("0x") {
-                let (category, invoke, group, length) = Self::parse_char_category(line)?;
-                let new_cate_id = u32::try_from(cate_map.len()).unwrap();
-                let cate_id = *cate_map.entry(category).or_insert(new_cate_id);
-                cate2info.insert(
-                    cate_id,
-                    CharInfo::new(0, cate_id, invoke, group, length).unwrap(),
+    /// Creates a new instance from `char.def` whose categories are a subset of `categories`.
+    ///
+    /// The category ids are identical to the indices of `categories`, so the instance is
+    /// compatible with handlers of unknown words built for `categories`.
+    /// The categories of ids in `required` must be defined in the input.
+    pub fn from_reader_with_categories<R>(
+        rdr: R,
+        categories: &[String],
+        required: &[u32],
+    ) -> Result<Self>
+    where
+        R: Read,
+    {
+        let def = CharDef::from_reader(rdr, Some(categories))?;
+        for &cate_id in required {
+            if !def.cate2info.contains_key(&cate_id) {
+                let msg = format!(
+                    "Category {} is required by the dictionary but not defined",
+                    categories[usize::from_u32(cate_id)]
                 );
-            } else {
-                char_ranges.push(Self::parse_char_range(line)?);
+                return Err(VibratoError::invalid_format("char.def", msg));
             }
         }
+        Self::from_def(def)
+    }
+
+    /// Creates a new instance by patching `self` with `char.def`.
+    ///
+    /// The input can redefine the existing categories and assign them to character ranges,
+    /// and the other characters are mapped as in `self`.
+    /// A redefined category applies to all the characters whose first category it is.
+    pub fn patch_from_reader<R>(&self, rdr: R) -> Result<Self>
+    where
+        R: Read,
+    {
+        let mut def = CharDef::from_reader(rdr, Some(&self.categories))?;
+        // Categories not redefined keep their information.
+        for &cinfo in &self.infos {
+            def.cate2info.entry(cinfo.base_id()).or_insert(cinfo);
+        }
 
-        let init_cinfo = Self::encode_cate_info(&["DEFAULT"], &cate2info, &cate_map)?;
-        let mut chr2inf = vec![init_cinfo; 1 << 16];
+        let mut table = CharInfoTable::new(self.categories.len());
+        let mut id_map = Vec::with_capacity(self.infos.len());
+        for (info_id, cinfo) in self.infos.iter().enumerate() {
+            let info_id = u16::try_from(info_id).unwrap();
+            let cinfo = def.cate2info[&cinfo.base_id()];
+            // Never exceeds the limit because the table is no larger than that of `self`.
+            let new_id = table.id(cinfo, self.cate_idset(info_id).to_vec()).unwrap();
+            id_map.push(new_id);
+        }
+        let chr2inf = self
+            .chr2inf
+            .iter()
+            .map(|&info_id| id_map[usize::from(info_id)])
+            .collect();
+        let ends = self.astral_starts[1..].iter().chain([&CODE_POINT_END]);
+        let astral_ranges = self
+            .astral_starts
+            .iter()
+            .zip(ends)
+            .zip(&self.astral_infos)
+            .map(|((&start, &end), &info_id)| (start, end, id_map[usize::from(info_id)]))
+            .collect();
+        Self::build(def, table, chr2inf, astral_ranges)
+    }
 
-        for r in &char_ranges {
-            let cinfo = Self::encode_cate_info(&r.categories, &cate2info, &cate_map)?;
-            for e in chr2inf.iter_mut().take(r.end).skip(r.start) {
-                *e = cinfo;
+    fn from_def(def: CharDef) -> Result<Self> {
+        let mut table = CharInfoTable::new(def.cate_map.len());
+        let init_id =
+            Self::encode_cate_info(&["DEFAULT"], &def.cate2info, &def.cate_map, &mut table)?;
+        let chr2inf = vec![init_id; usize::from_u32(ASTRAL_START)];
+        let astral_ranges = vec![(ASTRAL_START, CODE_POINT_END, init_id)];
+        Self::build(def, table, chr2inf, astral_ranges)
+    }
+
+    /// Assigns the character ranges in `def` on top of the given mapping,
+    /// where `astral_ranges` must cover all the code points out of the BMP.
+    fn build(
+        def: CharDef,
+        mut table: CharInfoTable,
+        mut chr2inf: Vec<u16>,
+        mut astral_ranges: Vec<(u32, u32, u16)>,
+    ) -> Result<Self> {
+        for r in &def.ranges {
+            let info_id =
+                Self::encode_cate_info(&r.categories, &def.cate2info, &def.cate_map, &mut table)
+                    .map_err(|e| e.at("char.def", r.line_no, None))?;
+            let bmp_end = r.end.min(chr2inf.len());
+            if r.start < bmp_end {
+                chr2inf[r.start..bmp_end].fill(info_id);
+            }
+            if r.end > chr2inf.len() {
+                let start = u32::try_from(r.start).unwrap().max(ASTRAL_START);
+                let end = u32::try_from(r.end).unwrap();
+                astral_ranges.push((start, end, info_id));
             }
         }
+        let (astral_starts, astral_infos) = Self::merge_astral_ranges(&astral_ranges);
 
-        let mut categories = vec![String::new(); cate_map.len()];
-        for (k, v) in cate_map {
+        let mut categories = vec![String::new(); def.cate_map.len()];
+        for (k, v) in def.cate_map {
             categories[usize::from_u32(v)] = k;
         }
 
         Ok(Self {
             chr2inf,
+            astral_starts,
+            astral_infos,
+            infos: table.infos,
+            cate_idsets: table.cate_idsets,
+            cate_idset_len: u32::try_from(table.cate_idset_len).unwrap(),
             categories,
         })
     }
 
+    /// Merges `ranges` of `(start, end, info_id)` out of the BMP, where later ones
+    /// take precedence, into sorted lists of the start code points and the info ids.
+    ///
+    /// The ranges must cover all the code points out of the BMP.
+    /// Adjacent ranges with the same information are merged.
+    fn merge_astral_ranges(ranges: &[(u32, u32, u16)]) -> (Vec<u32>, Vec<u16>) {
+        let mut events = Vec::with_capacity(ranges.len() * 2);
+        for (i, &(start, end, _)) in ranges.iter().enumerate() {
+            debug_assert!(ASTRAL_START <= start && start < end && end <= CODE_POINT_END);
+            events.push((start, i));
+            events.push((end, i));
+        }
+        events.sort_unstable();
+
+        // Indices of the ranges covering the current position, where the maximum is
+        // the latest one. Ended ranges are removed lazily.
+        let mut active = BinaryHeap::new();
+        let mut ended = vec![false; ranges.len()];
+        let mut starts = vec![];
+        let mut infos = vec![];
+        let mut events = events.into_iter().peekable();
+        while let Some(&(pos, _)) = events.peek() {
+            if pos == CODE_POINT_END {
+                break;
+            }
+            while let Some((_, i)) = events.next_if(|&(p, _)| p == pos) {
+                if ranges[i].0 == pos {
+                    active.push(i);
+                } else {
+                    ended[i] = true;
+                }
+            }
+            while active.peek().is_some_and(|&i| ended[i]) {
+                active.pop();
+            }
+            let info_id = ranges[*active.peek().unwrap()].2;
+            if infos.last() != Some(&info_id) {
+                starts.push(pos);
+                infos.push(info_id);
+            }
+        }
+        (starts, infos)
+    }
+
+    /// Checks that the mapping is well-formed, e.g., after deserialization.
+    pub fn verify(&self) -> bool {
+        let num_infos = self.infos.len();
+        let cate_idset_len = usize::from_u32(self.cate_idset_len);
+        self.chr2inf.len() == usize::from_u32(ASTRAL_START)
+            && self.astral_starts.first() == Some(&ASTRAL_START)
+            && self.astral_starts.windows(2).all(|w| w[0] < w[1])
+            && self.astral_starts.last() < Some(&CODE_POINT_END)
+            && self.astral_starts.len() == self.astral_infos.len()
+            && self
+                .chr2inf
+                .iter()
+                .chain(&self.astral_infos)
+                .all(|&info_id| usize::from(info_id) < num_infos)
+            && self
+                .infos
+                .iter()
+                .all(|cinfo| usize::from_u32(cinfo.base_id()) < self.categories.len())
+            && cate_idset_len == self.categories.len().div_ceil(64)
+            && self.cate_idsets.len() == num_infos * cate_idset_len
+    }
+
     fn encode_cate_info<S>(
         targets: &[S],
         cate2info: &HashMap<u32, CharInfo>,
         cate_map: &HashMap<String, u32>,
-    ) -> Result<CharInfo>
+        table: &mut CharInfoTable,
+    ) -> Result<u16>
     where
         S: AsRef<str>,
     {
-        let mut base_cinfo = *cate_map
-            .get(targets[0].as_ref())
-            .and_then(|base_target_id| cate2info.get(base_target_id))
-            .ok_or_else(|| {
-                let msg = format!("Undefined category: {}", targets[0].as_ref());
-                VibratoError::invalid_format("char.def", msg)
-            })?;
-        let mut cate_idset = base_cinfo.cate_idset();
+        let cinfo = |target: &S| {
+            cate_map
+                .get(target.as_ref())
+                .and_then(|target_id| cate2info.get(target_id))
+                .copied()
+                .ok_or_else(|| {
+                    let msg = format!("Undefined category: {}", target.as_ref());
+                    VibratoError::invalid_format("char.def", msg)
+                })
+        };
+        let base_cinfo = cinfo(&targets[0])?;
+        let mut cate_idset = vec![0; table.cate_idset_len];
         for target in targets {
-            let target_id = cate_map.get(target.as_ref()).unwrap();
-            let cinfo = cate2info.get(target_id).unwrap();
-            cate_idset |= 1 << cinfo.base_id();
+            let base_id = usize::from_u32(cinfo(target)?.base_id());
+            cate_idset[base_id / 64] |= 1 << (base_id % 64);
+        }
+        table.id(base_cinfo, cate_idset).ok_or_else(|| {
+            let msg = format!(
+                "The number of distinct combinations of categories must be no more than {MAX_CHAR_INFOS}"
+            );
+            VibratoError::invalid_format("char.def", msg)
+        })
+    }
+}
+
+/// Definitions of categories and character ranges parsed from `char.def`.
+struct CharDef {
+    cate_map: HashMap<String, u32>, // Name -> Id
+    cate2info: HashMap<u32, CharInfo>,
+    ranges: Vec<CharRange>,
+}
+
+impl CharDef {
+    /// Parses `char.def`, whose categories must be in `known_categories` if specified.
+    fn from_reader<R>(rdr: R, known_categories: Option<&[String]>) -> Result<Self>
+    where
+        R: Read,
+    {
+        let mut cate2info = HashMap::new();
+        let mut cate_map = HashMap::new();
+        let mut ranges = vec![];
+
+        if let Some(known_categories) = known_categories {
+            for (i, category) in known_categories.iter().enumerate() {
+                cate_map.insert(category.clone(), u32::try_from(i).unwrap());
+            }
+        } else {
+            cate_map.insert("DEFAULT".to_string(), 0);
+        }
+
+        let reader = BufReader::new(rdr);
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            let line_no = i + 1;
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if !line.starts_with("0x") {
+                let (category, invoke, group, length) = Self::parse_char_category(line, line_no)?;
+                if known_categories.is_some() && !cate_map.contains_key(&category) {
+                    let msg = format!("Category {category} is not defined in the dictionary");
+                    return Err(VibratoError::invalid_format_at(
+                        "char.def",
+                        line_no,
+                        Some(1),
+                        msg,
+                    ));
+                }
+                let new_cate_id = u32::try_from(cate_map.len()).unwrap();
+                let cate_id = *cate_map.entry(category).or_insert(new_cate_id);
+                if usize::from_u32(cate_id) >= MAX_CATEGORIES {
+                    let msg =
+                        format!("The number of categories must be no more than {MAX_CATEGORIES}");
+                    return Err(VibratoError::invalid_format_at(
+                        "char.def", line_no, None, msg,
+                    ));
+                }
+                let cinfo = CharInfo::new(cate_id, invoke, group, length).ok_or_else(|| {
+                    let msg = format!("LENGTH must be less than {}", 1 << LENGTH_BITS);
+                    VibratoError::invalid_format_at("char.def", line_no, Some(4), msg)
+                })?;
+                cate2info.insert(cate_id, cinfo);
+            } else {
+                ranges.push(Self::parse_char_range(line, line_no)?);
+            }
         }
-        base_cinfo.reset_cate_idset(cate_idset);
-        Ok(base_cinfo)
+
+        Ok(Self {
+            cate_map,
+            cate2info,
+            ranges,
+        })
     }
 
-    fn parse_char_category(line: &str) -> Result<(String, bool, bool, u16)> {
+    fn parse_char_category(line: &str, line_no: usize) -> Result<(String, bool, bool, u16)> {
         assert!(!line.is_empty());
         assert!(!line.starts_with("0x"));
 
@@ -224,48 +535,81 @@ impl CharProperty {
             let msg = format!(
                 "A character category must consists of four items separated by spaces, {line}",
             );
-            return Err(VibratoError::invalid_format("char.def", msg));
+            return Err(VibratoError::invalid_format_at(
+                "char.def", line_no, None, msg,
+            ));
         }
 
         let category = cols[0].to_string();
         let invoke = ["1", "0"]
             .contains(&cols[1])
             .then(|| cols[1] == "1")
-            .ok_or_else(|| VibratoError::invalid_format("char.def", "INVOKE must be 1 or 0."))?;
+            .ok_or_else(|| {
+                VibratoError::invalid_format_at(
+                    "char.def",
+                    line_no,
+                    Some(2),
+                    "INVOKE must be 1 or 0.",
+                )
+            })?;
         let group = ["1", "0"]
             .contains(&cols[2])
             .then(|| cols[2] == "1")
-            .ok_or_else(|| VibratoError::invalid_format("char.def", "GROUP must be 1 or 0."))?;
-        let length = cols[3].parse()?;
+            .ok_or_else(|| {
+                VibratoError::invalid_format_at(
+                    "char.def",
+                    line_no,
+                    Some(3),
+                    "GROUP must be 1 or 0.",
+                )
+            })?;
+        let length = cols[3]
+            .parse()
+            .map_err(|e| VibratoError::from(e).at("char.def", line_no, Some(4)))?;
 
         Ok((category, invoke, group, length))
     }
 
-    fn parse_char_range(line: &str) -> Result<CharRange> {
+    fn parse_char_range(line: &str, line_no: usize) -> Result<CharRange> {
         assert!(!line.is_empty());
         assert!(line.starts_with("0x"));
 
         let cols: Vec<_> = line.split_whitespace().collect();
         if cols.len() < 2 {
             let msg = format!("A character range must have two items at least, {line}");
-            return Err(VibratoError::invalid_format("char.def", msg));
+            return Err(VibratoError::invalid_format_at(
+                "char.def", line_no, None, msg,
+            ));
         }
 
         let r: Vec<_> = cols[0].split("..").collect();
-        let start = usize::from_str_radix(String::from(r[0]).trim_start_matches("0x"), 16)?;
+        let start = usize::from_str_radix(String::from(r[0]).trim_start_matches("0x"), 16)
+            .map_err(|e| VibratoError::from(e).at("char.def", line_no, Some(1)))?;
         let end = if r.len() > 1 {
-            usize::from_str_radix(String::from(r[1]).trim_start_matches("0x"), 16)? + 1
+            usize::from_str_radix(String::from(r[1]).trim_start_matches("0x"), 16)
+                .map_err(|e| VibratoError::from(e).at("char.def", line_no, Some(1)))?
+                + 1
         } else {
             start + 1
         };
         if start >= end {
             let msg =
                 format!("The start of a character range must be no more than the end, {line}");
-            return Err(VibratoError::invalid_format("char.def", msg));
+            return Err(VibratoError::invalid_format_at(
+                "char.def",
+                line_no,
+                Some(1),
+                msg,
+            ));
         }
-        if start > 0xFFFF || end > 0x10000 {
-            let msg = format!("A character range must be no more 0xFFFF, {line}");
-            return Err(VibratoError::invalid_format("char.def", msg));
+        if end > usize::from_u32(CODE_POINT_END) {
+            let msg = format!("A character range must be no more 0x10FFFF, {line}");
+            return Err(VibratoError::invalid_format_at(
+                "char.def",
+                line_no,
+                Some(1),
+                msg,
+            ));
         }
 
         let mut categories = vec![];
@@ -277,6 +621,7 @@ impl CharProperty {
             start,
             end,
             categories,
+            line_no,
         })
     }
 }
@@ -289,11 +634,15 @@ mod tests {
     fn test_from_reader_basic() {
         let data = "DEFAULT 0 1 0\nSPACE 0 1 0\n0x0020 SPACE";
         let prop = CharProperty::from_reader(data.as_bytes()).unwrap();
-        assert_eq!(prop.chr2inf[0x0020].cate_idset(), 0b10);
-        assert_eq!(prop.chr2inf[0x0020].base_id(), 1);
-        assert_eq!(prop.chr2inf[0x0020].invoke(), false);
-        assert_eq!(prop.chr2inf[0x0020].group(), true);
-        assert_eq!(prop.chr2inf[0x0020].length(), 0);
+        let info_id = prop.chr2inf[0x0020];
+        assert!(!prop.has_category(info_id, 0));
+        assert!(prop.has_category(info_id, 1));
+        let cinfo = prop.info(info_id);
+        assert_eq!(cinfo.base_id(), 1);
+        assert!(!cinfo.invoke());
+        assert!(cinfo.group());
+        assert_eq!(cinfo.length(), 0);
+        assert_eq!(prop.infos.len(), 2);
     }
 
     #[test]
@@ -303,6 +652,22 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_from_reader_error_location() {
+        let data = "DEFAULT 0 1 0\n# comment\n0x0..0xFFFF INVALID";
+        let Err(VibratoError::InvalidFormat(e)) = CharProperty::from_reader(data.as_bytes()) else {
+            panic!("must be an InvalidFormat error");
+        };
+        assert_eq!(e.line(), Some(3));
+
+        let data = "DEFAULT 0 1 0\nSPACE 0 1 x";
+        let Err(VibratoError::InvalidFormat(e)) = CharProperty::from_reader(data.as_bytes()) else {
+            panic!("must be an InvalidFormat error");
+        };
+        assert_eq!(e.line(), Some(2));
+        assert_eq!(e.column(), Some(4));
+    }
+
     #[test]
     fn test_from_reader_no_default_cate() {
         let data = "USER_DEFINED 0 1 0";
@@ -340,7 +705,7 @@ mod tests {
 
     #[test]
     fn test_from_reader_char_range_1() {
-        let data = "DEFAULT 0 1 0\n0x10000 DEFAULT";
+        let data = "DEFAULT 0 1 0\n0x110000 DEFAULT";
         let result = CharProperty::from_reader(data.as_bytes());
         assert!(result.is_err());
     }
@@ -353,7 +718,7 @@ mod tests {
 
     #[test]
     fn test_from_reader_char_range_3() {
-        let data = "DEFAULT 0 1 0\n0x0..0x10000 DEFAULT";
+        let data = "DEFAULT 0 1 0\n0x0..0x110000 DEFAULT";
         let result = CharProperty::from_reader(data.as_bytes());
         assert!(result.is_err());
     }
@@ -364,4 +729,212 @@ mod tests {
         let result = CharProperty::from_reader(data.as_bytes());
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_from_reader_char_range_astral() {
+        let data = "DEFAULT 0 1 0\nKANJI 0 0 2\nEMOJI 1 1 0\nSPACE 0 1 0\n\
+                    0x4E00..0x9FFF KANJI\n\
+                    0x1F000..0x10FFFF SPACE\n\
+                    0x20000..0x2A6DF KANJI\n\
+                    0x1F300..0x1F5FF EMOJI\n\
+                    0x1F600..0x1F64F EMOJI";
+        let prop = CharProperty::from_reader(data.as_bytes()).unwrap();
+        let kanji = prop.cate_id("KANJI").unwrap();
+        let emoji = prop.cate_id("EMOJI").unwrap();
+        let space = prop.cate_id("SPACE").unwrap();
+
+        assert_eq!(prop.char_info('漢').base_id(), kanji);
+        assert_eq!(prop.char_info('\u{10000}').base_id(), 0);
+        assert_eq!(prop.char_info('\u{1F0FF}').base_id(), space);
+        assert_eq!(prop.char_info('\u{1F300}').base_id(), emoji);
+        assert_eq!(prop.char_info('\u{1F600}').base_id(), emoji);
+        assert_eq!(prop.char_info('\u{1F64F}').base_id(), emoji);
+        assert_eq!(prop.char_info('\u{1F650}').base_id(), space);
+        assert_eq!(prop.char_info('\u{20B9F}').base_id(), kanji);
+        assert_eq!(prop.char_info('\u{2A6E0}').base_id(), space);
+        assert_eq!(prop.char_info('\u{10FFFF}').base_id(), space);
+        assert!(prop.char_info('\u{1F600}').invoke());
+        assert_eq!(
+            prop.astral_starts,
+            vec![0x10000, 0x1F000, 0x1F300, 0x1F650, 0x20000, 0x2A6E0]
+        );
+    }
+
+    #[test]
+    fn test_merge_astral_ranges() {
+        let (a, b, c) = (0, 1, 2);
+        let ranges = [(0x10000, 0x110000, a)];
+        let (starts, infos) = CharProperty::merge_astral_ranges(&ranges);
+        assert_eq!(starts, vec![0x10000]);
+        assert_eq!(infos, vec![a]);
+
+        let ranges = [
+            (0x10000, 0x110000, a),
+            (0x20000, 0x30000, b),
+            (0x28000, 0x110000, b),
+            (0x2C000, 0x2D000, c),
+            (0x40000, 0x50000, a),
+        ];
+        let (starts, infos) = CharProperty::merge_astral_ranges(&ranges);
+        assert_eq!(
+            starts,
+            vec![0x10000, 0x20000, 0x2C000, 0x2D000, 0x40000, 0x50000]
+        );
+        assert_eq!(infos, vec![a, b, c, b, a, b]);
+
+        let ranges = [
+            (0x10000, 0x110000, a),
+            (0x20000, 0x30000, b),
+            (0x10000, 0x110000, a),
+        ];
+        let (starts, infos) = CharProperty::merge_astral_ranges(&ranges);
+        assert_eq!(starts, vec![0x10000]);
+        assert_eq!(infos, vec![a]);
+    }
+
+    #[test]
+    fn test_verify() {
+        let data = "DEFAULT 0 1 0\nEMOJI 1 1 0\n0x1F600..0x1F64F EMOJI";
+        let prop = CharProperty::from_reader(data.as_bytes()).unwrap();
+        assert!(prop.verify());
+
+        let mut broken = prop.patch_from_reader("".as_bytes()).unwrap();
+        broken.astral_starts[0] = 0x10001;
+        assert!(!broken.verify());
+
+        let mut broken = prop.patch_from_reader("".as_bytes()).unwrap();
+        broken.astral_starts.swap(1, 2);
+        assert!(!broken.verify());
+
+        let mut broken = prop.patch_from_reader("".as_bytes()).unwrap();
+        broken.chr2inf[0] = 2;
+        assert!(!broken.verify());
+
+        let mut broken = prop.patch_from_reader("".as_bytes()).unwrap();
+        broken.cate_idsets.pop();
+        assert!(!broken.verify());
+    }
+
+    #[test]
+    fn test_from_reader_many_categories() {
+        let mut data = "DEFAULT 0 1 0\n".to_string();
+        for i in 1..200 {
+            data.push_str(&format!(
+                "CATE{i} 0 1 0\n0x{:X} CATE{i} CATE1\n",
+                0x4E00 + i
+            ));
+        }
+        let prop = CharProperty::from_reader(data.as_bytes()).unwrap();
+        assert_eq!(prop.num_categories(), 200);
+
+        let info_id = prop.char_info_id('\u{4EC7}');
+        assert_eq!(prop.info(info_id).base_id(), 199);
+        assert!(prop.has_category(info_id, 199));
+        assert!(prop.has_category(info_id, 1));
+        assert!(!prop.has_category(info_id, 198));
+        assert!(prop.shares_category(info_id, prop.char_info_id('\u{4E01}')));
+        assert!(!prop.shares_category(info_id, prop.char_info_id('\u{4E00}')));
+
+        for i in 200..MAX_CATEGORIES {
+            data.push_str(&format!("CATE{i} 0 1 0\n"));
+        }
+        let prop = CharProperty::from_reader(data.as_bytes()).unwrap();
+        assert_eq!(prop.num_categories(), MAX_CATEGORIES);
+
+        data.push_str("OVERFLOW 0 1 0\n");
+        let result = CharProperty::from_reader(data.as_bytes());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_from_reader_many_combinations() {
+        let mut data = "DEFAULT 0 1 0\n".to_string();
+        for i in 1..=256 {
+            data.push_str(&format!("CATE{i} 0 1 0\n"));
+        }
+        for c in 0..0x10000 {
+            data.push_str(&format!(
+                "0x{c:X} CATE{} CATE{}\n",
+                c / 256 + 1,
+                c % 256 + 1
+            ));
+        }
+        let result = CharProperty::from_reader(data.as_bytes());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_from_reader_too_long_length() {
+        let data = "DEFAULT 0 1 16";
+        let result = CharProperty::from_reader(data.as_bytes());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_from_reader_with_categories() {
+        let categories: Vec<_> = ["DEFAULT", "SPACE", "ALPHA", "NUMERIC"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        let data = "DEFAULT 0 1 0\nNUMERIC 1 1 0\nALPHA 1 1 0\n0x0030..0x0039 NUMERIC ALPHA";
+        let prop =
+            CharProperty::from_reader_with_categories(data.as_bytes(), &categories, &[]).unwrap();
+        assert_eq!(prop.categories(), categories);
+        assert_eq!(prop.cate_id("NUMERIC"), Some(3));
+        let info_id = prop.char_info_id('0');
+        assert_eq!(prop.info(info_id).base_id(), 3);
+        assert!(prop.has_category(info_id, 2));
+        assert!(prop.has_category(info_id, 3));
+        assert!(!prop.has_category(info_id, 1));
+    }
+
+    #[test]
+    fn test_from_reader_with_categories_undefined() {
+        let categories = vec!["DEFAULT".to_string(), "ALPHA".to_string()];
+        let data = "DEFAULT 0 1 0\nKANJI 0 0 2";
+        let Err(VibratoError::InvalidFormat(e)) =
+            CharProperty::from_reader_with_categories(data.as_bytes(), &categories, &[])
+        else {
+            panic!("must be an InvalidFormat error");
+        };
+        assert_eq!(e.line(), Some(2));
+
+        // ALPHA is known but not defined in the input.
+        let data = "DEFAULT 0 1 0\n0x0041..0x005A ALPHA";
+        let result = CharProperty::from_reader_with_categories(data.as_bytes(), &categories, &[]);
+        assert!(result.is_err());
+
+        // ALPHA is required but not defined in the input.
+        let data = "DEFAULT 0 1 0";
+        let result = CharProperty::from_reader_with_categories(data.as_bytes(), &categories, &[1]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_patch_from_reader() {
+        let data = "DEFAULT 0 1 0\nALPHA 1 1 0\nNUMERIC 1 1 0\nEMOJI 1 1 0\n\
+                    0x0030..0x0039 NUMERIC\n0x0041..0x005A ALPHA\n0x1F600..0x1F64F EMOJI";
+        let prop = CharProperty::from_reader(data.as_bytes()).unwrap();
+
+        let patch = "ALPHA 0 1 2\n0x002D ALPHA\n0x1F610 DEFAULT";
+        let patched = prop.patch_from_reader(patch.as_bytes()).unwrap();
+        assert_eq!(patched.categories(), prop.categories());
+
+        let alpha = patched.cate_id("ALPHA").unwrap();
+        for c in ['A', '-'] {
+            let cinfo = patched.char_info(c);
+            assert_eq!(cinfo.base_id(), alpha);
+            assert!(!cinfo.invoke());
+            assert_eq!(cinfo.length(), 2);
+        }
+        // The other characters are unchanged.
+        for c in ['0', 'あ', '\u{1F600}', '\u{1F64F}'] {
+            assert_eq!(patched.char_info(c), prop.char_info(c));
+        }
+        assert_eq!(patched.char_info('\u{1F610}'), prop.char_info('あ'));
+
+        // Only categories in the dictionary can be defined.
+        let patch = "KANJI 0 0 2\n0x4E00 KANJI";
+        assert!(prop.patch_from_reader(patch.as_bytes()).is_err());
+    }
 }
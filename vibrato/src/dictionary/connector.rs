@@ -1,13 +1,18 @@
+mod compressed_matrix_connector;
+mod custom_connector;
 mod dual_connector;
 mod matrix_connector;
 mod raw_connector;
 
 use bincode::{Decode, Encode};
 
-pub use crate::dictionary::connector::dual_connector::DualConnector;
+pub use crate::dictionary::connector::compressed_matrix_connector::CompressedMatrixConnector;
+pub use crate::dictionary::connector::custom_connector::{BoxedConnector, CustomConnector};
+pub use crate::dictionary::connector::dual_connector::{DualConnector, TemplateRemoval};
 pub use crate::dictionary::connector::matrix_connector::MatrixConnector;
 pub use crate::dictionary::connector::raw_connector::RawConnector;
 use crate::dictionary::mapper::ConnIdMapper;
+use crate::dictionary::{ConnectorKind, ConnectorMemoryUsage};
 
 pub trait Connector {
     /// Returns maximum number of left connection ID
@@ -22,7 +27,7 @@ pub trait Connector {
     fn map_connection_ids(&mut self, mapper: &ConnIdMapper);
 }
 
-pub trait ConnectorCost: Connector {
+pub trait ConnectorCost {
     /// Gets the value of the connection matrix
     fn cost(&self, right_id: u16, left_id: u16) -> i32;
 }
@@ -32,6 +37,43 @@ pub enum ConnectorWrapper {
     Matrix(MatrixConnector),
     Raw(RawConnector),
     Dual(DualConnector),
+    CompressedMatrix(CompressedMatrixConnector),
+    Custom(BoxedConnector),
+    WideMatrix(MatrixConnector<i32>),
+}
+
+impl ConnectorWrapper {
+    /// Returns the number of bytes of the heap memory.
+    pub fn memory_usage(&self) -> ConnectorMemoryUsage {
+        match self {
+            Self::Matrix(c) => ConnectorMemoryUsage {
+                kind: ConnectorKind::Matrix,
+                matrix: c.memory_usage(),
+                feature_ids: 0,
+                scorer: 0,
+            },
+            Self::WideMatrix(c) => ConnectorMemoryUsage {
+                kind: ConnectorKind::Matrix,
+                matrix: c.memory_usage(),
+                feature_ids: 0,
+                scorer: 0,
+            },
+            Self::Raw(c) => c.memory_usage(),
+            Self::Dual(c) => c.memory_usage(),
+            Self::CompressedMatrix(c) => ConnectorMemoryUsage {
+                kind: ConnectorKind::CompressedMatrix,
+                matrix: c.memory_usage(),
+                feature_ids: 0,
+                scorer: 0,
+            },
+            Self::Custom(c) => ConnectorMemoryUsage {
+                kind: ConnectorKind::Custom,
+                matrix: c.memory_usage(),
+                feature_ids: 0,
+                scorer: 0,
+            },
+        }
+    }
 }
 
 impl Connector for ConnectorWrapper {
@@ -39,8 +81,11 @@ impl Connector for ConnectorWrapper {
     fn num_left(&self) -> usize {
         match self {
             Self::Matrix(c) => c.num_left(),
+            Self::WideMatrix(c) => c.num_left(),
             Self::Raw(c) => c.num_left(),
             Self::Dual(c) => c.num_left(),
+            Self::CompressedMatrix(c) => c.num_left(),
+            Self::Custom(c) => c.num_left(),
         }
     }
 
@@ -48,8 +93,11 @@ impl Connector for ConnectorWrapper {
     fn num_right(&self) -> usize {
         match self {
             Self::Matrix(c) => c.num_right(),
+            Self::WideMatrix(c) => c.num_right(),
             Self::Raw(c) => c.num_right(),
             Self::Dual(c) => c.num_right(),
+            Self::CompressedMatrix(c) => c.num_right(),
+            Self::Custom(c) => c.num_right(),
         }
     }
 
@@ -57,8 +105,11 @@ impl Connector for ConnectorWrapper {
     fn map_connection_ids(&mut self, mapper: &ConnIdMapper) {
         match self {
             Self::Matrix(c) => c.map_connection_ids(mapper),
+            Self::WideMatrix(c) => c.map_connection_ids(mapper),
             Self::Raw(c) => c.map_connection_ids(mapper),
             Self::Dual(c) => c.map_connection_ids(mapper),
+            Self::CompressedMatrix(c) => c.map_connection_ids(mapper),
+            Self::Custom(c) => c.map_connection_ids(mapper),
         }
     }
 }
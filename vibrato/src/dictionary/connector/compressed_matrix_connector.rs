@@ -0,0 +1,238 @@
+use bincode::{Decode, Encode};
+
+use crate::dictionary::connector::{Connector, ConnectorCost, MatrixConnector};
+use crate::dictionary::mapper::ConnIdMapper;
+
+/// Maximum code of a quantized cost.
+const MAX_CODE: i32 = u8::MAX as i32;
+
+/// Encoding of a row of the matrix associated with a left id.
+#[derive(Clone, Copy, Decode, Encode)]
+struct Row {
+    base: i16,
+    // Interval of quantized costs, or `0` if the row is stored as is.
+    step: u16,
+    // Start position of the row in `codes` or `raw`.
+    offset: u32,
+}
+
+/// Matrix of connection costs quantized into 8 bits with a scale for each row.
+///
+/// A cost is approximated by `base + step * code` with a code in `0..=255`.
+/// Rows that cannot be approximated within the given maximum error are stored as is,
+/// so the error of any cost is bounded.
+#[derive(Decode, Encode)]
+pub struct CompressedMatrixConnector {
+    rows: Vec<Row>,
+    codes: Vec<u8>,
+    raw: Vec<i16>,
+    num_right: usize,
+    num_left: usize,
+    max_error: u16,
+}
+
+impl CompressedMatrixConnector {
+    /// Compresses a matrix so that the error of each cost is no more than `max_error`.
+    pub fn from_matrix(matrix: &MatrixConnector, max_error: u16) -> Self {
+        let num_right = matrix.num_right();
+        let num_left = matrix.num_left();
+        let mut rows = Vec::with_capacity(num_left);
+        let mut codes = vec![];
+        let mut raw = vec![];
+        let mut actual_error = 0;
+        let mut row_codes = Vec::with_capacity(num_right);
+        for left_id in 0..num_left {
+            let costs = matrix.row(left_id);
+            if let Some((base, step, error)) = quantize(costs, max_error, &mut row_codes) {
+                let offset = u32::try_from(codes.len()).unwrap();
+                codes.extend_from_slice(&row_codes);
+                rows.push(Row { base, step, offset });
+                actual_error = actual_error.max(error);
+            } else {
+                let offset = u32::try_from(raw.len()).unwrap();
+                raw.extend_from_slice(costs);
+                rows.push(Row {
+                    base: 0,
+                    step: 0,
+                    offset,
+                });
+            }
+        }
+        Self {
+            rows,
+            codes,
+            raw,
+            num_right,
+            num_left,
+            max_error: actual_error,
+        }
+    }
+
+    /// Returns the maximum absolute error of the costs introduced by the compression.
+    pub const fn max_error(&self) -> u16 {
+        self.max_error
+    }
+
+    /// Returns the number of bytes of the heap memory.
+    pub fn memory_usage(&self) -> usize {
+        self.rows.len() * std::mem::size_of::<Row>()
+            + self.codes.len()
+            + self.raw.len() * std::mem::size_of::<i16>()
+    }
+}
+
+/// Quantizes `costs` into `codes`, returning the base, the step, and the maximum error
+/// if the error is no more than `max_error`.
+fn quantize(costs: &[i16], max_error: u16, codes: &mut Vec<u8>) -> Option<(i16, u16, u16)> {
+    codes.clear();
+    let min = i32::from(*costs.iter().min()?);
+    let max = i32::from(*costs.iter().max()?);
+    // Rounding to the nearest code makes the error at most half of the step.
+    let step = ((max - min + MAX_CODE - 1) / MAX_CODE).max(1);
+    if step / 2 > i32::from(max_error) {
+        return None;
+    }
+    let mut error = 0;
+    for &cost in costs {
+        let code = ((i32::from(cost) - min + step / 2) / step).min(MAX_CODE);
+        error = error.max((min + step * code - i32::from(cost)).abs());
+        codes.push(u8::try_from(code).unwrap());
+    }
+    if error > i32::from(max_error) {
+        return None;
+    }
+    Some((
+        i16::try_from(min).unwrap(),
+        u16::try_from(step).unwrap(),
+        u16::try_from(error).unwrap(),
+    ))
+}
+
+impl Connector for CompressedMatrixConnector {
+    #[inline(always)]
+    fn num_left(&self) -> usize {
+        self.num_left
+    }
+
+    #[inline(always)]
+    fn num_right(&self) -> usize {
+        self.num_right
+    }
+
+    fn map_connection_ids(&mut self, mapper: &ConnIdMapper) {
+        assert_eq!(mapper.num_left(), self.num_left);
+        assert_eq!(mapper.num_right(), self.num_right);
+
+        let mut old_left_ids = vec![0; self.num_left];
+        for left_id in 0..self.num_left {
+            let new_left_id = usize::from(mapper.left(left_id as u16));
+            old_left_ids[new_left_id] = left_id;
+        }
+
+        let mut rows = Vec::with_capacity(self.num_left);
+        let mut codes = Vec::with_capacity(self.codes.len());
+        let mut raw = Vec::with_capacity(self.raw.len());
+        for &left_id in &old_left_ids {
+            let row = self.rows[left_id];
+            let start = row.offset as usize;
+            let offset = if row.step == 0 {
+                let offset = raw.len();
+                raw.resize(offset + self.num_right, 0);
+                for right_id in 0..self.num_right {
+                    let new_right_id = usize::from(mapper.right(right_id as u16));
+                    raw[offset + new_right_id] = self.raw[start + right_id];
+                }
+                offset
+            } else {
+                let offset = codes.len();
+                codes.resize(offset + self.num_right, 0);
+                for right_id in 0..self.num_right {
+                    let new_right_id = usize::from(mapper.right(right_id as u16));
+                    codes[offset + new_right_id] = self.codes[start + right_id];
+                }
+                offset
+            };
+            rows.push(Row {
+                offset: u32::try_from(offset).unwrap(),
+                ..row
+            });
+        }
+        self.rows = rows;
+        self.codes = codes;
+        self.raw = raw;
+    }
+}
+
+impl ConnectorCost for CompressedMatrixConnector {
+    #[inline(always)]
+    fn cost(&self, right_id: u16, left_id: u16) -> i32 {
+        debug_assert!(usize::from(right_id) < self.num_right);
+        debug_assert!(usize::from(left_id) < self.num_left);
+        let row = self.rows[usize::from(left_id)];
+        let index = row.offset as usize + usize::from(right_id);
+        if row.step == 0 {
+            i32::from(self.raw[index])
+        } else {
+            i32::from(row.base) + i32::from(row.step) * i32::from(self.codes[index])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix() -> MatrixConnector {
+        let data = "3 2
+0 0 0
+1 0 100
+2 0 -100
+0 1 -30000
+1 1 30000
+2 1 12345";
+        MatrixConnector::from_reader(data.as_bytes()).unwrap()
+    }
+
+    fn assert_within(conn: &CompressedMatrixConnector, matrix: &MatrixConnector, max_error: u16) {
+        for right_id in 0..3 {
+            for left_id in 0..2 {
+                let diff = conn.cost(right_id, left_id) - matrix.cost(right_id, left_id);
+                assert!(diff.unsigned_abs() <= u32::from(conn.max_error()));
+            }
+        }
+        assert!(conn.max_error() <= max_error);
+    }
+
+    #[test]
+    fn test_lossless() {
+        let matrix = matrix();
+        let conn = CompressedMatrixConnector::from_matrix(&matrix, 0);
+        assert_within(&conn, &matrix, 0);
+        // The first row fits in 8 bits, but the second one does not.
+        assert_eq!((conn.codes.len(), conn.raw.len()), (3, 3));
+    }
+
+    #[test]
+    fn test_lossy() {
+        let matrix = matrix();
+        let conn = CompressedMatrixConnector::from_matrix(&matrix, 200);
+        assert_within(&conn, &matrix, 200);
+        assert!(conn.max_error() > 0);
+        assert_eq!((conn.codes.len(), conn.raw.len()), (6, 0));
+    }
+
+    #[test]
+    fn test_mapping() {
+        let matrix = matrix();
+        let mapper = ConnIdMapper::new(vec![1, 0], vec![2, 0, 1]);
+        let mut conn = CompressedMatrixConnector::from_matrix(&matrix, 0);
+        conn.map_connection_ids(&mapper);
+
+        assert_eq!(conn.cost(0, 0), 30000);
+        assert_eq!(conn.cost(1, 0), 12345);
+        assert_eq!(conn.cost(2, 0), -30000);
+        assert_eq!(conn.cost(0, 1), 100);
+        assert_eq!(conn.cost(1, 1), -100);
+        assert_eq!(conn.cost(2, 1), 0);
+    }
+}
@@ -0,0 +1,155 @@
+use bincode::de::Decoder;
+use bincode::enc::Encoder;
+use bincode::error::{DecodeError, EncodeError};
+use bincode::{Decode, Encode};
+
+use crate::dictionary::connector::{Connector, ConnectorCost};
+use crate::dictionary::mapper::ConnIdMapper;
+
+/// Model of connection costs supplied by users.
+///
+/// A custom connector can be plugged into a dictionary with
+/// [`SystemDictionaryBuilder::build_from_readers_with_connector()`](crate::SystemDictionaryBuilder::build_from_readers_with_connector)
+/// or [`Dictionary::reset_connector()`](crate::Dictionary::reset_connector),
+/// and the lattice is built in the same manner as the built-in connectors.
+/// Such a dictionary cannot be exported with [`Dictionary::write()`](crate::Dictionary::write).
+///
+/// # Invariants
+///
+/// Implementations must satisfy the following conditions:
+///
+///  - Connection id [`BOS_EOS_CONNECTION_ID`](crate::common::BOS_EOS_CONNECTION_ID) (`0`) is
+///    reserved for BOS/EOS; it is used as the right id of BOS and the left id of EOS.
+///  - [`Self::cost()`] accepts any `right_id` less than [`Self::num_right()`] and
+///    any `left_id` less than [`Self::num_left()`]. The numbers must cover all the ids
+///    of the lexicons and the unknown-word entries, or the dictionary is rejected.
+///  - [`Self::map_connection_ids()`] renumbers the ids so that the cost of a pair of new ids
+///    equals that of the old pair, leaving id `0` unchanged.
+///
+/// # Examples
+///
+/// ```
+/// use vibrato::dictionary::CustomConnector;
+///
+/// /// Connector assigning costs by the classes of connection ids.
+/// struct ClassConnector {
+///     right_classes: Vec<usize>,
+///     left_classes: Vec<usize>,
+///     costs: Vec<Vec<i32>>,
+/// }
+///
+/// impl CustomConnector for ClassConnector {
+///     fn num_left(&self) -> usize {
+///         self.left_classes.len()
+///     }
+///
+///     fn num_right(&self) -> usize {
+///         self.right_classes.len()
+///     }
+///
+///     fn cost(&self, right_id: u16, left_id: u16) -> i32 {
+///         let right_class = self.right_classes[usize::from(right_id)];
+///         let left_class = self.left_classes[usize::from(left_id)];
+///         self.costs[right_class][left_class]
+///     }
+///
+///     fn map_connection_ids(&mut self, left_map: &[u16], right_map: &[u16]) {
+///         let mut left_classes = vec![0; self.left_classes.len()];
+///         for (old_id, &new_id) in left_map.iter().enumerate() {
+///             left_classes[usize::from(new_id)] = self.left_classes[old_id];
+///         }
+///         self.left_classes = left_classes;
+///         let mut right_classes = vec![0; self.right_classes.len()];
+///         for (old_id, &new_id) in right_map.iter().enumerate() {
+///             right_classes[usize::from(new_id)] = self.right_classes[old_id];
+///         }
+///         self.right_classes = right_classes;
+///     }
+/// }
+/// ```
+pub trait CustomConnector: Send + Sync {
+    /// Returns the number of left connection ids.
+    fn num_left(&self) -> usize;
+
+    /// Returns the number of right connection ids.
+    fn num_right(&self) -> usize;
+
+    /// Returns the cost of connecting a word with `right_id` to the following word
+    /// with `left_id`.
+    fn cost(&self, right_id: u16, left_id: u16) -> i32;
+
+    /// Renumbers connection ids, where `left_map[i]` and `right_map[i]` are the new ids
+    /// of old left and right ids `i`, respectively.
+    ///
+    /// The maps are permutations of `0..self.num_left()` and `0..self.num_right()`
+    /// fixing `0`.
+    /// This is called when the connector is plugged into a dictionary whose ids have been
+    /// edited with [`Dictionary::map_connection_ids_from_iter()`](crate::Dictionary::map_connection_ids_from_iter),
+    /// and when the ids of the dictionary are edited later.
+    fn map_connection_ids(&mut self, left_map: &[u16], right_map: &[u16]);
+
+    /// Returns the number of bytes of the heap memory, which is reported by
+    /// [`Dictionary::memory_usage()`](crate::Dictionary::memory_usage).
+    fn memory_usage(&self) -> usize {
+        0
+    }
+}
+
+/// Holder of a custom connector in a dictionary.
+pub struct BoxedConnector(Box<dyn CustomConnector>);
+
+impl BoxedConnector {
+    pub fn new<C>(connector: C) -> Self
+    where
+        C: CustomConnector + 'static,
+    {
+        Self(Box::new(connector))
+    }
+
+    /// Returns the number of bytes of the heap memory.
+    pub fn memory_usage(&self) -> usize {
+        self.0.memory_usage()
+    }
+}
+
+impl Connector for BoxedConnector {
+    #[inline(always)]
+    fn num_left(&self) -> usize {
+        self.0.num_left()
+    }
+
+    #[inline(always)]
+    fn num_right(&self) -> usize {
+        self.0.num_right()
+    }
+
+    fn map_connection_ids(&mut self, mapper: &ConnIdMapper) {
+        assert_eq!(mapper.num_left(), self.num_left());
+        assert_eq!(mapper.num_right(), self.num_right());
+        self.0
+            .map_connection_ids(mapper.left_map(), mapper.right_map());
+    }
+}
+
+impl ConnectorCost for BoxedConnector {
+    #[inline(always)]
+    fn cost(&self, right_id: u16, left_id: u16) -> i32 {
+        self.0.cost(right_id, left_id)
+    }
+}
+
+impl Encode for BoxedConnector {
+    fn encode<E: Encoder>(&self, _encoder: &mut E) -> Result<(), EncodeError> {
+        Err(EncodeError::Other("custom connectors cannot be serialized"))
+    }
+}
+
+impl<Context> Decode<Context> for BoxedConnector {
+    fn decode<D: Decoder>(_decoder: &mut D) -> Result<Self, DecodeError> {
+        Err(DecodeError::Other(
+            "custom connectors cannot be deserialized",
+        ))
+    }
+}
+
+bincode::impl_borrow_decode!(BoxedConnector);
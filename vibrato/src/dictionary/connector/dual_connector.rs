@@ -9,6 +9,7 @@ use crate::dictionary::connector::raw_connector::scorer::{
 use crate::dictionary::connector::raw_connector::{RawConnectorBuilder, INVALID_FEATURE_ID};
 use crate::dictionary::connector::{Connector, ConnectorCost, MatrixConnector};
 use crate::dictionary::mapper::ConnIdMapper;
+use crate::dictionary::{ConnectorKind, ConnectorMemoryUsage};
 use crate::errors::Result;
 use crate::num::U31;
 
@@ -22,63 +23,220 @@ pub struct DualConnector {
     raw_scorer: Scorer,
 }
 
+/// Removal of a feature template from the matrix of a dual connector.
+///
+/// This is reported to the callback given to
+/// [`SystemDictionaryBuilder::dual_connector_progress()`](crate::SystemDictionaryBuilder::dual_connector_progress).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TemplateRemoval {
+    /// Index of the feature template moved from the matrix to the raw scorer.
+    pub template: usize,
+    /// Number of right connection ids of the matrix after the removal.
+    pub num_right: usize,
+    /// Number of left connection ids of the matrix after the removal.
+    pub num_left: usize,
+}
+
+impl TemplateRemoval {
+    /// Returns the number of cells of the matrix after the removal.
+    pub const fn matrix_size(&self) -> usize {
+        self.num_right * self.num_left
+    }
+}
+
+/// Signatures of rows of feature ids, updated incrementally on removing feature templates.
+///
+/// A signature is the wrapping sum of hashes of pairs of a template index and a feature id,
+/// so removing a template from a signature only subtracts the hash of the pair.
+/// Rows are counted as distinct by their feature ids, not only by their signatures,
+/// so hash collisions do not understate the size of the matrix.
+struct RowSignatures<'a> {
+    feat_ids_tmp: &'a [Vec<U31>],
+    // The first signature is for the row reserved for BOS/EOS.
+    signatures: Vec<u64>,
+    // Templates remaining in the signatures.
+    templates: Vec<usize>,
+}
+
+impl<'a> RowSignatures<'a> {
+    fn new(feat_ids_tmp: &'a [Vec<U31>], feat_template_size: usize) -> Self {
+        let mut signatures = vec![0u64; feat_ids_tmp.len() + 1];
+        for template in 0..feat_template_size {
+            for (i, signature) in signatures.iter_mut().enumerate() {
+                *signature = signature
+                    .wrapping_add(feature_hash(template, feat_id(feat_ids_tmp, i, template)));
+            }
+        }
+        Self {
+            feat_ids_tmp,
+            signatures,
+            templates: (0..feat_template_size).collect(),
+        }
+    }
+
+    /// Counts distinct rows.
+    fn count(&self) -> usize {
+        self.count_distinct(None)
+    }
+
+    /// Counts distinct rows after removing the template.
+    fn count_without(&self, template: usize) -> usize {
+        self.count_distinct(Some(template))
+    }
+
+    fn count_distinct(&self, excluded: Option<usize>) -> usize {
+        // The first row for each signature, and the other distinct rows on collisions.
+        let mut firsts = HashMap::with_capacity(self.signatures.len());
+        let mut collisions: HashMap<u64, Vec<usize>> = HashMap::new();
+        let mut count = 0;
+        for (i, &signature) in self.signatures.iter().enumerate() {
+            let signature = excluded.map_or(signature, |template| {
+                let feat_id = feat_id(self.feat_ids_tmp, i, template);
+                signature.wrapping_sub(feature_hash(template, feat_id))
+            });
+            let &mut first = firsts.entry(signature).or_insert(i);
+            if first == i {
+                count += 1;
+            } else if !self.rows_eq(i, first, excluded) {
+                let others = collisions.entry(signature).or_default();
+                if !others.iter().any(|&j| self.rows_eq(i, j, excluded)) {
+                    others.push(i);
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    /// Checks if the `i`-th and `j`-th rows have the same feature ids
+    /// in the remaining templates other than `excluded`.
+    fn rows_eq(&self, i: usize, j: usize, excluded: Option<usize>) -> bool {
+        self.templates
+            .iter()
+            .filter(|&&template| Some(template) != excluded)
+            .all(|&template| {
+                feat_id(self.feat_ids_tmp, i, template) == feat_id(self.feat_ids_tmp, j, template)
+            })
+    }
+
+    fn remove(&mut self, template: usize) {
+        for (i, signature) in self.signatures.iter_mut().enumerate() {
+            let feat_id = feat_id(self.feat_ids_tmp, i, template);
+            *signature = signature.wrapping_sub(feature_hash(template, feat_id));
+        }
+        self.templates.retain(|&t| t != template);
+    }
+}
+
+/// Gets the feature id of the template in the `i`-th row, where the row `0` is for BOS/EOS.
+#[inline(always)]
+fn feat_id(feat_ids_tmp: &[Vec<U31>], i: usize, template: usize) -> U31 {
+    if i == 0 {
+        U31::default()
+    } else {
+        *feat_ids_tmp[i - 1]
+            .get(template)
+            .unwrap_or(&INVALID_FEATURE_ID)
+    }
+}
+
+/// Hashes a pair of a template index and a feature id with SplitMix64.
+#[inline(always)]
+fn feature_hash(template: usize, feat_id: U31) -> u64 {
+    let mut z =
+        ((template as u64) << 32 | u64::from(feat_id.get())).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Applies `f` to each candidate in parallel.
+fn map_parallel<F>(candidates: &[usize], f: F) -> Vec<usize>
+where
+    F: Fn(usize) -> usize + Sync,
+{
+    let num_threads = std::thread::available_parallelism()
+        .map_or(1, std::num::NonZeroUsize::get)
+        .min(candidates.len());
+    if num_threads <= 1 {
+        return candidates.iter().map(|&c| f(c)).collect();
+    }
+    let chunk_size = candidates.len().div_ceil(num_threads);
+    let f = &f;
+    std::thread::scope(|s| {
+        let mut handles = Vec::with_capacity(num_threads);
+        for chunk in candidates.chunks(chunk_size) {
+            handles.push(s.spawn(move || chunk.iter().map(|&c| f(c)).collect::<Vec<_>>()));
+        }
+        let mut results = Vec::with_capacity(candidates.len());
+        for handle in handles {
+            results.extend(handle.join().unwrap());
+        }
+        results
+    })
+}
+
 impl DualConnector {
-    /// Removes feature templates so that the matrix size is smaller using greedy search
-    /// and returns a set of rest IDs.
-    pub fn remove_feature_templates_greedy(
-        raw_feat_template_size: usize,
+    /// Moves feature templates from the matrix to the raw scorer using greedy search,
+    /// so that the matrix is smaller, and returns the moved templates in order.
+    ///
+    /// In each round, the template whose removal minimizes the matrix is selected.
+    /// Up to [`SIMD_SIZE`] templates are moved, but the search stops once the number of cells
+    /// of the matrix is no more than `max_matrix_size`.
+    pub fn remove_feature_templates_greedy<F>(
         right_feat_ids_tmp: &[Vec<U31>],
         left_feat_ids_tmp: &[Vec<U31>],
         total_feat_template_size: usize,
-    ) -> HashSet<usize> {
-        let mut matrix_indices: HashSet<usize> = (0..total_feat_template_size).collect();
-        eprintln!(
-            "Initial matrix size: {}",
-            left_feat_ids_tmp.len() * right_feat_ids_tmp.len()
-        );
-        for _ in 0..raw_feat_template_size {
-            let mut candidate_idx = 0;
-            let mut min_matrix_size = left_feat_ids_tmp.len() * right_feat_ids_tmp.len();
-            for &trial_idx in &matrix_indices {
-                let calculate_num_conn_ids = |feat_ids_tmp: &[Vec<U31>]| {
-                    let mut map = HashMap::new();
-                    for row in feat_ids_tmp {
-                        let mut new_feats = vec![];
-                        for &i in &matrix_indices {
-                            if i != trial_idx {
-                                if let Some(f) = row.get(i) {
-                                    new_feats.push(f);
-                                }
-                            }
-                        }
-                        *map.entry(new_feats).or_insert(0) += 1;
-                    }
-                    map.len()
-                };
-                let right_num_conn_ids = calculate_num_conn_ids(right_feat_ids_tmp);
-                let left_num_conn_ids = calculate_num_conn_ids(left_feat_ids_tmp);
-                if right_num_conn_ids * left_num_conn_ids <= min_matrix_size {
-                    min_matrix_size = right_num_conn_ids * left_num_conn_ids;
-                    candidate_idx = trial_idx;
-                }
+        max_matrix_size: Option<usize>,
+        mut progress: F,
+    ) -> Vec<usize>
+    where
+        F: FnMut(&TemplateRemoval),
+    {
+        let mut right_signatures = RowSignatures::new(right_feat_ids_tmp, total_feat_template_size);
+        let mut left_signatures = RowSignatures::new(left_feat_ids_tmp, total_feat_template_size);
+        let mut matrix_size = (right_feat_ids_tmp.len() + 1) * (left_feat_ids_tmp.len() + 1);
+        let mut candidates: Vec<usize> = (0..total_feat_template_size).collect();
+        let mut removed = vec![];
+        while removed.len() < SIMD_SIZE && !candidates.is_empty() {
+            if max_matrix_size.is_some_and(|max_size| matrix_size <= max_size) {
+                break;
             }
-            eprintln!("Removed feature template: #{candidate_idx}, matrix size: {min_matrix_size}");
-            matrix_indices.remove(&candidate_idx);
+            let sizes = map_parallel(&candidates, |template| {
+                right_signatures.count_without(template) * left_signatures.count_without(template)
+            });
+            // Ties are broken by the smallest index for reproducibility.
+            let (k, _) = sizes
+                .iter()
+                .enumerate()
+                .min_by_key(|&(k, &size)| (size, candidates[k]))
+                .unwrap();
+            let template = candidates.remove(k);
+            right_signatures.remove(template);
+            left_signatures.remove(template);
+            let num_right = right_signatures.count();
+            let num_left = left_signatures.count();
+            matrix_size = num_right * num_left;
+            removed.push(template);
+            progress(&TemplateRemoval {
+                template,
+                num_right,
+                num_left,
+            });
         }
-        matrix_indices
+        removed
     }
 
     fn create_matrix_connector(
         right_feat_ids_tmp: &[Vec<U31>],
         left_feat_ids_tmp: &[Vec<U31>],
         matrix_indices: &[usize],
-        feat_template_size: usize,
         scorer: &Scorer,
     ) -> (MatrixConnector, Vec<u16>, Vec<u16>) {
         let generate_feature_map = |feat_ids_tmp: &[Vec<U31>]| {
             let mut conn_id_map = vec![0];
             let mut feats_map = HashMap::new();
-            feats_map.insert(vec![U31::default(); feat_template_size - SIMD_SIZE], 0);
+            feats_map.insert(vec![U31::default(); matrix_indices.len()], 0);
             for row in feat_ids_tmp {
                 let mut feat_ids = vec![];
                 for &idx in matrix_indices {
@@ -114,18 +272,22 @@ impl DualConnector {
         raw_indices: &[usize],
         scorer_builder: &mut ScorerBuilder,
     ) -> (Vec<U31>, Vec<U31>) {
-        let mut right_feat_ids = vec![U31::default(); raw_indices.len()];
-        let mut left_feat_ids = vec![U31::default(); raw_indices.len()];
-        for row in right_feat_ids_tmp {
-            for &idx in raw_indices {
-                right_feat_ids.push(*row.get(idx).unwrap_or(&INVALID_FEATURE_ID));
-            }
-        }
-        for row in left_feat_ids_tmp {
-            for &idx in raw_indices {
-                left_feat_ids.push(*row.get(idx).unwrap_or(&INVALID_FEATURE_ID));
+        // Each row is padded with INVALID_FEATURE_IDs into a vector of SIMD_SIZE.
+        let collect_feature_ids = |feat_ids_tmp: &[Vec<U31>]| {
+            let mut feat_ids = vec![U31::default(); SIMD_SIZE];
+            for row in feat_ids_tmp {
+                for &idx in raw_indices {
+                    feat_ids.push(*row.get(idx).unwrap_or(&INVALID_FEATURE_ID));
+                }
+                feat_ids.resize(
+                    feat_ids.len() + SIMD_SIZE - raw_indices.len(),
+                    INVALID_FEATURE_ID,
+                );
             }
-        }
+            feat_ids
+        };
+        let right_feat_ids = collect_feature_ids(right_feat_ids_tmp);
+        let left_feat_ids = collect_feature_ids(left_feat_ids_tmp);
         let right_used_feats: HashSet<_> = right_feat_ids.iter().cloned().collect();
         let left_used_feats: HashSet<_> = left_feat_ids.iter().cloned().collect();
         for (i, left_map) in scorer_builder.trie.iter_mut().enumerate() {
@@ -143,12 +305,36 @@ impl DualConnector {
         (right_feat_ids, left_feat_ids)
     }
 
+    /// Returns the number of bytes of the heap memory.
+    ///
+    /// The maps from connection ids to rows/columns of the matrix are counted in `matrix`.
+    pub fn memory_usage(&self) -> ConnectorMemoryUsage {
+        ConnectorMemoryUsage {
+            kind: ConnectorKind::Dual,
+            matrix: self.matrix_connector.memory_usage()
+                + (self.right_conn_id_map.len() + self.left_conn_id_map.len())
+                    * std::mem::size_of::<u16>(),
+            feature_ids: (self.right_feat_ids.len() + self.left_feat_ids.len())
+                * std::mem::size_of::<U31x8>(),
+            scorer: self.raw_scorer.memory_usage(),
+        }
+    }
+
     /// Creates a new instance from `bigram.right`, `bigram.left`, and `bigram.cost`.
-    pub fn from_readers<R, L, C>(right_rdr: R, left_rdr: L, cost_rdr: C) -> Result<Self>
+    ///
+    /// See [`Self::remove_feature_templates_greedy()`] for `max_matrix_size` and `progress`.
+    pub fn from_readers<R, L, C, F>(
+        right_rdr: R,
+        left_rdr: L,
+        cost_rdr: C,
+        max_matrix_size: Option<usize>,
+        progress: F,
+    ) -> Result<Self>
     where
         R: Read,
         L: Read,
         C: Read,
+        F: FnMut(&TemplateRemoval),
     {
         let RawConnectorBuilder {
             right_feat_ids_tmp,
@@ -159,27 +345,22 @@ impl DualConnector {
         let scorer = scorer_builder.build();
 
         // Split features into RawConnector and MatrixConnector
-        let matrix_ids_set = Self::remove_feature_templates_greedy(
-            SIMD_SIZE,
+        let mut raw_indices = Self::remove_feature_templates_greedy(
             &right_feat_ids_tmp,
             &left_feat_ids_tmp,
             feat_template_size,
+            max_matrix_size,
+            progress,
         );
-        let mut matrix_indices = vec![];
-        let mut raw_indices = vec![];
-        for i in 0..feat_template_size {
-            if matrix_ids_set.contains(&i) {
-                matrix_indices.push(i);
-            } else {
-                raw_indices.push(i);
-            }
-        }
+        raw_indices.sort_unstable();
+        let matrix_indices: Vec<_> = (0..feat_template_size)
+            .filter(|i| raw_indices.binary_search(i).is_err())
+            .collect();
 
         let (matrix_connector, right_conn_id_map, left_conn_id_map) = Self::create_matrix_connector(
             &right_feat_ids_tmp,
             &left_feat_ids_tmp,
             &matrix_indices,
-            feat_template_size,
             &scorer,
         );
         let (right_feat_ids, left_feat_ids) = Self::create_raw_connector(
@@ -282,6 +463,8 @@ impl ConnectorCost for DualConnector {
 mod tests {
     use super::*;
 
+    use crate::dictionary::connector::RawConnector;
+
     #[test]
     fn from_readers_test() {
         let right_rdr = "\
@@ -313,7 +496,8 @@ YZ/yz\t-130
 90/90\t180"
             .as_bytes();
 
-        let conn = DualConnector::from_readers(right_rdr, left_rdr, cost_rdr).unwrap();
+        let conn =
+            DualConnector::from_readers(right_rdr, left_rdr, cost_rdr, None, |_| {}).unwrap();
 
         assert_eq!(conn.cost(1, 2), 50);
         assert_eq!(conn.cost(2, 1), 40);
@@ -350,7 +534,8 @@ YZ/yz\t-130
 90/90\t180"
             .as_bytes();
 
-        let mut conn = DualConnector::from_readers(right_rdr, left_rdr, cost_rdr).unwrap();
+        let mut conn =
+            DualConnector::from_readers(right_rdr, left_rdr, cost_rdr, None, |_| {}).unwrap();
 
         let mapper = ConnIdMapper::new(vec![1, 2, 0], vec![2, 0, 1]);
         conn.map_connection_ids(&mapper);
@@ -358,4 +543,120 @@ YZ/yz\t-130
         assert_eq!(conn.cost(0, 0), 50);
         assert_eq!(conn.cost(1, 2), 40);
     }
+
+    #[test]
+    fn remove_feature_templates_greedy_test() {
+        // Removing template 0 merges the first two rows, and then template 1 is redundant.
+        let right_feat_ids_tmp = vec![
+            vec![
+                U31::new(1).unwrap(),
+                U31::new(1).unwrap(),
+                U31::new(0).unwrap(),
+            ],
+            vec![
+                U31::new(2).unwrap(),
+                U31::new(1).unwrap(),
+                U31::new(0).unwrap(),
+            ],
+            vec![
+                U31::new(3).unwrap(),
+                U31::new(2).unwrap(),
+                U31::new(0).unwrap(),
+            ],
+        ];
+        let left_feat_ids_tmp = vec![vec![U31::new(1).unwrap(); 3]];
+
+        let mut removals = vec![];
+        let removed = DualConnector::remove_feature_templates_greedy(
+            &right_feat_ids_tmp,
+            &left_feat_ids_tmp,
+            3,
+            None,
+            |r| removals.push(*r),
+        );
+        assert_eq!(removed, vec![0, 1, 2]);
+        assert_eq!(removals[0].matrix_size(), 3 * 2);
+        assert_eq!(removals[1].matrix_size(), 2);
+        assert_eq!(removals[2].matrix_size(), 1);
+
+        let removed = DualConnector::remove_feature_templates_greedy(
+            &right_feat_ids_tmp,
+            &left_feat_ids_tmp,
+            3,
+            Some(6),
+            |_| {},
+        );
+        assert_eq!(removed, vec![0]);
+
+        let removed = DualConnector::remove_feature_templates_greedy(
+            &right_feat_ids_tmp,
+            &left_feat_ids_tmp,
+            3,
+            Some(100),
+            |_| {},
+        );
+        assert!(removed.is_empty());
+    }
+
+    #[test]
+    fn row_signatures_collision_test() {
+        let feat_ids_tmp = vec![
+            vec![U31::new(1).unwrap(), U31::new(2).unwrap()],
+            vec![U31::new(3).unwrap(), U31::new(2).unwrap()],
+            vec![U31::new(1).unwrap(), U31::new(2).unwrap()],
+        ];
+        let mut signatures = RowSignatures::new(&feat_ids_tmp, 2);
+        assert_eq!(signatures.count(), 3);
+
+        // Simulates collisions of all the signatures.
+        signatures.signatures.fill(0);
+        assert_eq!(signatures.count(), 3);
+
+        signatures.remove(0);
+        signatures.signatures.fill(0);
+        assert_eq!(signatures.count(), 2);
+    }
+
+    #[test]
+    fn max_matrix_size_test() {
+        let right_rdr = "\
+1\tAB,*,CD,*,EF,*,GH,*,IJ,*,KL,*,MN,*,OP,*,QR,*,ST
+2\tUV,*,WX,*,YZ,*,12,*,34,*,56,*,78,*,90,*,*,*,*";
+        let left_rdr = "\
+1\tuv,*,wx,*,yz,*,12,*,34,*,56,*,78,*,90,*,*,*,*
+2\tab,*,cd,*,ef,*,gh,*,ij,*,kl,*,mn,*,op,*,qr,*,st";
+        let cost_rdr = "\
+AB/ab\t-10
+CD/cd\t20
+ST/st\t100
+UV/uv\t-110
+90/90\t180";
+
+        let raw_conn = RawConnector::from_readers(
+            right_rdr.as_bytes(),
+            left_rdr.as_bytes(),
+            cost_rdr.as_bytes(),
+        )
+        .unwrap();
+        for max_matrix_size in [None, Some(4), Some(9)] {
+            let mut num_removals = 0;
+            let conn = DualConnector::from_readers(
+                right_rdr.as_bytes(),
+                left_rdr.as_bytes(),
+                cost_rdr.as_bytes(),
+                max_matrix_size,
+                |_| num_removals += 1,
+            )
+            .unwrap();
+            assert!(num_removals <= SIMD_SIZE);
+            for right_id in 0..3 {
+                for left_id in 0..3 {
+                    assert_eq!(
+                        conn.cost(right_id, left_id),
+                        raw_conn.cost(right_id, left_id)
+                    );
+                }
+            }
+        }
+    }
 }
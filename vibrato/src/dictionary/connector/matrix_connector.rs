@@ -4,18 +4,48 @@ use bincode::{Decode, Encode};
 
 use crate::dictionary::connector::{Connector, ConnectorCost};
 use crate::dictionary::mapper::ConnIdMapper;
+use crate::dictionary::CostWidth;
 use crate::errors::{Result, VibratoError};
 
+/// Type of costs stored in a matrix.
+pub trait MatrixCost: Copy + Default + Into<i32> {
+    const WIDTH: CostWidth;
+
+    /// Converts a cost checked with [`Self::WIDTH`].
+    fn from_checked(cost: i32) -> Self;
+}
+
+impl MatrixCost for i16 {
+    const WIDTH: CostWidth = CostWidth::Bits16;
+
+    #[inline(always)]
+    fn from_checked(cost: i32) -> Self {
+        Self::try_from(cost).unwrap()
+    }
+}
+
+impl MatrixCost for i32 {
+    const WIDTH: CostWidth = CostWidth::Bits32;
+
+    #[inline(always)]
+    fn from_checked(cost: i32) -> Self {
+        cost
+    }
+}
+
 /// Matrix of connection costs.
 #[derive(Decode, Encode)]
-pub struct MatrixConnector {
-    data: Vec<i16>,
+pub struct MatrixConnector<T = i16> {
+    data: Vec<T>,
     num_right: usize,
     num_left: usize,
 }
 
-impl MatrixConnector {
-    pub const fn new(data: Vec<i16>, num_right: usize, num_left: usize) -> Self {
+impl<T> MatrixConnector<T>
+where
+    T: MatrixCost,
+{
+    pub const fn new(data: Vec<T>, num_right: usize, num_left: usize) -> Self {
         Self {
             data,
             num_right,
@@ -31,20 +61,26 @@ impl MatrixConnector {
         let reader = BufReader::new(rdr);
         let mut lines = reader.lines();
 
-        let (num_right, num_left) = Self::parse_header(&lines.next().unwrap()?)?;
-        let mut data = vec![0; num_right * num_left];
+        let header = lines.next().ok_or_else(|| {
+            VibratoError::invalid_format_at("matrix.def", 1, None, "The header is missing.")
+        })??;
+        let (num_right, num_left) = Self::parse_header(&header)?;
+        let mut data = vec![T::default(); num_right * num_left];
 
-        for line in lines {
+        for (i, line) in lines.enumerate() {
             let line = line?;
+            let line_no = i + 2;
             if !line.is_empty() {
-                let (right_id, left_id, conn_cost) = Self::parse_body(&line)?;
+                let (right_id, left_id, conn_cost) = Self::parse_body(&line, line_no)?;
                 if num_right <= right_id || num_left <= left_id {
-                    return Err(VibratoError::invalid_format(
+                    return Err(VibratoError::invalid_format_at(
                         "matrix.def",
+                        line_no,
+                        None,
                         "left/right_id must be within num_left/right.",
                     ));
                 }
-                data[left_id * num_right + right_id] = conn_cost;
+                data[left_id * num_right + right_id] = T::from_checked(conn_cost);
             }
         }
         Ok(Self::new(data, num_right, num_left))
@@ -55,26 +91,54 @@ impl MatrixConnector {
         if cols.len() != 2 {
             let msg =
                 format!("The header must consists of two integers separated by spaces, {line}");
-            Err(VibratoError::invalid_format("matrix.def", msg))
+            Err(VibratoError::invalid_format_at("matrix.def", 1, None, msg))
         } else {
-            let num_right: u16 = cols[0].parse()?;
-            let num_left: u16 = cols[1].parse()?;
+            let num_right: u16 = Self::parse_col(cols[0], 1, 1)?;
+            let num_left: u16 = Self::parse_col(cols[1], 1, 2)?;
             Ok((usize::from(num_right), usize::from(num_left)))
         }
     }
 
-    fn parse_body(line: &str) -> Result<(usize, usize, i16)> {
+    fn parse_body(line: &str, line_no: usize) -> Result<(usize, usize, i32)> {
         let cols: Vec<_> = line.split(' ').collect();
         if cols.len() != 3 {
             let msg = format!(
                 "A row other than the header must consists of three integers separated by spaces, {line}"
             );
-            Err(VibratoError::invalid_format("matrix.def", msg))
+            Err(VibratoError::invalid_format_at(
+                "matrix.def",
+                line_no,
+                None,
+                msg,
+            ))
         } else {
-            Ok((cols[0].parse()?, cols[1].parse()?, cols[2].parse()?))
+            Ok((
+                Self::parse_col(cols[0], line_no, 1)?,
+                Self::parse_col(cols[1], line_no, 2)?,
+                T::WIDTH.parse_cost("matrix.def", line_no, 3, cols[2])?,
+            ))
         }
     }
 
+    fn parse_col<U>(col: &str, line_no: usize, column: usize) -> Result<U>
+    where
+        U: std::str::FromStr<Err = std::num::ParseIntError>,
+    {
+        col.parse()
+            .map_err(|e| VibratoError::from(e).at("matrix.def", line_no, Some(column)))
+    }
+
+    /// Returns the number of bytes of the heap memory.
+    pub fn memory_usage(&self) -> usize {
+        self.data.len() * std::mem::size_of::<T>()
+    }
+
+    /// Returns the costs associated with the left id, indexed by right ids.
+    pub(crate) fn row(&self, left_id: usize) -> &[T] {
+        let start = left_id * self.num_right;
+        &self.data[start..start + self.num_right]
+    }
+
     #[inline(always)]
     fn index(&self, right_id: u16, left_id: u16) -> usize {
         debug_assert!(usize::from(right_id) < self.num_right);
@@ -85,7 +149,10 @@ impl MatrixConnector {
     }
 }
 
-impl Connector for MatrixConnector {
+impl<T> Connector for MatrixConnector<T>
+where
+    T: MatrixCost,
+{
     #[inline(always)]
     fn num_left(&self) -> usize {
         self.num_left
@@ -100,7 +167,7 @@ impl Connector for MatrixConnector {
         assert_eq!(mapper.num_left(), self.num_left);
         assert_eq!(mapper.num_right(), self.num_right);
 
-        let mut mapped = vec![0; self.data.len()];
+        let mut mapped = vec![T::default(); self.data.len()];
         for right_id in 0..self.num_right {
             let right_id = right_id as u16;
             let new_right_id = mapper.right(right_id);
@@ -116,11 +183,14 @@ impl Connector for MatrixConnector {
     }
 }
 
-impl ConnectorCost for MatrixConnector {
+impl<T> ConnectorCost for MatrixConnector<T>
+where
+    T: MatrixCost,
+{
     #[inline(always)]
     fn cost(&self, right_id: u16, left_id: u16) -> i32 {
         let index = self.index(right_id, left_id);
-        i32::from(self.data[index])
+        self.data[index].into()
     }
 }
 
@@ -135,7 +205,7 @@ mod tests {
 0 1 1
 1 0 -2
 1 1 -3";
-        let conn = MatrixConnector::from_reader(data.as_bytes()).unwrap();
+        let conn = MatrixConnector::<i16>::from_reader(data.as_bytes()).unwrap();
         assert_eq!(conn.cost(0, 0), 0);
         assert_eq!(conn.cost(0, 1), 1);
         assert_eq!(conn.cost(1, 0), -2);
@@ -151,7 +221,7 @@ mod tests {
 1 0 -3
 1 1 -4
 1 2 -5";
-        let conn = MatrixConnector::from_reader(data.as_bytes()).unwrap();
+        let conn = MatrixConnector::<i16>::from_reader(data.as_bytes()).unwrap();
         assert_eq!(conn.cost(0, 0), 0);
         assert_eq!(conn.cost(0, 1), 1);
         assert_eq!(conn.cost(0, 2), 2);
@@ -169,7 +239,7 @@ mod tests {
 1 0 -3
 1 1 -4
 1 2 -5";
-        let mut conn = MatrixConnector::from_reader(data.as_bytes()).unwrap();
+        let mut conn = MatrixConnector::<i16>::from_reader(data.as_bytes()).unwrap();
 
         let mapper = ConnIdMapper::new(vec![2, 0, 1], vec![1, 0]);
         conn.map_connection_ids(&mapper);
@@ -189,7 +259,7 @@ mod tests {
 0 1 1
 1 0 -2
 1 1 -3";
-        let result = MatrixConnector::from_reader(data.as_bytes());
+        let result = MatrixConnector::<i16>::from_reader(data.as_bytes());
 
         assert!(result.is_err());
     }
@@ -201,7 +271,7 @@ mod tests {
 0 1 1
 1 0 -2
 1 1 -3";
-        let result = MatrixConnector::from_reader(data.as_bytes());
+        let result = MatrixConnector::<i16>::from_reader(data.as_bytes());
 
         assert!(result.is_err());
     }
@@ -213,7 +283,7 @@ mod tests {
 0 1 1
 1 -2
 1 1 -3";
-        let result = MatrixConnector::from_reader(data.as_bytes());
+        let result = MatrixConnector::<i16>::from_reader(data.as_bytes());
 
         assert!(result.is_err());
     }
@@ -225,7 +295,7 @@ mod tests {
 0 1 1
 1 0 1 -2
 1 1 -3";
-        let result = MatrixConnector::from_reader(data.as_bytes());
+        let result = MatrixConnector::<i16>::from_reader(data.as_bytes());
 
         assert!(result.is_err());
     }
@@ -233,7 +303,7 @@ mod tests {
     #[test]
     fn test_larger_matrix() {
         let data = "65536 65536";
-        let result = MatrixConnector::from_reader(data.as_bytes());
+        let result = MatrixConnector::<i16>::from_reader(data.as_bytes());
 
         assert!(result.is_err());
     }
@@ -245,7 +315,7 @@ mod tests {
 0 1 1
 1 2 -2
 1 1 -3";
-        let result = MatrixConnector::from_reader(data.as_bytes());
+        let result = MatrixConnector::<i16>::from_reader(data.as_bytes());
 
         assert!(result.is_err());
     }
@@ -257,8 +327,44 @@ mod tests {
 0 1 1
 2 0 -2
 1 1 -3";
-        let result = MatrixConnector::from_reader(data.as_bytes());
+        let result = MatrixConnector::<i16>::from_reader(data.as_bytes());
 
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_cost_width() {
+        let data = "2 1\n0 0 0\n1 0 40000";
+        let Err(VibratoError::InvalidFormat(e)) =
+            MatrixConnector::<i16>::from_reader(data.as_bytes())
+        else {
+            panic!("must be an InvalidFormat error");
+        };
+        assert_eq!(e.line(), Some(3));
+        assert_eq!(e.column(), Some(3));
+
+        let conn = MatrixConnector::<i32>::from_reader(data.as_bytes()).unwrap();
+        assert_eq!(conn.cost(1, 0), 40000);
+    }
+
+    #[test]
+    fn test_error_location() {
+        let data = "2 2\n0 0 0\n\n0 x 1";
+        let Err(VibratoError::InvalidFormat(e)) =
+            MatrixConnector::<i16>::from_reader(data.as_bytes())
+        else {
+            panic!("must be an InvalidFormat error");
+        };
+        assert_eq!(e.line(), Some(4));
+        assert_eq!(e.column(), Some(2));
+
+        let data = "2 2\n0 0 0\n2 0 1";
+        let Err(VibratoError::InvalidFormat(e)) =
+            MatrixConnector::<i16>::from_reader(data.as_bytes())
+        else {
+            panic!("must be an InvalidFormat error");
+        };
+        assert_eq!(e.line(), Some(3));
+        assert_eq!(e.column(), None);
+    }
 }
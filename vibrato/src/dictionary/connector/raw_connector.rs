@@ -10,6 +10,7 @@ use crate::dictionary::connector::raw_connector::scorer::{
 };
 use crate::dictionary::connector::{Connector, ConnectorCost};
 use crate::dictionary::mapper::ConnIdMapper;
+use crate::dictionary::{ConnectorKind, ConnectorMemoryUsage};
 use crate::errors::{Result, VibratoError};
 use crate::num::U31;
 use crate::utils;
@@ -96,6 +97,17 @@ impl RawConnector {
         ))
     }
 
+    /// Returns the number of bytes of the heap memory.
+    pub fn memory_usage(&self) -> ConnectorMemoryUsage {
+        ConnectorMemoryUsage {
+            kind: ConnectorKind::Raw,
+            matrix: 0,
+            feature_ids: (self.right_feat_ids.len() + self.left_feat_ids.len())
+                * std::mem::size_of::<U31x8>(),
+            scorer: self.scorer.memory_usage(),
+        }
+    }
+
     #[inline(always)]
     fn right_feature_ids(&self, right_id: u16) -> &[U31x8] {
         &self.right_feat_ids[usize::from(right_id) * self.feat_template_size
@@ -197,10 +209,11 @@ impl RawConnectorBuilder {
         let mut scorer_builder = ScorerBuilder::new();
 
         let cost_rdr = BufReader::new(cost_rdr);
-        for line in cost_rdr.lines() {
+        for (i, line) in cost_rdr.lines().enumerate() {
             let line = line?;
             let (right_feat_id, left_feat_id, cost) =
-                Self::parse_cost(&line, &mut right_feat_id_map, &mut left_feat_id_map)?;
+                Self::parse_cost(&line, &mut right_feat_id_map, &mut left_feat_id_map)
+                    .map_err(|e| e.at("bigram.cost", i + 1, None))?;
             scorer_builder.insert(right_feat_id, left_feat_id, cost);
         }
 
@@ -210,10 +223,13 @@ impl RawConnectorBuilder {
         let right_rdr = BufReader::new(right_rdr);
         for (i, line) in right_rdr.lines().enumerate() {
             let line = line?;
-            let (id, feat_ids) = Self::parse_features(&line, &right_feat_id_map, "bigram.right")?;
+            let (id, feat_ids) = Self::parse_features(&line, &right_feat_id_map, "bigram.right")
+                .map_err(|e| e.at("bigram.right", i + 1, None))?;
             if id != i + 1 {
-                return Err(VibratoError::invalid_format(
+                return Err(VibratoError::invalid_format_at(
                     "bigram.right",
+                    i + 1,
+                    Some(1),
                     "must be ascending order",
                 ));
             }
@@ -225,10 +241,13 @@ impl RawConnectorBuilder {
         let left_rdr = BufReader::new(left_rdr);
         for (i, line) in left_rdr.lines().enumerate() {
             let line = line?;
-            let (id, feat_ids) = Self::parse_features(&line, &left_feat_id_map, "bigram.left")?;
+            let (id, feat_ids) = Self::parse_features(&line, &left_feat_id_map, "bigram.left")
+                .map_err(|e| e.at("bigram.left", i + 1, None))?;
             if id != i + 1 {
-                return Err(VibratoError::invalid_format(
+                return Err(VibratoError::invalid_format_at(
                     "bigram.left",
+                    i + 1,
+                    Some(1),
                     "must be ascending order",
                 ));
             }
@@ -257,7 +276,9 @@ impl RawConnectorBuilder {
         let features_str = spl.next();
         let rest = spl.next();
         if let (Some(id_str), Some(features_str), None) = (id_str, features_str, rest) {
-            let id: usize = id_str.parse()?;
+            let id: usize = id_str
+                .parse()
+                .map_err(|e| VibratoError::invalid_format_in_column(name, 1, format!("{e}")))?;
             let features = utils::parse_csv_row(features_str);
             let mut result = vec![];
             for feature in features {
@@ -295,7 +316,9 @@ impl RawConnectorBuilder {
         let cost_str = spl.next();
         let rest = spl.next();
         if let (Some(feature_str), Some(cost_str), None) = (feature_str, cost_str, rest) {
-            let cost: i32 = cost_str.parse()?;
+            let cost: i32 = cost_str.parse().map_err(|e| {
+                VibratoError::invalid_format_in_column("bigram.cost", 2, format!("{e}"))
+            })?;
             let mut spl = feature_str.split('/');
             let right_str = spl.next();
             let left_str = spl.next();
@@ -508,4 +531,30 @@ POS-SURF:代名詞/は\t-300"
 
         assert_eq!(conn.cost(0, 0), -200);
     }
+
+    #[test]
+    fn from_readers_error_location_test() {
+        let right_rdr = "1\tSURF-SURF:これ\n".as_bytes();
+        let left_rdr = "1\tSURF-SURF:は\n".as_bytes();
+        let cost_rdr = "SURF-SURF:これ/は\t-100\nSURF-SURF:これ/は\tx\n".as_bytes();
+        let Err(VibratoError::InvalidFormat(e)) =
+            RawConnectorBuilder::from_readers(right_rdr, left_rdr, cost_rdr)
+        else {
+            panic!("must be an InvalidFormat error");
+        };
+        assert_eq!(e.arg(), "bigram.cost");
+        assert_eq!(e.line(), Some(2));
+        assert_eq!(e.column(), Some(2));
+
+        let right_rdr = "1\tSURF-SURF:これ\n3\tSURF-SURF:は\n".as_bytes();
+        let left_rdr = "1\tSURF-SURF:は\n".as_bytes();
+        let cost_rdr = "SURF-SURF:これ/は\t-100\n".as_bytes();
+        let Err(VibratoError::InvalidFormat(e)) =
+            RawConnectorBuilder::from_readers(right_rdr, left_rdr, cost_rdr)
+        else {
+            panic!("must be an InvalidFormat error");
+        };
+        assert_eq!(e.arg(), "bigram.right");
+        assert_eq!(e.line(), Some(2));
+    }
 }
@@ -1,6 +1,6 @@
 use std::collections::BTreeMap;
 
-#[cfg(target_feature = "avx2")]
+#[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::{self, __m256i};
 
 use bincode::{
@@ -16,12 +16,14 @@ use crate::utils::FromU32;
 const UNUSED_CHECK: u32 = u32::MAX;
 
 pub const SIMD_SIZE: usize = 8;
-#[cfg(not(target_feature = "avx2"))]
-#[derive(Clone, Copy)]
+
+/// Eight keys aligned for SIMD loads.
+///
+/// The layout is the same regardless of the instruction set used for scoring,
+/// so the serialized data is independent of the running CPU.
+#[derive(Clone, Copy, Default)]
+#[repr(C, align(32))]
 pub struct U31x8([U31; SIMD_SIZE]);
-#[cfg(target_feature = "avx2")]
-#[derive(Clone, Copy)]
-pub struct U31x8(__m256i);
 
 impl U31x8 {
     pub fn to_simd_vec(data: &[U31]) -> Vec<Self> {
@@ -29,44 +31,26 @@ impl U31x8 {
         for xs in data.chunks(SIMD_SIZE) {
             let mut array = [U31::default(); SIMD_SIZE];
             array[..xs.len()].copy_from_slice(xs);
-
-            #[cfg(not(target_feature = "avx2"))]
             result.push(Self(array));
-
-            // Safety
-            debug_assert_eq!(std::mem::size_of_val(array.as_slice()), 32);
-            #[cfg(target_feature = "avx2")]
-            unsafe {
-                result.push(Self(x86_64::_mm256_loadu_si256(
-                    array.as_ptr() as *const __m256i
-                )));
-            }
         }
         result
     }
-}
 
-impl Default for U31x8 {
-    #[cfg(not(target_feature = "avx2"))]
-    fn default() -> Self {
-        Self([U31::default(); SIMD_SIZE])
-    }
-
-    #[cfg(target_feature = "avx2")]
-    fn default() -> Self {
-        unsafe { Self(x86_64::_mm256_set1_epi32(0)) }
+    /// # Safety
+    ///
+    /// The CPU must support AVX2.
+    #[cfg(target_arch = "x86_64")]
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn load(&self) -> __m256i {
+        // Safety: `U31x8` is 32-byte aligned and consists of eight `u32`s.
+        x86_64::_mm256_load_si256(self.0.as_ptr() as *const __m256i)
     }
 }
 
 impl<Context> Decode<Context> for U31x8 {
     fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
-        let data: [U31; 8] = Decode::decode(decoder)?;
-
-        // Safety
-        debug_assert_eq!(std::mem::size_of_val(data.as_slice()), 32);
-        #[cfg(target_feature = "avx2")]
-        let data = unsafe { x86_64::_mm256_loadu_si256(data.as_ptr() as *const __m256i) };
-
+        let data: [U31; SIMD_SIZE] = Decode::decode(decoder)?;
         Ok(Self(data))
     }
 }
@@ -74,30 +58,30 @@ bincode::impl_borrow_decode!(U31x8);
 
 impl Encode for U31x8 {
     fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
-        #[cfg(not(target_feature = "avx2"))]
-        let data = (
-            self.0[0], self.0[1], self.0[2], self.0[3], self.0[4], self.0[5], self.0[6], self.0[7],
-        );
-
-        #[cfg(target_feature = "avx2")]
-        let data = unsafe {
-            (
-                x86_64::_mm256_extract_epi32(self.0, 0),
-                x86_64::_mm256_extract_epi32(self.0, 1),
-                x86_64::_mm256_extract_epi32(self.0, 2),
-                x86_64::_mm256_extract_epi32(self.0, 3),
-                x86_64::_mm256_extract_epi32(self.0, 4),
-                x86_64::_mm256_extract_epi32(self.0, 5),
-                x86_64::_mm256_extract_epi32(self.0, 6),
-                x86_64::_mm256_extract_epi32(self.0, 7),
-            )
-        };
-
-        Encode::encode(&data, encoder)?;
+        Encode::encode(&self.0, encoder)?;
         Ok(())
     }
 }
 
+/// Implementation of [`Scorer::accumulate_cost()`] selected at runtime.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Kernel {
+    Scalar,
+    #[cfg(target_arch = "x86_64")]
+    Avx2,
+}
+
+impl Kernel {
+    /// Detects the fastest implementation supported by the running CPU.
+    fn detect() -> Self {
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx2") {
+            return Self::Avx2;
+        }
+        Self::Scalar
+    }
+}
+
 pub struct ScorerBuilder {
     // Two-level trie mapping a pair of two keys into a cost, where
     // the first level stores the first key, and the second level stores the second key.
@@ -151,20 +135,7 @@ impl ScorerBuilder {
             }
         }
 
-        #[cfg(target_feature = "avx2")]
-        let bases_len = unsafe { x86_64::_mm256_set1_epi32(i32::try_from(bases.len()).unwrap()) };
-        #[cfg(target_feature = "avx2")]
-        let checks_len = unsafe { x86_64::_mm256_set1_epi32(i32::try_from(checks.len()).unwrap()) };
-        Scorer {
-            bases,
-            checks,
-            costs,
-
-            #[cfg(target_feature = "avx2")]
-            bases_len,
-            #[cfg(target_feature = "avx2")]
-            checks_len,
-        }
+        Scorer::new(bases, checks, costs)
     }
 }
 
@@ -172,26 +143,12 @@ pub struct Scorer {
     bases: Vec<u32>,
     checks: Vec<u32>,
     costs: Vec<i32>,
-
-    #[cfg(target_feature = "avx2")]
-    bases_len: __m256i,
-    #[cfg(target_feature = "avx2")]
-    checks_len: __m256i,
+    kernel: Kernel,
 }
 
-#[allow(clippy::derivable_impls)]
 impl Default for Scorer {
     fn default() -> Self {
-        Self {
-            bases: vec![],
-            checks: vec![],
-            costs: vec![],
-
-            #[cfg(target_feature = "avx2")]
-            bases_len: unsafe { x86_64::_mm256_set1_epi32(0) },
-            #[cfg(target_feature = "avx2")]
-            checks_len: unsafe { x86_64::_mm256_set1_epi32(0) },
-        }
+        Self::new(vec![], vec![], vec![])
     }
 }
 
@@ -208,21 +165,7 @@ impl<Context> Decode<Context> for Scorer {
             });
         }
 
-        #[cfg(target_feature = "avx2")]
-        let bases_len = unsafe { x86_64::_mm256_set1_epi32(i32::try_from(bases.len()).unwrap()) };
-        #[cfg(target_feature = "avx2")]
-        let checks_len = unsafe { x86_64::_mm256_set1_epi32(i32::try_from(checks.len()).unwrap()) };
-
-        Ok(Self {
-            bases,
-            checks,
-            costs,
-
-            #[cfg(target_feature = "avx2")]
-            bases_len,
-            #[cfg(target_feature = "avx2")]
-            checks_len,
-        })
+        Ok(Self::new(bases, checks, costs))
     }
 }
 bincode::impl_borrow_decode!(Scorer);
@@ -237,7 +180,22 @@ impl Encode for Scorer {
 }
 
 impl Scorer {
-    #[cfg(not(target_feature = "avx2"))]
+    fn new(bases: Vec<u32>, checks: Vec<u32>, costs: Vec<i32>) -> Self {
+        debug_assert_eq!(checks.len(), costs.len());
+        Self {
+            bases,
+            checks,
+            costs,
+            kernel: Kernel::detect(),
+        }
+    }
+
+    /// Returns the number of bytes of the heap memory.
+    pub fn memory_usage(&self) -> usize {
+        (self.bases.len() + self.checks.len()) * std::mem::size_of::<u32>()
+            + self.costs.len() * std::mem::size_of::<i32>()
+    }
+
     #[inline(always)]
     fn retrieve_cost(&self, key1: U31, key2: U31) -> Option<i32> {
         if let Some(base) = self.bases.get(usize::from_u32(key1.get())) {
@@ -252,9 +210,18 @@ impl Scorer {
         None
     }
 
-    #[cfg(not(target_feature = "avx2"))]
     #[inline(always)]
     pub fn accumulate_cost(&self, keys1: &[U31x8], keys2: &[U31x8]) -> i32 {
+        match self.kernel {
+            Kernel::Scalar => self.accumulate_cost_scalar(keys1, keys2),
+            // Safety: AVX2 is detected in `Kernel::detect()`.
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx2 => unsafe { self.accumulate_cost_avx2(keys1, keys2) },
+        }
+    }
+
+    #[inline(always)]
+    fn accumulate_cost_scalar(&self, keys1: &[U31x8], keys2: &[U31x8]) -> i32 {
         let mut score = 0;
         for (key1, key2) in keys1.iter().zip(keys2) {
             for (&key1, &key2) in key1.0.iter().zip(&key2.0) {
@@ -268,16 +235,20 @@ impl Scorer {
 
     /// # Safety
     ///
-    /// Arguments must satisfy the following constraints:
-    ///
-    /// * 0 <= key1
-    /// * 0 <= key2
-    /// * self.costs.len() == self.checks.len()
-    #[cfg(target_feature = "avx2")]
-    #[inline(always)]
-    pub unsafe fn retrieve_cost(&self, key1: __m256i, key2: __m256i) -> __m256i {
+    /// The CPU must support AVX2, and `self.costs.len() == self.checks.len()` must hold.
+    /// Keys are non-negative because of [`U31`].
+    #[cfg(target_arch = "x86_64")]
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn retrieve_cost_avx2(
+        &self,
+        key1: __m256i,
+        key2: __m256i,
+        bases_len: __m256i,
+        checks_len: __m256i,
+    ) -> __m256i {
         // key1 < bases.len() ?
-        let mask_valid_key1 = x86_64::_mm256_cmpgt_epi32(self.bases_len, key1);
+        let mask_valid_key1 = x86_64::_mm256_cmpgt_epi32(bases_len, key1);
         // base = bases[key1]
         let base = x86_64::_mm256_mask_i32gather_epi32(
             x86_64::_mm256_set1_epi32(0),
@@ -290,10 +261,8 @@ impl Scorer {
         // (base >= 0 && key2 >= 0 ==> pos >= 0)
         let pos = x86_64::_mm256_xor_si256(base, key2);
         // pos < checks.len() && key1 < bases.len() ?
-        let mask_valid_pos = x86_64::_mm256_and_si256(
-            x86_64::_mm256_cmpgt_epi32(self.checks_len, pos),
-            mask_valid_key1,
-        );
+        let mask_valid_pos =
+            x86_64::_mm256_and_si256(x86_64::_mm256_cmpgt_epi32(checks_len, pos), mask_valid_key1);
         // check = checks[pos]
         let check = x86_64::_mm256_mask_i32gather_epi32(
             x86_64::_mm256_set1_epi32(UNUSED_CHECK as i32),
@@ -317,31 +286,25 @@ impl Scorer {
 
     /// # Safety
     ///
-    /// Arguments must satisfy the following constraints:
-    ///
-    /// * 0 <= key1
-    /// * 0 <= key2
-    /// * self.costs.len() == self.checks.len()
-    #[cfg(target_feature = "avx2")]
-    #[inline(always)]
-    pub fn accumulate_cost(&self, keys1: &[U31x8], keys2: &[U31x8]) -> i32 {
-        unsafe {
-            let mut sums = x86_64::_mm256_set1_epi32(0);
-            for (key1, key2) in keys1.iter().zip(keys2) {
-                let key1 = key1.0;
-                let key2 = key2.0;
-
-                sums = x86_64::_mm256_add_epi32(sums, self.retrieve_cost(key1, key2));
-            }
-            x86_64::_mm256_extract_epi32(sums, 0)
-                + x86_64::_mm256_extract_epi32(sums, 1)
-                + x86_64::_mm256_extract_epi32(sums, 2)
-                + x86_64::_mm256_extract_epi32(sums, 3)
-                + x86_64::_mm256_extract_epi32(sums, 4)
-                + x86_64::_mm256_extract_epi32(sums, 5)
-                + x86_64::_mm256_extract_epi32(sums, 6)
-                + x86_64::_mm256_extract_epi32(sums, 7)
+    /// The CPU must support AVX2.
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "avx2")]
+    unsafe fn accumulate_cost_avx2(&self, keys1: &[U31x8], keys2: &[U31x8]) -> i32 {
+        let bases_len = x86_64::_mm256_set1_epi32(i32::try_from(self.bases.len()).unwrap());
+        let checks_len = x86_64::_mm256_set1_epi32(i32::try_from(self.checks.len()).unwrap());
+        let mut sums = x86_64::_mm256_set1_epi32(0);
+        for (key1, key2) in keys1.iter().zip(keys2) {
+            let cost = self.retrieve_cost_avx2(key1.load(), key2.load(), bases_len, checks_len);
+            sums = x86_64::_mm256_add_epi32(sums, cost);
         }
+        x86_64::_mm256_extract_epi32(sums, 0)
+            + x86_64::_mm256_extract_epi32(sums, 1)
+            + x86_64::_mm256_extract_epi32(sums, 2)
+            + x86_64::_mm256_extract_epi32(sums, 3)
+            + x86_64::_mm256_extract_epi32(sums, 4)
+            + x86_64::_mm256_extract_epi32(sums, 5)
+            + x86_64::_mm256_extract_epi32(sums, 6)
+            + x86_64::_mm256_extract_epi32(sums, 7)
     }
 }
 
@@ -351,7 +314,15 @@ mod tests {
 
     use crate::dictionary::connector::raw_connector::INVALID_FEATURE_ID;
 
-    #[cfg(not(target_feature = "avx2"))]
+    /// Returns the kernels supported by the running CPU.
+    fn kernels() -> Vec<Kernel> {
+        let mut kernels = vec![Kernel::Scalar];
+        if Kernel::detect() != Kernel::Scalar {
+            kernels.push(Kernel::detect());
+        }
+        kernels
+    }
+
     #[test]
     fn retrieve_cost_test() {
         let mut builder = ScorerBuilder::new();
@@ -426,60 +397,65 @@ mod tests {
         builder.insert(U31::new(1).unwrap(), U31::new(4).unwrap(), 18);
         builder.insert(U31::new(0).unwrap(), U31::new(18).unwrap(), 19);
         builder.insert(U31::new(18).unwrap(), U31::new(11).unwrap(), 20);
-        let scorer = builder.build();
-
-        assert_eq!(
-            scorer.accumulate_cost(
-                &U31x8::to_simd_vec(&[
-                    U31::new(18).unwrap(),
-                    U31::new(17).unwrap(),
-                    U31::new(0).unwrap(),
-                    INVALID_FEATURE_ID,
-                    U31::new(8).unwrap(),
-                    U31::new(12).unwrap(),
-                    U31::new(19).unwrap(),
-                    INVALID_FEATURE_ID,
-                    INVALID_FEATURE_ID,
-                    U31::new(9).unwrap(),
-                    U31::new(0).unwrap(),
-                    U31::new(7).unwrap(),
-                    U31::new(17).unwrap(),
-                    U31::new(13).unwrap(),
-                    U31::new(0).unwrap(),
-                    INVALID_FEATURE_ID
-                ]),
-                &U31x8::to_simd_vec(&[
-                    U31::new(17).unwrap(),
-                    U31::new(0).unwrap(),
-                    U31::new(0).unwrap(),
-                    INVALID_FEATURE_ID,
-                    U31::new(6).unwrap(),
-                    U31::new(18).unwrap(),
-                    U31::new(5).unwrap(),
-                    INVALID_FEATURE_ID,
-                    INVALID_FEATURE_ID,
-                    U31::new(9).unwrap(),
-                    U31::new(19).unwrap(),
-                    U31::new(9).unwrap(),
-                    U31::new(4).unwrap(),
-                    U31::new(0).unwrap(),
-                    U31::new(18).unwrap(),
-                    INVALID_FEATURE_ID
-                ]),
-            ),
-            100,
-        );
+        let mut scorer = builder.build();
+
+        for kernel in kernels() {
+            scorer.kernel = kernel;
+            assert_eq!(
+                scorer.accumulate_cost(
+                    &U31x8::to_simd_vec(&[
+                        U31::new(18).unwrap(),
+                        U31::new(17).unwrap(),
+                        U31::new(0).unwrap(),
+                        INVALID_FEATURE_ID,
+                        U31::new(8).unwrap(),
+                        U31::new(12).unwrap(),
+                        U31::new(19).unwrap(),
+                        INVALID_FEATURE_ID,
+                        INVALID_FEATURE_ID,
+                        U31::new(9).unwrap(),
+                        U31::new(0).unwrap(),
+                        U31::new(7).unwrap(),
+                        U31::new(17).unwrap(),
+                        U31::new(13).unwrap(),
+                        U31::new(0).unwrap(),
+                        INVALID_FEATURE_ID
+                    ]),
+                    &U31x8::to_simd_vec(&[
+                        U31::new(17).unwrap(),
+                        U31::new(0).unwrap(),
+                        U31::new(0).unwrap(),
+                        INVALID_FEATURE_ID,
+                        U31::new(6).unwrap(),
+                        U31::new(18).unwrap(),
+                        U31::new(5).unwrap(),
+                        INVALID_FEATURE_ID,
+                        INVALID_FEATURE_ID,
+                        U31::new(9).unwrap(),
+                        U31::new(19).unwrap(),
+                        U31::new(9).unwrap(),
+                        U31::new(4).unwrap(),
+                        U31::new(0).unwrap(),
+                        U31::new(18).unwrap(),
+                        INVALID_FEATURE_ID
+                    ]),
+                ),
+                100,
+            );
+        }
     }
 
     #[test]
     fn accumulate_cost_empty_test() {
         let builder = ScorerBuilder::new();
-        let scorer = builder.build();
+        let mut scorer = builder.build();
 
-        assert_eq!(scorer.accumulate_cost(&[], &[]), 0);
+        for kernel in kernels() {
+            scorer.kernel = kernel;
+            assert_eq!(scorer.accumulate_cost(&[], &[]), 0);
+        }
     }
 
-    #[cfg(not(target_feature = "avx2"))]
     #[test]
     fn u31x8_encode_decode_test() {
         let data = U31x8([
@@ -0,0 +1,123 @@
+use std::str::FromStr;
+
+use bincode::{Decode, Encode};
+
+use crate::errors::{Result, VibratoError};
+
+/// Width of connection costs in matrices and word costs stored in a dictionary.
+///
+/// Sources with a cost out of the range of the width are rejected.
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, Decode, Encode)]
+pub enum CostWidth {
+    /// 16-bit costs, as in MeCab.
+    #[default]
+    Bits16,
+    /// 32-bit costs for models with large weights, doubling the memory of the costs.
+    Bits32,
+}
+
+impl CostWidth {
+    /// Returns the number of bits.
+    pub const fn bits(self) -> u32 {
+        match self {
+            Self::Bits16 => 16,
+            Self::Bits32 => 32,
+        }
+    }
+
+    /// Checks if the cost fits in the width.
+    pub(crate) fn contains(self, cost: i64) -> bool {
+        match self {
+            Self::Bits16 => i16::try_from(cost).is_ok(),
+            Self::Bits32 => i32::try_from(cost).is_ok(),
+        }
+    }
+
+    /// Checks that the parsed cost fits in the width.
+    ///
+    /// # Arguments
+    ///
+    ///  - `arg`: Name of the source.
+    ///  - `line`: Line number of the cost.
+    ///  - `column`: Column number of the cost.
+    ///  - `col`: Cost string.
+    pub(crate) fn parse_cost(
+        self,
+        arg: &'static str,
+        line: usize,
+        column: usize,
+        col: &str,
+    ) -> Result<i32> {
+        let cost: i64 = col
+            .parse()
+            .map_err(|e| VibratoError::from(e).at(arg, line, Some(column)))?;
+        if !self.contains(cost) {
+            let mut msg = format!("The cost {cost} overflows {} bits.", self.bits());
+            if self == Self::Bits16 {
+                msg.push_str(" Consider compiling the dictionary with 32-bit costs.");
+            }
+            return Err(VibratoError::invalid_format_at(
+                arg,
+                line,
+                Some(column),
+                msg,
+            ));
+        }
+        Ok(i32::try_from(cost).unwrap())
+    }
+}
+
+impl FromStr for CostWidth {
+    type Err = VibratoError;
+
+    /// Parses the number of bits, `16` or `32`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "16" => Ok(Self::Bits16),
+            "32" => Ok(Self::Bits32),
+            _ => Err(VibratoError::invalid_argument(
+                "cost_width",
+                format!("Unsupported cost width: {s}. Choices are 16 and 32."),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cost() {
+        assert_eq!(
+            CostWidth::Bits16
+                .parse_cost("lex.csv", 1, 4, "-32768")
+                .unwrap(),
+            -32768
+        );
+        assert!(CostWidth::Bits16
+            .parse_cost("lex.csv", 1, 4, "32768")
+            .is_err());
+        assert_eq!(
+            CostWidth::Bits32
+                .parse_cost("lex.csv", 1, 4, "32768")
+                .unwrap(),
+            32768
+        );
+        assert!(CostWidth::Bits32
+            .parse_cost("lex.csv", 1, 4, "2147483648")
+            .is_err());
+    }
+
+    #[test]
+    fn test_overflow_location() {
+        let Err(VibratoError::InvalidFormat(e)) =
+            CostWidth::Bits16.parse_cost("matrix.def", 3, 3, "40000")
+        else {
+            panic!("must be an InvalidFormat error");
+        };
+        assert_eq!(e.line(), Some(3));
+        assert_eq!(e.column(), Some(3));
+        assert!(e.msg().contains("overflows 16 bits"));
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt;
+use std::io::Read;
+use std::str::FromStr;
+
+use encoding_rs::DecoderResult;
+
+use crate::errors::{Result, VibratoError};
+
+/// Character encoding of dictionary sources.
+///
+/// Sources in an encoding other than UTF-8 are transcoded into UTF-8 on read.
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash)]
+pub enum SourceEncoding {
+    /// UTF-8.
+    #[default]
+    Utf8,
+    /// EUC-JP, used in IPADIC and many legacy MeCab dictionaries.
+    EucJp,
+    /// Shift_JIS.
+    ShiftJis,
+}
+
+impl SourceEncoding {
+    /// Reads all bytes from `rdr` and transcodes them into UTF-8.
+    ///
+    /// # Errors
+    ///
+    /// [`VibratoError`] is returned when the reading fails or when an invalid byte sequence
+    /// is found. The error has the line number of the sequence.
+    pub(crate) fn read_to_utf8<R>(self, mut rdr: R, name: &'static str) -> Result<Vec<u8>>
+    where
+        R: Read,
+    {
+        let mut bytes = vec![];
+        rdr.read_to_end(&mut bytes)?;
+        self.decode(bytes, name)
+    }
+
+    fn decode(self, bytes: Vec<u8>, name: &'static str) -> Result<Vec<u8>> {
+        let encoding = match self {
+            Self::Utf8 => {
+                return match std::str::from_utf8(&bytes) {
+                    Ok(_) => Ok(bytes),
+                    Err(e) => Err(self.invalid_sequence(&bytes, e.valid_up_to(), name)),
+                };
+            }
+            Self::EucJp => encoding_rs::EUC_JP,
+            Self::ShiftJis => encoding_rs::SHIFT_JIS,
+        };
+        let mut decoder = encoding.new_decoder_without_bom_handling();
+        let mut decoded = String::with_capacity(
+            decoder
+                .max_utf8_buffer_length_without_replacement(bytes.len())
+                .unwrap_or(bytes.len()),
+        );
+        let (result, nread) =
+            decoder.decode_to_string_without_replacement(&bytes, &mut decoded, true);
+        match result {
+            DecoderResult::InputEmpty => Ok(decoded.into_bytes()),
+            DecoderResult::Malformed(bad_len, extra_len) => {
+                let pos = nread - usize::from(bad_len) - usize::from(extra_len);
+                Err(self.invalid_sequence(&bytes, pos, name))
+            }
+            // The buffer has enough capacity.
+            DecoderResult::OutputFull => unreachable!(),
+        }
+    }
+
+    fn invalid_sequence(self, bytes: &[u8], pos: usize, name: &'static str) -> VibratoError {
+        // b'\n' never appears inside multi-byte characters in the supported encodings.
+        let line = bytes[..pos].iter().filter(|&&b| b == b'\n').count() + 1;
+        let msg = format!("Invalid byte sequence in {self} at byte offset {pos}");
+        VibratoError::invalid_format_at(name, line, None, msg)
+    }
+}
+
+impl fmt::Display for SourceEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Utf8 => "UTF-8",
+            Self::EucJp => "EUC-JP",
+            Self::ShiftJis => "Shift_JIS",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for SourceEncoding {
+    type Err = VibratoError;
+
+    /// Parses an encoding name such as `utf-8`, `euc-jp`, or `shift_jis` (case-insensitive).
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Self::Utf8),
+            "euc-jp" | "eucjp" => Ok(Self::EucJp),
+            "shift_jis" | "shift-jis" | "sjis" => Ok(Self::ShiftJis),
+            _ => Err(VibratoError::invalid_argument(
+                "encoding",
+                format!("Unsupported encoding: {s}. Choices are utf-8, euc-jp, and shift_jis."),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utf8() {
+        let data = "自然,0,0,1,名詞".as_bytes();
+        let result = SourceEncoding::Utf8.read_to_utf8(data, "lex.csv").unwrap();
+        assert_eq!(result, data);
+    }
+
+    #[test]
+    fn test_euc_jp() {
+        // "自然,0,0,1,名詞" in EUC-JP
+        let data = b"\xbc\xab\xc1\xb3,0,0,1,\xcc\xbe\xbb\xec";
+        let result = SourceEncoding::EucJp
+            .read_to_utf8(&data[..], "lex.csv")
+            .unwrap();
+        assert_eq!(result, "自然,0,0,1,名詞".as_bytes());
+    }
+
+    #[test]
+    fn test_shift_jis() {
+        // "自然,0,0,1,名詞" in Shift_JIS
+        let data = b"\x8e\xa9\x91R,0,0,1,\x96\xbc\x8e\x8c";
+        let result = SourceEncoding::ShiftJis
+            .read_to_utf8(&data[..], "lex.csv")
+            .unwrap();
+        assert_eq!(result, "自然,0,0,1,名詞".as_bytes());
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(
+            "UTF-8".parse::<SourceEncoding>().unwrap(),
+            SourceEncoding::Utf8
+        );
+        assert_eq!(
+            "euc-jp".parse::<SourceEncoding>().unwrap(),
+            SourceEncoding::EucJp
+        );
+        assert_eq!(
+            "sjis".parse::<SourceEncoding>().unwrap(),
+            SourceEncoding::ShiftJis
+        );
+        assert!("latin1".parse::<SourceEncoding>().is_err());
+    }
+
+    #[test]
+    fn test_invalid_sequence() {
+        let data = b"a,0,0,1,b\nc,0,0,1,\xff\xff";
+        for encoding in [
+            SourceEncoding::Utf8,
+            SourceEncoding::EucJp,
+            SourceEncoding::ShiftJis,
+        ] {
+            let Err(VibratoError::InvalidFormat(e)) = encoding.read_to_utf8(&data[..], "lex.csv")
+            else {
+                panic!("must be an InvalidFormat error");
+            };
+            assert_eq!(e.arg(), "lex.csv");
+            assert_eq!(e.line(), Some(2));
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use bincode::{Decode, Encode};
+
+use crate::utils;
+
+const HALFWIDTH_KATAKANA: &str = "ヲァィゥェォャュョッーアイウエオカキクケコサシスセソタチツテトナニヌネノハヒフヘホマミムメモヤユヨラリルレロワン";
+const HALFWIDTH_PUNCTUATIONS: &str = "。「」、・";
+const VOICEABLE_KATAKANA: &str = "カキクケコサシスセソタチツテトハヒフヘホ";
+const SEMI_VOICEABLE_KATAKANA: &str = "ハヒフヘホ";
+
+/// Kinds of character folding used for dictionary matching.
+///
+/// Surfaces in the lexicons and input sentences are folded in the same manner,
+/// so that words in different forms can be matched.
+#[derive(Clone, Copy, Default, Debug, Eq, PartialEq, Hash, Decode, Encode)]
+pub struct Folding {
+    kana: bool,
+    width: bool,
+}
+
+impl Folding {
+    /// Creates a new instance that folds nothing.
+    pub const fn new() -> Self {
+        Self {
+            kana: false,
+            width: false,
+        }
+    }
+
+    /// Folds hiragana into katakana.
+    pub const fn kana(mut self, yes: bool) -> Self {
+        self.kana = yes;
+        self
+    }
+
+    /// Folds fullwidth ASCII into halfwidth ASCII, and halfwidth katakana into fullwidth
+    /// katakana. A halfwidth katakana followed by a voiced sound mark is composed.
+    pub const fn width(mut self, yes: bool) -> Self {
+        self.width = yes;
+        self
+    }
+
+    /// Checks if the instance folds nothing.
+    pub const fn is_empty(&self) -> bool {
+        !self.kana && !self.width
+    }
+
+    /// Checks if the instance folds everything that `other` folds.
+    pub const fn contains(&self, other: Self) -> bool {
+        (self.kana || !other.kana) && (self.width || !other.width)
+    }
+
+    /// Folds the given characters.
+    ///
+    /// # Arguments
+    ///
+    ///  - `input`: Characters to be folded.
+    ///  - `output`: Folded characters.
+    ///  - `positions`: Positions in `input` indexed by positions in `output`,
+    ///    including the end position.
+    pub(crate) fn fold(&self, input: &[char], output: &mut Vec<char>, positions: &mut Vec<usize>) {
+        output.clear();
+        positions.clear();
+        for (i, &c) in input.iter().enumerate() {
+            if self.width {
+                if let Some(composed) = output.last().and_then(|&p| compose_voiced(p, c)) {
+                    *output.last_mut().unwrap() = composed;
+                    continue;
+                }
+            }
+            let c = if self.width { fold_width(c) } else { c };
+            let c = if self.kana {
+                utils::hiragana_to_katakana(c)
+            } else {
+                c
+            };
+            output.push(c);
+            positions.push(i);
+        }
+        positions.push(input.len());
+    }
+
+    /// Checks if the given characters are the same after folding.
+    pub(crate) fn eq_folded(&self, lhs: &str, rhs: &[char]) -> bool {
+        self.fold_iter(lhs.chars())
+            .eq(self.fold_iter(rhs.iter().copied()))
+    }
+
+    /// Same as [`Self::fold()`] but folds characters lazily without positions.
+    fn fold_iter<I>(self, input: I) -> impl Iterator<Item = char>
+    where
+        I: Iterator<Item = char>,
+    {
+        let mut input = input.peekable();
+        std::iter::from_fn(move || {
+            let c = input.next()?;
+            let c = if self.width { fold_width(c) } else { c };
+            let mut c = if self.kana {
+                utils::hiragana_to_katakana(c)
+            } else {
+                c
+            };
+            if self.width {
+                while let Some(composed) = input.peek().and_then(|&mark| compose_voiced(c, mark)) {
+                    c = composed;
+                    input.next();
+                }
+            }
+            Some(c)
+        })
+    }
+
+    /// Folds the given string.
+    pub(crate) fn fold_str(&self, input: &str) -> String {
+        let input: Vec<_> = input.chars().collect();
+        let mut output = vec![];
+        let mut positions = vec![];
+        self.fold(&input, &mut output, &mut positions);
+        output.into_iter().collect()
+    }
+}
+
+fn fold_width(c: char) -> char {
+    match c {
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(u32::from(c) - 0xFEE0).unwrap(),
+        '\u{3000}' => ' ',
+        '\u{FF61}'..='\u{FF65}' => nth_char(HALFWIDTH_PUNCTUATIONS, u32::from(c) - 0xFF61),
+        '\u{FF66}'..='\u{FF9D}' => nth_char(HALFWIDTH_KATAKANA, u32::from(c) - 0xFF66),
+        '\u{FF9E}' => '\u{309B}',
+        '\u{FF9F}' => '\u{309C}',
+        _ => c,
+    }
+}
+
+/// Composes a fullwidth katakana `prev` with a halfwidth (semi-)voiced sound mark `mark`.
+fn compose_voiced(prev: char, mark: char) -> Option<char> {
+    match mark {
+        '\u{FF9E}' if prev == 'ウ' => Some('ヴ'),
+        '\u{FF9E}' if VOICEABLE_KATAKANA.contains(prev) => char::from_u32(u32::from(prev) + 1),
+        '\u{FF9F}' if SEMI_VOICEABLE_KATAKANA.contains(prev) => char::from_u32(u32::from(prev) + 2),
+        _ => None,
+    }
+}
+
+fn nth_char(s: &str, n: u32) -> char {
+    s.chars().nth(usize::try_from(n).unwrap()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fold_kana() {
+        let folding = Folding::new().kana(true);
+        assert_eq!(
+            folding.fold_str("とうきょうトウキョウｶﾞ"),
+            "トウキョウトウキョウｶﾞ"
+        );
+    }
+
+    #[test]
+    fn test_fold_width() {
+        let folding = Folding::new().width(true);
+        assert_eq!(
+            folding.fold_str("ｶﾞﾊﾟｳﾞｱﾞｰ｡ＡＢＣ　がぱ"),
+            "ガパヴア゛ー。ABC がぱ"
+        );
+    }
+
+    #[test]
+    fn test_fold_positions() {
+        let folding = Folding::new().kana(true).width(true);
+        let input: Vec<_> = "ｶﾞっこう".chars().collect();
+        let mut output = vec![];
+        let mut positions = vec![];
+        folding.fold(&input, &mut output, &mut positions);
+        assert_eq!(output, "ガッコウ".chars().collect::<Vec<_>>());
+        assert_eq!(positions, vec![0, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_eq_folded() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        let folding = Folding::new().kana(true).width(true);
+        assert!(folding.eq_folded("ガッコウ", &chars("ｶﾞっこう")));
+        assert!(folding.eq_folded("ア゛ABC", &chars("ｱﾞＡＢＣ")));
+        assert!(!folding.eq_folded("ガッコウ", &chars("ｶっこう")));
+
+        let folding = Folding::new().kana(true);
+        assert!(folding.eq_folded("ガッコウ", &chars("がっこう")));
+        assert!(!folding.eq_folded("ガッコウ", &chars("ｶﾞｯｺｳ")));
+    }
+
+    #[test]
+    fn test_contains() {
+        let both = Folding::new().kana(true).width(true);
+        let kana = Folding::new().kana(true);
+        assert!(both.contains(kana));
+        assert!(both.contains(Folding::new()));
+        assert!(!kana.contains(both));
+        assert!(!kana.contains(Folding::new().width(true)));
+    }
+}
@@ -1,6 +1,7 @@
 mod feature;
 mod map;
 mod param;
+mod typo;
 
 use std::io::Read;
 
@@ -8,12 +9,14 @@ use bincode::{Decode, Encode};
 use csv_core::ReadFieldResult;
 
 use crate::dictionary::connector::Connector;
+use crate::dictionary::folding::Folding;
 use crate::dictionary::lexicon::feature::WordFeatures;
 use crate::dictionary::lexicon::map::WordMap;
 use crate::dictionary::lexicon::param::WordParams;
+use crate::dictionary::lexicon::typo::TypoMap;
 use crate::dictionary::mapper::ConnIdMapper;
 use crate::dictionary::word_idx::WordIdx;
-use crate::dictionary::LexType;
+use crate::dictionary::{CostWidth, LexType, MemoryUsage};
 use crate::errors::{Result, VibratoError};
 use crate::utils::FromU32;
 
@@ -26,6 +29,12 @@ pub struct Lexicon {
     params: WordParams,
     features: WordFeatures,
     lex_type: LexType,
+    folding: Folding,
+    // Map from folded surfaces, which is built only if `folding` is not empty.
+    folded_map: Option<WordMap>,
+    typo_map: Option<TypoMap>,
+    // Surfaces, which are stored only with a folded map or a typo map.
+    surfaces: Option<WordFeatures>,
 }
 
 impl Lexicon {
@@ -45,6 +54,67 @@ impl Lexicon {
             })
     }
 
+    /// Iterates words whose folded surfaces are prefixes of the folded input.
+    ///
+    /// `end_char` of the matches is a position in the folded input.
+    /// Nothing is returned if the lexicon has no folded map.
+    #[inline(always)]
+    pub fn folded_common_prefix_iterator<'a>(
+        &'a self,
+        input: &'a [char],
+    ) -> impl Iterator<Item = LexMatch> + 'a {
+        self.folded_map
+            .iter()
+            .flat_map(move |map| map.common_prefix_iterator(input))
+            .map(move |(word_id, end_char)| {
+                LexMatch::new(
+                    WordIdx::new(self.lex_type, word_id),
+                    self.params.get(usize::from_u32(word_id)),
+                    end_char,
+                )
+            })
+    }
+
+    /// Calls `f` with each word whose surface is within `max_distance` (no more than one)
+    /// from a prefix of the input, and the edit distance.
+    ///
+    /// Nothing is reported if the lexicon has no typo map.
+    #[inline(always)]
+    pub fn for_each_typo_candidate<F>(&self, input: &[char], max_distance: usize, mut f: F)
+    where
+        F: FnMut(LexMatch, usize),
+    {
+        if let (Some(typo_map), Some(surfaces)) = (self.typo_map.as_ref(), self.surfaces.as_ref()) {
+            typo_map.for_each_candidate(
+                surfaces,
+                input,
+                max_distance,
+                |word_id, end_char, dist| {
+                    f(
+                        LexMatch::new(
+                            WordIdx::new(self.lex_type, word_id),
+                            self.params.get(usize::from_u32(word_id)),
+                            end_char,
+                        ),
+                        dist,
+                    );
+                },
+            );
+        }
+    }
+
+    /// Checks if the lexicon has a typo map.
+    #[inline(always)]
+    pub const fn has_typo_map(&self) -> bool {
+        self.typo_map.is_some()
+    }
+
+    /// Gets the folding applied to the surfaces.
+    #[inline(always)]
+    pub const fn folding(&self) -> Folding {
+        self.folding
+    }
+
     /// Do NOT make this function public to maintain consistency in
     /// the connection-id mapping among members of `Dictionary`.
     /// The consistency is managed in `Dictionary`.
@@ -52,18 +122,63 @@ impl Lexicon {
         self.params.map_connection_ids(mapper);
     }
 
+    /// Rebuilds the feature strings from the given columns.
+    pub fn select_feature_columns(&mut self, columns: &[usize]) {
+        self.features.select_columns(columns);
+    }
+
     #[inline(always)]
     pub fn word_param(&self, word_idx: WordIdx) -> WordParam {
         debug_assert_eq!(word_idx.lex_type, self.lex_type);
         self.params.get(usize::from_u32(word_idx.word_id))
     }
 
+    /// Gets the surface, which is stored only with a folded map or a typo map.
+    #[inline(always)]
+    pub fn word_surface(&self, word_idx: WordIdx) -> Option<&str> {
+        debug_assert_eq!(word_idx.lex_type, self.lex_type);
+        self.surfaces
+            .as_ref()
+            .map(|surfaces| surfaces.get(usize::from_u32(word_idx.word_id)))
+    }
+
     #[inline(always)]
     pub fn word_feature(&self, word_idx: WordIdx) -> &str {
         debug_assert_eq!(word_idx.lex_type, self.lex_type);
         self.features.get(usize::from_u32(word_idx.word_id))
     }
 
+    /// Returns the number of entries.
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Gets the width of the word costs.
+    #[inline(always)]
+    pub const fn cost_width(&self) -> CostWidth {
+        self.params.width()
+    }
+
+    /// Adds the number of bytes of the heap memory to `usage`.
+    pub fn add_memory_usage(&self, usage: &mut MemoryUsage) {
+        usage.trie += self.map.trie_memory_usage();
+        usage.postings += self.map.postings_memory_usage();
+        if let Some(map) = self.folded_map.as_ref() {
+            usage.trie += map.trie_memory_usage();
+            usage.postings += map.postings_memory_usage();
+        }
+        if let Some(typo_map) = self.typo_map.as_ref() {
+            usage.trie += typo_map.trie_memory_usage();
+            usage.postings += typo_map.postings_memory_usage();
+        }
+        if let Some(surfaces) = self.surfaces.as_ref() {
+            usage.postings += surfaces.memory_usage();
+        }
+        usage.params += self.params.memory_usage();
+        usage.features += self.features.memory_usage();
+    }
+
     /// Checks if left/right-ids are valid with connector.
     pub fn verify<C>(&self, conn: &C) -> bool
     where
@@ -82,38 +197,111 @@ impl Lexicon {
     }
 
     /// Builds a new instance from a list of entries.
-    pub fn from_entries(entries: &[RawWordEntry], lex_type: LexType) -> Result<Self> {
+    ///
+    /// If `folding` is not empty, the surfaces folded with it are additionally indexed.
+    /// If `typo` is `true`, a typo map is additionally built.
+    /// The surfaces are stored in either case.
+    /// The word costs are stored in `width`.
+    pub fn from_entries(
+        entries: &[RawWordEntry],
+        lex_type: LexType,
+        folding: Folding,
+        typo: bool,
+        width: CostWidth,
+    ) -> Result<Self> {
         let map = WordMap::new(entries.iter().map(|e| &e.surface))?;
-        let params = WordParams::new(entries.iter().map(|e| e.param));
+        let params = WordParams::new(entries.iter().map(|e| e.param), width)?;
         let features = WordFeatures::new(entries.iter().map(|e| &e.feature));
+        let folded_map = if folding.is_empty() {
+            None
+        } else {
+            Some(WordMap::new(
+                entries.iter().map(|e| folding.fold_str(&e.surface)),
+            )?)
+        };
+
+        let typo_map = if typo {
+            Some(TypoMap::new(entries.iter().map(|e| &e.surface))?)
+        } else {
+            None
+        };
+        let surfaces = (folded_map.is_some() || typo_map.is_some())
+            .then(|| WordFeatures::new(entries.iter().map(|e| &e.surface)));
 
         Ok(Self {
             map,
             params,
             features,
             lex_type,
+            folding,
+            folded_map,
+            typo_map,
+            surfaces,
         })
     }
 
     /// Builds a new instance from a lexicon file in the CSV format.
-    pub fn from_reader<R>(mut rdr: R, lex_type: LexType) -> Result<Self>
+    ///
+    /// The arguments `folding`, `typo`, and `width` are the same as those of
+    /// [`Self::from_entries()`].
+    /// Entries with empty surfaces are skipped.
+    pub fn from_reader<R>(
+        mut rdr: R,
+        lex_type: LexType,
+        folding: Folding,
+        typo: bool,
+        width: CostWidth,
+    ) -> Result<Self>
     where
         R: Read,
     {
         let mut buf = vec![];
         rdr.read_to_end(&mut buf)?;
 
-        let entries = Self::parse_csv(&buf, "lex.csv")?;
+        let (entries, _) = Self::parse_csv(&buf, "lex.csv", width)?;
 
-        Self::from_entries(&entries, lex_type)
+        Self::from_entries(&entries, lex_type, folding, typo, width)
     }
 
+    /// Parses a lexicon file in the CSV format.
+    ///
+    /// Errors are reported with the line and column at which they occur,
+    /// including word costs overflowing `width`.
+    /// Entries with empty surfaces are skipped, and their line numbers are returned
+    /// along with the parsed entries.
     pub(crate) fn parse_csv<'a>(
-        mut bytes: &'a [u8],
+        bytes: &'a [u8],
         name: &'static str,
-    ) -> Result<Vec<RawWordEntry<'a>>> {
+        width: CostWidth,
+    ) -> Result<(Vec<RawWordEntry<'a>>, Vec<usize>)> {
         let mut entries = vec![];
+        let mut skipped = vec![];
+        Self::parse_csv_records(bytes, name, width, |entry| {
+            let entry = entry?;
+            if entry.surface.is_empty() {
+                skipped.push(entry.line);
+            } else {
+                entries.push(entry);
+            }
+            Ok(())
+        })?;
+        Ok((entries, skipped))
+    }
 
+    /// Parses a lexicon file in the CSV format, passing each record to `f`.
+    ///
+    /// A record that cannot be parsed is passed as an error with its location,
+    /// and the parsing continues with the next record until `f` returns an error.
+    /// Entries with empty surfaces are also passed.
+    pub(crate) fn parse_csv_records<'a, F>(
+        mut bytes: &'a [u8],
+        name: &'static str,
+        width: CostWidth,
+        mut f: F,
+    ) -> Result<()>
+    where
+        F: FnMut(Result<RawWordEntry<'a>>) -> Result<()>,
+    {
         let mut rdr = csv_core::Reader::new();
         let mut features_bytes = bytes;
         let mut record_bytes = bytes;
@@ -126,9 +314,20 @@ impl Lexicon {
         let mut left_id = 0;
         let mut right_id = 0;
         let mut word_cost = 0;
+        let mut line = 1;
+        // The first error in the current record.
+        let mut error = None;
 
         loop {
             let (result, nin, nout) = rdr.read_field(bytes, &mut output);
+            // The line of the field, excluding the terminating newline.
+            let field_line = usize::try_from(rdr.line()).unwrap()
+                - usize::from(nin != 0 && bytes[nin - 1] == b'\n');
+            if field_cnt == 0 {
+                // The surface can contain newlines if quoted.
+                line = field_line - output[..nout].iter().filter(|&&b| b == b'\n').count();
+            }
+            let column = Some(field_cnt + 1);
             let record_end = match result {
                 ReadFieldResult::InputEmpty => {
                     features_len += nin + 1;
@@ -136,28 +335,50 @@ impl Lexicon {
                     true
                 }
                 ReadFieldResult::OutputFull => {
-                    return Err(VibratoError::invalid_format(name, "Field too large"))
+                    return Err(VibratoError::invalid_format_at(
+                        name,
+                        field_line,
+                        column,
+                        "Field too large",
+                    ))
                 }
                 ReadFieldResult::Field { record_end } => {
-                    match field_cnt {
+                    let parse_field = || {
+                        std::str::from_utf8(&output[..nout])
+                            .map_err(|e| VibratoError::from(e).at(name, line, column))
+                    };
+                    let parsed = match field_cnt {
                         0 => {
-                            surface = std::str::from_utf8(&output[..nout])?.to_string();
                             record_bytes = bytes;
+                            parse_field().map(|field| surface = field.to_string())
                         }
-                        1 => {
-                            left_id = std::str::from_utf8(&output[..nout])?.parse()?;
-                        }
-                        2 => {
-                            right_id = std::str::from_utf8(&output[..nout])?.parse()?;
-                        }
+                        1 => parse_field().and_then(|field| {
+                            left_id = field
+                                .parse()
+                                .map_err(|e| VibratoError::from(e).at(name, line, column))?;
+                            Ok(())
+                        }),
+                        2 => parse_field().and_then(|field| {
+                            right_id = field
+                                .parse()
+                                .map_err(|e| VibratoError::from(e).at(name, line, column))?;
+                            Ok(())
+                        }),
                         3 => {
-                            word_cost = std::str::from_utf8(&output[..nout])?.parse()?;
                             features_bytes = &bytes[nin..];
                             features_len = 0;
+                            parse_field().and_then(|field| {
+                                word_cost = width.parse_cost(name, line, 4, field)?;
+                                Ok(())
+                            })
                         }
                         _ => {
                             features_len += nin;
+                            Ok(())
                         }
+                    };
+                    if let Err(e) = parsed {
+                        error.get_or_insert(e);
                     }
                     record_end_pos += nin;
                     record_end
@@ -168,27 +389,26 @@ impl Lexicon {
                 if field_cnt == 0 && nin == 0 {
                     continue;
                 }
-                if field_cnt <= 3 {
-                    let msg = format!(
-                        "A csv row of lexicon must have five items at least, {:?}",
-                        std::str::from_utf8(&record_bytes[..record_end_pos])?,
-                    );
-                    return Err(VibratoError::invalid_format(name, msg));
-                }
-                let feature = std::str::from_utf8(&features_bytes[..features_len - 1])?;
-                if surface.is_empty() {
-                    eprintln!(
-                        "Skipped an empty surface, {:?}",
-                        std::str::from_utf8(&record_bytes[..record_end_pos])?,
-                    );
-                } else {
-                    entries.push(RawWordEntry {
-                        surface,
-                        param: WordParam::new(left_id, right_id, word_cost),
-                        feature,
-                    });
-                }
-                surface = String::new();
+                let entry = match error.take() {
+                    Some(e) => Err(e),
+                    None if field_cnt <= 3 => {
+                        let msg = format!(
+                            "A csv row of lexicon must have five items at least, {:?}",
+                            String::from_utf8_lossy(&record_bytes[..record_end_pos]),
+                        );
+                        Err(VibratoError::invalid_format_at(name, line, None, msg))
+                    }
+                    None => std::str::from_utf8(&features_bytes[..features_len - 1])
+                        .map_err(|e| VibratoError::from(e).at(name, line, Some(5)))
+                        .map(|feature| RawWordEntry {
+                            surface: std::mem::take(&mut surface),
+                            param: WordParam::new(left_id, right_id, word_cost),
+                            feature,
+                            line,
+                        }),
+                };
+                f(entry)?;
+                surface.clear();
                 field_cnt = 0;
                 record_end_pos = 0;
             } else {
@@ -196,7 +416,7 @@ impl Lexicon {
             }
             bytes = &bytes[nin..];
         }
-        Ok(entries)
+        Ok(())
     }
 }
 
@@ -223,6 +443,8 @@ pub struct RawWordEntry<'a> {
     pub surface: String,
     pub param: WordParam,
     pub feature: &'a str,
+    /// Line number starting from 1 at which the entry is defined.
+    pub line: usize,
 }
 
 #[cfg(test)]
@@ -233,14 +455,22 @@ mod tests {
     fn test_common_prefix_iterator() {
         let lexicon = Lexicon {
             map: WordMap::new(["東京", "東京都", "東京", "京都"]).unwrap(),
-            params: WordParams::new([
-                WordParam::new(1, 2, 3),
-                WordParam::new(4, 5, 6),
-                WordParam::new(7, 8, 9),
-                WordParam::new(10, 11, 12),
-            ]),
+            params: WordParams::new(
+                [
+                    WordParam::new(1, 2, 3),
+                    WordParam::new(4, 5, 6),
+                    WordParam::new(7, 8, 9),
+                    WordParam::new(10, 11, 12),
+                ],
+                CostWidth::Bits16,
+            )
+            .unwrap(),
             features: WordFeatures::default(),
             lex_type: LexType::System,
+            folding: Folding::default(),
+            folded_map: None,
+            typo_map: None,
+            surfaces: None,
         };
         let input: Vec<_> = "東京都".chars().collect();
         let mut it = lexicon.common_prefix_iterator(&input);
@@ -271,10 +501,39 @@ mod tests {
         assert_eq!(it.next(), None);
     }
 
+    #[test]
+    fn test_folded_common_prefix_iterator() {
+        let data = "トウキョウ,0,0,1,a\nとう,0,0,2,b\nｶﾞ,0,0,3,c";
+        let lexicon = Lexicon::from_reader(
+            data.as_bytes(),
+            LexType::System,
+            Folding::new().kana(true).width(true),
+            false,
+            CostWidth::Bits16,
+        )
+        .unwrap();
+        let input: Vec<_> = "トウキョウ".chars().collect();
+        let ids: Vec<_> = lexicon
+            .folded_common_prefix_iterator(&input)
+            .map(|m| (m.word_idx.word_id, m.end_char))
+            .collect();
+        assert_eq!(ids, vec![(1, 2), (0, 5)]);
+        let input: Vec<_> = "ガ".chars().collect();
+        assert_eq!(lexicon.folded_common_prefix_iterator(&input).count(), 1);
+        assert_eq!(lexicon.common_prefix_iterator(&input).count(), 0);
+    }
+
     #[test]
     fn test_from_reader_system() {
         let data = "自然,0,2,1,sizen\n言語,1,0,-4,gengo,げんご";
-        let lex = Lexicon::from_reader(data.as_bytes(), LexType::System).unwrap();
+        let lex = Lexicon::from_reader(
+            data.as_bytes(),
+            LexType::System,
+            Folding::default(),
+            false,
+            CostWidth::Bits16,
+        )
+        .unwrap();
         assert_eq!(lex.params.get(0), WordParam::new(0, 2, 1));
         assert_eq!(lex.params.get(1), WordParam::new(1, 0, -4));
         assert_eq!(lex.features.get(0), "sizen");
@@ -285,7 +544,14 @@ mod tests {
     #[test]
     fn test_from_reader_user() {
         let data = "自然,0,2,1,sizen\n言語,1,0,-4,gengo,げんご";
-        let lex = Lexicon::from_reader(data.as_bytes(), LexType::User).unwrap();
+        let lex = Lexicon::from_reader(
+            data.as_bytes(),
+            LexType::User,
+            Folding::default(),
+            false,
+            CostWidth::Bits16,
+        )
+        .unwrap();
         assert_eq!(lex.params.get(0), WordParam::new(0, 2, 1));
         assert_eq!(lex.params.get(1), WordParam::new(1, 0, -4));
         assert_eq!(lex.features.get(0), "sizen");
@@ -296,35 +562,140 @@ mod tests {
     #[test]
     fn test_parse_csv_empty_surface() {
         let data = "自然,0,2,1,sizen\n,1,0,-4,gengo,げんご";
-        let result = Lexicon::parse_csv(data.as_bytes(), "test").unwrap();
+        let (result, skipped) =
+            Lexicon::parse_csv(data.as_bytes(), "test", CostWidth::Bits16).unwrap();
         assert_eq!(result.len(), 1);
+        assert_eq!(skipped, [2]);
     }
 
     #[test]
     fn test_from_reader_few_cols() {
         let data = "自然,0,2";
-        let result = Lexicon::from_reader(data.as_bytes(), LexType::System);
+        let result = Lexicon::from_reader(
+            data.as_bytes(),
+            LexType::System,
+            Folding::default(),
+            false,
+            CostWidth::Bits16,
+        );
         assert!(result.is_err());
     }
 
     #[test]
     fn test_from_reader_invalid_left_id() {
         let data = "自然,-2,2,1,a";
-        let result = Lexicon::from_reader(data.as_bytes(), LexType::System);
+        let result = Lexicon::from_reader(
+            data.as_bytes(),
+            LexType::System,
+            Folding::default(),
+            false,
+            CostWidth::Bits16,
+        );
         assert!(result.is_err());
     }
 
     #[test]
     fn test_from_reader_invalid_right_id() {
         let data = "自然,2,-2,1,a";
-        let result = Lexicon::from_reader(data.as_bytes(), LexType::System);
+        let result = Lexicon::from_reader(
+            data.as_bytes(),
+            LexType::System,
+            Folding::default(),
+            false,
+            CostWidth::Bits16,
+        );
         assert!(result.is_err());
     }
 
     #[test]
     fn test_from_reader_invalid_cost() {
         let data = "自然,2,1,コスト,a";
-        let result = Lexicon::from_reader(data.as_bytes(), LexType::System);
+        let result = Lexicon::from_reader(
+            data.as_bytes(),
+            LexType::System,
+            Folding::default(),
+            false,
+            CostWidth::Bits16,
+        );
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_parse_csv_error_location() {
+        let data = "自然,0,2,1,a\n\n\"言,語\",1,x,0,b";
+        let Err(VibratoError::InvalidFormat(e)) =
+            Lexicon::parse_csv(data.as_bytes(), "lex.csv", CostWidth::Bits16)
+        else {
+            panic!("must be an InvalidFormat error");
+        };
+        assert_eq!(e.line(), Some(3));
+        assert_eq!(e.column(), Some(3));
+        assert!(e
+            .to_string()
+            .starts_with("InvalidFormatError: lex.csv:3:3: "));
+    }
+
+    #[test]
+    fn test_parse_csv_few_cols_location() {
+        let data = "自然,0,2,1,a\r\n言語,1,2\r\n";
+        let Err(VibratoError::InvalidFormat(e)) =
+            Lexicon::parse_csv(data.as_bytes(), "lex.csv", CostWidth::Bits16)
+        else {
+            panic!("must be an InvalidFormat error");
+        };
+        assert_eq!(e.line(), Some(2));
+        assert_eq!(e.column(), None);
+    }
+
+    #[test]
+    fn test_parse_csv_records() {
+        let data = "自然,0,2,1,a\n言語,x,0,-4,b\n\"処\n理\",1,0,-4,c\n";
+        let mut lines = vec![];
+        Lexicon::parse_csv_records(data.as_bytes(), "lex.csv", CostWidth::Bits16, |entry| {
+            lines.push(entry.map(|e| (e.line, e.surface)).map_err(|_| ()));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            lines,
+            [
+                Ok((1, "自然".to_string())),
+                Err(()),
+                Ok((3, "処\n理".to_string()))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_csv_line() {
+        let data = "自然,0,2,1,a\n\n言語,1,0,-4,b\n";
+        let (result, _) =
+            Lexicon::parse_csv(data.as_bytes(), "lex.csv", CostWidth::Bits16).unwrap();
+        assert_eq!(result[0].line, 1);
+        assert_eq!(result[1].line, 3);
+    }
+
+    #[test]
+    fn test_parse_csv_cost_width() {
+        let data = "自然,0,2,1,a\n言語,1,0,-40000,b\n";
+        let Err(VibratoError::InvalidFormat(e)) =
+            Lexicon::parse_csv(data.as_bytes(), "lex.csv", CostWidth::Bits16)
+        else {
+            panic!("must be an InvalidFormat error");
+        };
+        assert_eq!(e.line(), Some(2));
+        assert_eq!(e.column(), Some(4));
+        assert!(e.msg().contains("overflows 16 bits"));
+
+        let lex = Lexicon::from_reader(
+            data.as_bytes(),
+            LexType::System,
+            Folding::default(),
+            false,
+            CostWidth::Bits32,
+        )
+        .unwrap();
+        assert_eq!(lex.params.get(1), WordParam::new(1, 0, -40000));
+        assert_eq!(lex.cost_width(), CostWidth::Bits32);
+    }
 }
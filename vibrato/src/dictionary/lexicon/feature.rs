@@ -1,5 +1,7 @@
 use bincode::{Decode, Encode};
 
+use crate::utils;
+
 #[derive(Default, Decode, Encode)]
 pub struct WordFeatures {
     features: Vec<String>,
@@ -23,4 +25,19 @@ impl WordFeatures {
     pub fn get(&self, word_id: usize) -> &str {
         &self.features[word_id]
     }
+
+    /// Rebuilds the features from the given columns.
+    pub fn select_columns(&mut self, columns: &[usize]) {
+        for f in &mut self.features {
+            *f = utils::select_csv_cells(f, columns);
+        }
+    }
+
+    /// Returns the number of bytes of the heap memory.
+    pub fn memory_usage(&self) -> usize {
+        self.features
+            .iter()
+            .map(|f| std::mem::size_of::<String>() + f.len())
+            .sum()
+    }
 }
@@ -40,6 +40,34 @@ impl WordMap {
                 .map(move |word_id| (word_id, e.end_char))
         })
     }
+
+    /// Same as [`Self::common_prefix_iterator()`] but takes an iterator of characters.
+    #[inline(always)]
+    pub fn common_prefix_iterator_chars<'a, I>(
+        &'a self,
+        input: I,
+    ) -> impl Iterator<Item = (u32, usize)> + 'a
+    where
+        I: Iterator<Item = char> + 'a,
+    {
+        self.trie
+            .common_prefix_iterator_chars(input)
+            .flat_map(move |e| {
+                self.postings
+                    .ids(usize::from_u32(e.value))
+                    .map(move |word_id| (word_id, e.end_char))
+            })
+    }
+
+    /// Returns the number of bytes of the heap memory used by the trie.
+    pub fn trie_memory_usage(&self) -> usize {
+        self.trie.memory_usage()
+    }
+
+    /// Returns the number of bytes of the heap memory used by the postings.
+    pub fn postings_memory_usage(&self) -> usize {
+        self.postings.memory_usage()
+    }
 }
 
 #[derive(Default)]
@@ -19,6 +19,11 @@ impl Postings {
         let len = usize::from_u32(self.data[i]);
         self.data[i + 1..i + 1 + len].iter().cloned()
     }
+
+    /// Returns the number of bytes of the heap memory.
+    pub fn memory_usage(&self) -> usize {
+        self.data.len() * std::mem::size_of::<u32>()
+    }
 }
 
 #[derive(Default)]
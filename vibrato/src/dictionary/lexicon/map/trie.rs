@@ -45,13 +45,30 @@ impl Trie {
         })
     }
 
+    /// Returns the number of bytes of the heap memory.
+    pub fn memory_usage(&self) -> usize {
+        self.da.heap_bytes()
+    }
+
     #[inline(always)]
     pub fn common_prefix_iterator<'a>(
         &'a self,
         input: &'a [char],
     ) -> impl Iterator<Item = TrieMatch> + 'a {
+        self.common_prefix_iterator_chars(input.iter().cloned())
+    }
+
+    /// Same as [`Self::common_prefix_iterator()`] but takes an iterator of characters.
+    #[inline(always)]
+    pub fn common_prefix_iterator_chars<'a, I>(
+        &'a self,
+        input: I,
+    ) -> impl Iterator<Item = TrieMatch> + 'a
+    where
+        I: Iterator<Item = char> + 'a,
+    {
         self.da
-            .common_prefix_search(input.iter().cloned())
+            .common_prefix_search(input)
             .map(move |(value, end_char)| TrieMatch::new(value, end_char))
     }
 }
@@ -1,17 +1,24 @@
 use bincode::{Decode, Encode};
 
 use crate::dictionary::mapper::ConnIdMapper;
+use crate::dictionary::CostWidth;
+use crate::errors::{Result, VibratoError};
 
+/// Parameter of a word used in the Viterbi search.
 #[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Decode, Encode)]
 pub struct WordParam {
+    /// Left connection id.
     pub left_id: u16,
+    /// Right connection id.
     pub right_id: u16,
-    pub word_cost: i16,
+    /// Cost of the word.
+    pub word_cost: i32,
 }
 
 impl WordParam {
+    /// Creates a new instance.
     #[inline(always)]
-    pub const fn new(left_id: u16, right_id: u16, word_cost: i16) -> Self {
+    pub const fn new(left_id: u16, right_id: u16, word_cost: i32) -> Self {
         Self {
             left_id,
             right_id,
@@ -20,35 +27,95 @@ impl WordParam {
     }
 }
 
+/// Parameter of a word stored with a 16-bit cost.
+#[derive(Clone, Copy, Decode, Encode)]
+pub struct NarrowWordParam {
+    left_id: u16,
+    right_id: u16,
+    word_cost: i16,
+}
+
+/// Parameters of words stored in the cost width of the dictionary.
 #[derive(Decode, Encode)]
-pub struct WordParams {
-    params: Vec<WordParam>,
+pub enum WordParams {
+    Bits16(Vec<NarrowWordParam>),
+    Bits32(Vec<WordParam>),
 }
 
 impl WordParams {
-    pub fn new<I>(params: I) -> Self
+    pub fn new<I>(params: I, width: CostWidth) -> Result<Self>
     where
         I: IntoIterator<Item = WordParam>,
     {
-        Self {
-            params: params.into_iter().collect(),
-        }
+        let params = params.into_iter();
+        Ok(match width {
+            CostWidth::Bits16 => Self::Bits16(
+                params
+                    .map(|p| {
+                        let word_cost = i16::try_from(p.word_cost).map_err(|_| {
+                            let msg = format!("The word cost {} overflows 16 bits.", p.word_cost);
+                            VibratoError::invalid_argument("params", msg)
+                        })?;
+                        Ok(NarrowWordParam {
+                            left_id: p.left_id,
+                            right_id: p.right_id,
+                            word_cost,
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+            CostWidth::Bits32 => Self::Bits32(params.collect()),
+        })
     }
 
     #[inline(always)]
     pub fn get(&self, word_id: usize) -> WordParam {
-        self.params[word_id]
+        match self {
+            Self::Bits16(params) => {
+                let p = params[word_id];
+                WordParam::new(p.left_id, p.right_id, i32::from(p.word_cost))
+            }
+            Self::Bits32(params) => params[word_id],
+        }
     }
 
     #[inline(always)]
     pub fn len(&self) -> usize {
-        self.params.len()
+        match self {
+            Self::Bits16(params) => params.len(),
+            Self::Bits32(params) => params.len(),
+        }
+    }
+
+    pub const fn width(&self) -> CostWidth {
+        match self {
+            Self::Bits16(_) => CostWidth::Bits16,
+            Self::Bits32(_) => CostWidth::Bits32,
+        }
+    }
+
+    /// Returns the number of bytes of the heap memory.
+    pub fn memory_usage(&self) -> usize {
+        match self {
+            Self::Bits16(params) => params.len() * std::mem::size_of::<NarrowWordParam>(),
+            Self::Bits32(params) => params.len() * std::mem::size_of::<WordParam>(),
+        }
     }
 
     pub fn map_connection_ids(&mut self, mapper: &ConnIdMapper) {
-        for p in &mut self.params {
-            p.left_id = mapper.left(p.left_id);
-            p.right_id = mapper.right(p.right_id);
+        match self {
+            Self::Bits16(params) => {
+                for p in params {
+                    p.left_id = mapper.left(p.left_id);
+                    p.right_id = mapper.right(p.right_id);
+                }
+            }
+            Self::Bits32(params) => {
+                for p in params {
+                    p.left_id = mapper.left(p.left_id);
+                    p.right_id = mapper.right(p.right_id);
+                }
+            }
         }
     }
 }
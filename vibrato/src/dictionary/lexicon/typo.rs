@@ -0,0 +1,191 @@
+use bincode::{Decode, Encode};
+
+use crate::dictionary::lexicon::feature::WordFeatures;
+use crate::dictionary::lexicon::map::{WordMap, WordMapBuilder};
+use crate::errors::Result;
+use crate::utils::FromU32;
+
+/// The minimum number of characters of surfaces matched with an edit,
+/// avoiding a flood of candidates for short words.
+pub const MIN_TYPO_SURFACE_LEN: usize = 3;
+
+/// Index of surfaces for lookup within edit distance one.
+///
+/// This is based on the symmetric deletion algorithm; surfaces and their variants deleting
+/// one character are stored in the trie, and variants of the input deleting one character
+/// are searched. The found candidates are verified with the surfaces given on lookup.
+#[derive(Decode, Encode)]
+pub struct TypoMap {
+    map: WordMap,
+    // The maximum number of characters of the surfaces.
+    max_len: usize,
+}
+
+impl TypoMap {
+    pub fn new<I, W>(surfaces: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = W>,
+        W: AsRef<str>,
+    {
+        let surfaces: Vec<_> = surfaces
+            .into_iter()
+            .map(|w| w.as_ref().to_string())
+            .collect();
+        let mut b = WordMapBuilder::new();
+        let mut max_len = 0;
+        for (i, surface) in surfaces.iter().enumerate() {
+            let word_id = u32::try_from(i)?;
+            let chars: Vec<_> = surface.chars().collect();
+            max_len = max_len.max(chars.len());
+            let mut variants = vec![surface.clone()];
+            if chars.len() >= MIN_TYPO_SURFACE_LEN {
+                for k in 0..chars.len() {
+                    variants.push(deleted(&chars, k).collect());
+                }
+            }
+            variants.sort_unstable();
+            variants.dedup();
+            for variant in variants {
+                b.add_record(variant, word_id);
+            }
+        }
+        Ok(Self {
+            map: b.build()?,
+            max_len,
+        })
+    }
+
+    /// Calls `f` with the word id, the number of matched characters, and the edit distance
+    /// for each word whose surface is within `max_distance` from a prefix of `input`.
+    ///
+    /// `surfaces` must be those given to [`Self::new()`].
+    ///
+    /// `max_distance` must be no more than one, and words shorter than
+    /// [`MIN_TYPO_SURFACE_LEN`] are reported only if the distance is zero.
+    /// A word can be reported more than once for the same prefix.
+    pub fn for_each_candidate<F>(
+        &self,
+        surfaces: &WordFeatures,
+        input: &[char],
+        max_distance: usize,
+        mut f: F,
+    ) where
+        F: FnMut(u32, usize, usize),
+    {
+        debug_assert!(max_distance <= 1);
+        let input = &input[..input.len().min(self.max_len + max_distance)];
+        let mut verify = |word_id: u32, len: usize| {
+            let surface = surfaces.get(usize::from_u32(word_id));
+            match distance_within_one(&input[..len], surface) {
+                Some(0) => f(word_id, len, 0),
+                Some(1) if max_distance == 1 && surface.chars().count() >= MIN_TYPO_SURFACE_LEN => {
+                    f(word_id, len, 1)
+                }
+                _ => {}
+            }
+        };
+        for (word_id, len) in self.map.common_prefix_iterator(input) {
+            verify(word_id, len);
+        }
+        if max_distance == 0 {
+            return;
+        }
+        for k in 0..input.len() {
+            for (word_id, len) in self.map.common_prefix_iterator_chars(deleted(input, k)) {
+                // Matches not containing the deleted character are found without deletion.
+                if k <= len {
+                    verify(word_id, len + 1);
+                }
+            }
+        }
+    }
+
+    /// Returns the number of bytes of the heap memory used by the trie.
+    pub fn trie_memory_usage(&self) -> usize {
+        self.map.trie_memory_usage()
+    }
+
+    /// Returns the number of bytes of the heap memory used by the postings.
+    pub fn postings_memory_usage(&self) -> usize {
+        self.map.postings_memory_usage()
+    }
+}
+
+/// Iterates the characters deleting the `k`-th one.
+fn deleted(chars: &[char], k: usize) -> impl Iterator<Item = char> + '_ {
+    chars[..k].iter().chain(&chars[k + 1..]).cloned()
+}
+
+/// Computes the edit distance between `a` and `b` if it is no more than one.
+fn distance_within_one(a: &[char], b: &str) -> Option<usize> {
+    let mut b = b.chars();
+    let mut i = 0;
+    for (x, y) in a.iter().zip(b.by_ref()) {
+        if *x != y {
+            // Tries substitution, insertion, and deletion of the remaining characters.
+            let rest_a = &a[i + 1..];
+            let rest_b = b.as_str();
+            let substituted = rest_a.iter().copied().eq(rest_b.chars());
+            let inserted = a[i..].iter().copied().eq(rest_b.chars());
+            let deleted = rest_a
+                .iter()
+                .copied()
+                .eq(std::iter::once(y).chain(rest_b.chars()));
+            return (substituted || inserted || deleted).then_some(1);
+        }
+        i += 1;
+    }
+    // One is a prefix of the other.
+    match (a.len() - i, b.count()) {
+        (0, 0) => Some(0),
+        (1, 0) | (0, 1) => Some(1),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance_within_one() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert_eq!(distance_within_one(&chars("すごい"), "すごい"), Some(0));
+        assert_eq!(distance_within_one(&chars("すごーい"), "すごい"), Some(1));
+        assert_eq!(distance_within_one(&chars("すい"), "すごい"), Some(1));
+        assert_eq!(distance_within_one(&chars("すぎい"), "すごい"), Some(1));
+        assert_eq!(distance_within_one(&chars("すごいよ"), "すごい"), Some(1));
+        assert_eq!(distance_within_one(&chars("すご"), "すごい"), Some(1));
+        assert_eq!(distance_within_one(&chars("ごすい"), "すごい"), None);
+        assert_eq!(distance_within_one(&chars("す"), "すごい"), None);
+    }
+
+    #[test]
+    fn test_for_each_candidate() {
+        let words = ["ありがとう", "あり", "すごい"];
+        let map = TypoMap::new(words).unwrap();
+        let surfaces = WordFeatures::new(words);
+        let mut candidates = vec![];
+        let input: Vec<_> = "ありがとぉ".chars().collect();
+        map.for_each_candidate(&surfaces, &input, 1, |word_id, len, dist| {
+            candidates.push((word_id, len, dist))
+        });
+        candidates.sort_unstable();
+        candidates.dedup();
+        // "あり" is too short to be matched with an edit.
+        assert_eq!(candidates, vec![(0, 4, 1), (0, 5, 1), (1, 2, 0)]);
+
+        let mut candidates = vec![];
+        let input: Vec<_> = "すごーい".chars().collect();
+        map.for_each_candidate(&surfaces, &input, 0, |word_id, len, dist| {
+            candidates.push((word_id, len, dist))
+        });
+        assert!(candidates.is_empty());
+        map.for_each_candidate(&surfaces, &input, 1, |word_id, len, dist| {
+            candidates.push((word_id, len, dist))
+        });
+        candidates.sort_unstable();
+        candidates.dedup();
+        assert_eq!(candidates, vec![(2, 2, 1), (2, 3, 1), (2, 4, 1)]);
+    }
+}
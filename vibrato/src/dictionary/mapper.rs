@@ -36,6 +36,31 @@ impl ConnIdMapper {
         self.right[usize::from(id)]
     }
 
+    /// Returns the new left ids indexed by old ones.
+    #[inline(always)]
+    pub fn left_map(&self) -> &[u16] {
+        &self.left
+    }
+
+    /// Returns the new right ids indexed by old ones.
+    #[inline(always)]
+    pub fn right_map(&self) -> &[u16] {
+        &self.right
+    }
+
+    /// Returns the number of bytes of the heap memory.
+    pub fn memory_usage(&self) -> usize {
+        (self.left.len() + self.right.len()) * std::mem::size_of::<u16>()
+    }
+
+    /// Returns the mapper applying `self` and then `next`.
+    pub fn compose(&self, next: &Self) -> Self {
+        Self::new(
+            self.left.iter().map(|&id| next.left(id)).collect(),
+            self.right.iter().map(|&id| next.right(id)).collect(),
+        )
+    }
+
     pub fn from_iter<L, R>(lmap: L, rmap: R) -> Result<Self>
     where
         L: IntoIterator<Item = u16>,
@@ -146,6 +171,92 @@ impl ConnIdCounter {
     }
 }
 
+/// Fraction of lookups covered by the hot connection ids reported in [`IdLocality`].
+pub const HOT_COVERAGE: f64 = 0.99;
+
+/// Locality of connection ids of one side, left or right, in a corpus.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IdLocality {
+    /// Number of the most frequent ids covering [`HOT_COVERAGE`] of lookups,
+    /// excluding the BOS/EOS id.
+    pub hot_ids: usize,
+
+    /// Number of ids from `0` to the largest hot id before reordering.
+    pub span_before: usize,
+
+    /// Number of ids from `0` to the largest hot id after reordering,
+    /// which is `hot_ids + 1` because the hot ids are packed.
+    pub span_after: usize,
+}
+
+impl IdLocality {
+    /// Computes the locality from probabilities sorted in descending order.
+    fn new(probs: &[(usize, f64)]) -> Self {
+        let mut hot_ids = 0;
+        let mut max_id = 0;
+        let mut covered = 0.;
+        for &(id, prob) in probs {
+            if covered >= HOT_COVERAGE || prob == 0. {
+                break;
+            }
+            covered += prob;
+            hot_ids += 1;
+            max_id = max_id.max(id);
+        }
+        Self {
+            hot_ids,
+            span_before: max_id + 1,
+            span_after: hot_ids + 1,
+        }
+    }
+}
+
+/// Report of [`Dictionary::reorder_connection_ids_by_corpus()`](crate::Dictionary::reorder_connection_ids_by_corpus).
+///
+/// Most lookups of connection costs fall into the block of the matrix spanned by the hot ids,
+/// so the ratio of the blocks before and after reordering estimates the improvement
+/// of the locality of reference.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReorderReport {
+    /// Number of non-empty sentences tokenized.
+    pub num_sentences: usize,
+
+    /// Locality of left ids.
+    pub left: IdLocality,
+
+    /// Locality of right ids.
+    pub right: IdLocality,
+}
+
+impl ReorderReport {
+    pub(crate) fn new(
+        num_sentences: usize,
+        lid_probs: &[(usize, f64)],
+        rid_probs: &[(usize, f64)],
+    ) -> Self {
+        Self {
+            num_sentences,
+            left: IdLocality::new(lid_probs),
+            right: IdLocality::new(rid_probs),
+        }
+    }
+
+    /// Returns the number of matrix cells spanned by the hot ids before reordering.
+    pub const fn hot_cells_before(&self) -> usize {
+        self.left.span_before * self.right.span_before
+    }
+
+    /// Returns the number of matrix cells spanned by the hot ids after reordering.
+    pub const fn hot_cells_after(&self) -> usize {
+        self.left.span_after * self.right.span_after
+    }
+
+    /// Returns how many times the block of hot cells is shrunk by reordering.
+    pub fn improvement(&self) -> f64 {
+        self.hot_cells_before() as f64 / self.hot_cells_after() as f64
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -163,24 +274,52 @@ mod tests {
         assert_eq!(rprobs, vec![(2, 7f64 / 10f64), (1, 0f64 / 10f64)]);
     }
 
+    #[test]
+    fn test_id_locality() {
+        let mut counter = ConnIdCounter::new(5, 2);
+        counter.add(4, 1, 900);
+        counter.add(3, 1, 95);
+        counter.add(1, 1, 5);
+
+        let (lprobs, _) = counter.compute_probs();
+        let locality = IdLocality::new(&lprobs);
+        assert_eq!(
+            locality,
+            IdLocality {
+                hot_ids: 2,
+                span_before: 5,
+                span_after: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn test_compose() {
+        let first = ConnIdMapper::new(vec![0, 2, 1], vec![0, 1]);
+        let second = ConnIdMapper::new(vec![0, 2, 1], vec![0, 1]);
+        let mapper = first.compose(&second);
+        assert_eq!(mapper.left_map(), &[0, 1, 2]);
+        assert_eq!(mapper.right_map(), &[0, 1]);
+    }
+
     #[test]
     fn test_parse_basic() {
         let map = vec![2, 3, 4, 1];
-        let mapping = ConnIdMapper::parse(map.into_iter()).unwrap();
+        let mapping = ConnIdMapper::parse(map).unwrap();
         assert_eq!(mapping, vec![0, 4, 1, 2, 3]);
     }
 
     #[test]
     fn test_parse_zero() {
         let map = vec![2, 3, 0, 1];
-        let result = ConnIdMapper::parse(map.into_iter());
+        let result = ConnIdMapper::parse(map);
         assert!(result.is_err());
     }
 
     #[test]
     fn test_parse_oor() {
         let map = vec![2, 3, 5, 1];
-        let result = ConnIdMapper::parse(map.into_iter());
+        let result = ConnIdMapper::parse(map);
         assert!(result.is_err());
     }
 }
@@ -1,3 +1,6 @@
+mod pattern;
+
+use std::borrow::Cow;
 use std::io::Read;
 
 use bincode::{Decode, Encode};
@@ -7,41 +10,90 @@ use crate::dictionary::connector::Connector;
 use crate::dictionary::lexicon::{Lexicon, WordParam};
 use crate::dictionary::mapper::ConnIdMapper;
 use crate::dictionary::word_idx::WordIdx;
-use crate::dictionary::LexType;
+use crate::dictionary::{CostWidth, LexType};
 use crate::errors::{Result, VibratoError};
 use crate::sentence::Sentence;
-use crate::utils::FromU32;
-
-#[cfg(feature = "train")]
-use crate::utils;
+use crate::utils::{self, FromU32};
 
 use crate::common::MAX_SENTENCE_LENGTH;
 
+use pattern::{UnkPatterns, MAX_PATTERN_MATCH_LEN};
+
+/// Category id assigned to entries of unknown-word patterns.
+const PATTERN_CATE_ID: u16 = u16::MAX;
+
+/// Expands placeholders in a feature string of `unk.def` with the surface of a word.
+///
+/// The following placeholders are supported in each cell:
+///
+///  - `{surface}`: the surface as it is,
+///  - `{hiragana}`: the surface whose katakana are converted into hiragana,
+///  - `{katakana}`: the surface whose hiragana are converted into katakana, and
+///  - `{lowercase}`: the lowercased surface.
+///
+/// Other text, including unknown placeholders, is left as it is.
+/// Expanded cells are quoted if necessary.
+pub fn expand_feature<'a>(template: &'a str, surface: &str) -> Cow<'a, str> {
+    if !template.contains('{') {
+        return Cow::Borrowed(template);
+    }
+    let mut buf = vec![];
+    for (i, cell) in utils::parse_csv_row(template).iter().enumerate() {
+        if i != 0 {
+            buf.push(b',');
+        }
+        let cell = expand_feature_cell(cell, surface);
+        // Writing to Vec<u8> never fails.
+        utils::quote_csv_cell(&mut buf, cell.as_bytes()).unwrap();
+    }
+    // The input is valid UTF-8, and quoting only adds ASCII characters.
+    Cow::Owned(String::from_utf8(buf).unwrap())
+}
+
+fn expand_feature_cell(mut cell: &str, surface: &str) -> String {
+    let mut expanded = String::new();
+    while let Some(pos) = cell.find('{') {
+        expanded.push_str(&cell[..pos]);
+        cell = &cell[pos..];
+        let name = cell[1..].find('}').map(|end| &cell[1..=end]);
+        let value = match name {
+            Some("surface") => surface.to_string(),
+            Some("hiragana") => utils::to_hiragana(surface),
+            Some("katakana") => utils::to_katakana(surface),
+            Some("lowercase") => surface.to_lowercase(),
+            _ => {
+                expanded.push('{');
+                cell = &cell[1..];
+                continue;
+            }
+        };
+        expanded.push_str(&value);
+        // Skips the braces and the name.
+        cell = &cell[name.unwrap().len() + 2..];
+    }
+    expanded.push_str(cell);
+    expanded
+}
+
 #[derive(Default, Debug, Clone, Decode, Encode, PartialEq, Eq)]
 pub struct UnkEntry {
     pub cate_id: u16,
     pub left_id: u16,
     pub right_id: u16,
-    pub word_cost: i16,
+    pub word_cost: i32,
     pub feature: String,
 }
 
 #[derive(Default, Debug, Clone)]
 pub struct UnkWord {
-    start_char: usize,
     end_char: usize,
     left_id: u16,
     right_id: u16,
-    word_cost: i16,
+    word_cost: i32,
     word_id: u16,
 }
 
 impl UnkWord {
-    #[inline(always)]
-    pub const fn start_char(&self) -> usize {
-        self.start_char
-    }
-
     #[inline(always)]
     pub const fn end_char(&self) -> usize {
         self.end_char
@@ -62,13 +114,55 @@ impl UnkWord {
 #[derive(Decode, Encode)]
 pub struct UnkHandler {
     offsets: Vec<usize>, // indexed by category id
+    // Entries of patterns follow those of categories.
     entries: Vec<UnkEntry>,
+    patterns: UnkPatterns,
+    // Feature columns selected on build, which are also applied to patterns given later.
+    feature_columns: Option<Vec<usize>>,
 }
 
 impl UnkHandler {
+    /// Generates unknown words matching the patterns at `start_char`,
+    /// returning `true` if at least one word is generated.
+    ///
+    /// The patterns are matched against at most [`MAX_PATTERN_MATCH_LEN`] characters,
+    /// so that the time is linear in the length of the sentence.
+    #[inline(always)]
+    pub fn gen_pattern_words<F>(&self, sent: &Sentence, start_char: usize, mut f: F) -> bool
+    where
+        F: FnMut(UnkWord),
+    {
+        let mut has_matched = false;
+        if self.patterns.is_empty() {
+            return has_matched;
+        }
+        let end_char = sent.len_char().min(start_char + MAX_PATTERN_MATCH_LEN);
+        let text = &sent.raw()[sent.byte_position(start_char)..sent.byte_position(end_char)];
+        let pattern_offset = *self.offsets.last().unwrap();
+        self.patterns.for_each_match(text, |i, m| {
+            let end_char = start_char + m.chars().count();
+            if !sent.is_boundary(end_char) {
+                return;
+            }
+            let word_id = pattern_offset + i;
+            let e = &self.entries[word_id];
+            f(UnkWord {
+                end_char,
+                left_id: e.left_id,
+                right_id: e.right_id,
+                word_cost: e.word_cost,
+                // The number of entries is checked on load.
+                word_id: u16::try_from(word_id).unwrap(),
+            });
+            has_matched = true;
+        });
+        has_matched
+    }
+
     pub fn gen_unk_words<F>(
         &self,
         sent: &Sentence,
+        char_prop: &CharProperty,
         start_char: usize,
         mut has_matched: bool,
         max_grouping_len: Option<usize>,
@@ -76,7 +170,7 @@ impl UnkHandler {
     ) where
         F: FnMut(UnkWord),
     {
-        let cinfo = sent.char_info(start_char);
+        let cinfo = char_prop.info(sent.char_info_id(start_char));
         if has_matched && !cinfo.invoke() {
             return;
         }
@@ -92,7 +186,9 @@ impl UnkHandler {
             let max_grouping_len = max_grouping_len.map_or(MAX_SENTENCE_LENGTH, |l| l);
             // Note: Do NOT write `max_grouping_len+1` to avoid overflow.
             if groupable - 1 <= max_grouping_len {
-                f = self.scan_entries(start_char, start_char + groupable, cinfo, f);
+                // Extends the group to the end of the grapheme cluster.
+                let end_char = sent.next_boundary(start_char + groupable - 1);
+                f = self.scan_entries(end_char, cinfo, f);
                 has_matched = true;
             }
         }
@@ -105,18 +201,22 @@ impl UnkHandler {
             if sent.len_char() < end_char {
                 break;
             }
-            f = self.scan_entries(start_char, end_char, cinfo, f);
+            if !sent.is_boundary(end_char) {
+                continue;
+            }
+            f = self.scan_entries(end_char, cinfo, f);
             has_matched = true;
         }
 
         // Generates at least one unknown word.
         if !has_matched {
-            self.scan_entries(start_char, start_char + 1, cinfo, f);
+            let end_char = sent.next_boundary(start_char);
+            self.scan_entries(end_char, cinfo, f);
         }
     }
 
     #[inline(always)]
-    fn scan_entries<F>(&self, start_char: usize, end_char: usize, cinfo: CharInfo, mut f: F) -> F
+    fn scan_entries<F>(&self, end_char: usize, cinfo: CharInfo, mut f: F) -> F
     where
         F: FnMut(UnkWord),
     {
@@ -125,12 +225,11 @@ impl UnkHandler {
         for word_id in start..end {
             let e = &self.entries[word_id];
             f(UnkWord {
-                start_char,
                 end_char,
                 left_id: e.left_id,
                 right_id: e.right_id,
                 word_cost: e.word_cost,
-                word_id: word_id as u16,
+                word_id: u16::try_from(word_id).unwrap(),
             });
         }
         f
@@ -143,13 +242,14 @@ impl UnkHandler {
     pub fn compatible_unk_index(
         &self,
         sent: &Sentence,
+        char_prop: &CharProperty,
         start_char: usize,
         end_char: usize,
         feature: &str,
     ) -> Option<WordIdx> {
         let features = utils::parse_csv_row(feature);
 
-        let cinfo = sent.char_info(start_char);
+        let cinfo = char_prop.info(sent.char_info_id(start_char));
 
         let groupable = sent.groupable(start_char);
 
@@ -194,12 +294,35 @@ impl UnkHandler {
         self.entries[usize::from_u32(word_idx.word_id)].cate_id
     }
 
-    #[cfg(feature = "train")]
     #[inline(always)]
     pub fn len(&self) -> usize {
         self.entries.len()
     }
 
+    /// Checks if unknown words are defined for the category.
+    #[inline(always)]
+    pub fn has_entries(&self, cate_id: u32) -> bool {
+        let cate_id = usize::from_u32(cate_id);
+        self.offsets[cate_id] != self.offsets[cate_id + 1]
+    }
+
+    /// Returns the number of unknown-word patterns.
+    #[inline(always)]
+    pub fn num_patterns(&self) -> usize {
+        self.patterns.len()
+    }
+
+    /// Returns the number of bytes of the heap memory.
+    pub fn memory_usage(&self) -> usize {
+        self.offsets.len() * std::mem::size_of::<usize>()
+            + self
+                .entries
+                .iter()
+                .map(|e| std::mem::size_of::<UnkEntry>() + e.feature.len())
+                .sum::<usize>()
+            + self.patterns.memory_usage()
+    }
+
     /// Do NOT make this function public to maintain consistency in
     /// the connection-id mapping among members of `Dictionary`.
     /// The consistency is managed in `Dictionary`.
@@ -210,6 +333,20 @@ impl UnkHandler {
         }
     }
 
+    /// Rebuilds the feature strings from the given columns,
+    /// which are also applied to patterns given later.
+    pub fn select_feature_columns(&mut self, columns: &[usize]) {
+        for e in &mut self.entries {
+            e.feature = utils::select_csv_cells(&e.feature, columns);
+        }
+        self.feature_columns = Some(columns.to_vec());
+    }
+
+    /// Gets the feature columns selected on build.
+    pub fn feature_columns(&self) -> Option<&[usize]> {
+        self.feature_columns.as_deref()
+    }
+
     /// Checks if left/right-ids are valid to the connector.
     pub fn verify<C>(&self, conn: &C) -> bool
     where
@@ -226,20 +363,20 @@ impl UnkHandler {
         true
     }
 
-    /// Creates a new instance from `unk.def`.
-    pub fn from_reader<R>(mut rdr: R, char_prop: &CharProperty) -> Result<Self>
+    /// Creates a new instance from `unk.def`, whose costs must fit in `width`.
+    pub fn from_reader<R>(mut rdr: R, char_prop: &CharProperty, width: CostWidth) -> Result<Self>
     where
         R: Read,
     {
         let mut buf = vec![];
         rdr.read_to_end(&mut buf)?;
 
-        let parsed = Lexicon::parse_csv(&buf, "unk.def")?;
+        let (parsed, _) = Lexicon::parse_csv(&buf, "unk.def", width)?;
         let mut map = vec![vec![]; char_prop.num_categories()];
         for item in parsed {
             let cate_id = u16::try_from(char_prop.cate_id(&item.surface).ok_or_else(|| {
                 let msg = format!("Undefined category: {}", item.surface);
-                VibratoError::invalid_format("unk.def", msg)
+                VibratoError::invalid_format_at("unk.def", item.line, Some(1), msg)
             })?)
             .unwrap();
             let e = UnkEntry {
@@ -259,7 +396,77 @@ impl UnkHandler {
             entries.append(&mut v);
         }
         offsets.push(entries.len());
-        Ok(Self { offsets, entries })
+        Ok(Self {
+            offsets,
+            entries,
+            patterns: UnkPatterns::default(),
+            feature_columns: None,
+        })
+    }
+
+    /// Replaces the unknown-word patterns with those in `pattern.def`.
+    ///
+    /// Each line consists of a regular expression followed by left/right ids,
+    /// a word cost, and a feature, in the same CSV format as `unk.def`.
+    /// Regular expressions containing commas must be quoted.
+    /// The costs must fit in `width`, and the feature columns selected on build are applied.
+    pub fn reset_patterns_from_reader<R>(
+        &mut self,
+        mut rdr: R,
+        mapper: Option<&ConnIdMapper>,
+        width: CostWidth,
+    ) -> Result<()>
+    where
+        R: Read,
+    {
+        let mut buf = vec![];
+        rdr.read_to_end(&mut buf)?;
+
+        let (parsed, _) = Lexicon::parse_csv(&buf, "pattern.def", width)?;
+        let mut sources = vec![];
+        let mut entries = vec![];
+        for item in parsed {
+            UnkPatterns::check(&item.surface).map_err(|e| {
+                let msg = format!("Invalid pattern: {e}");
+                VibratoError::invalid_format_at("pattern.def", item.line, Some(1), msg)
+            })?;
+            let feature = self.feature_columns.as_ref().map_or_else(
+                || item.feature.to_string(),
+                |columns| utils::select_csv_cells(item.feature, columns),
+            );
+            let mut e = UnkEntry {
+                cate_id: PATTERN_CATE_ID,
+                left_id: item.param.left_id,
+                right_id: item.param.right_id,
+                word_cost: item.param.word_cost,
+                feature,
+            };
+            if let Some(mapper) = mapper {
+                e.left_id = mapper.left(e.left_id);
+                e.right_id = mapper.right(e.right_id);
+            }
+            sources.push(item.surface);
+            entries.push(e);
+        }
+
+        let num_entries = *self.offsets.last().unwrap() + entries.len();
+        if num_entries > usize::from(u16::MAX) {
+            return Err(VibratoError::invalid_argument(
+                "rdr",
+                "The number of unknown-word entries must be less than 65536.",
+            ));
+        }
+        self.patterns = UnkPatterns::new(sources)
+            .map_err(|e| VibratoError::invalid_argument("rdr", e.to_string()))?;
+        self.entries.truncate(*self.offsets.last().unwrap());
+        self.entries.append(&mut entries);
+        Ok(())
+    }
+
+    /// Removes all the unknown-word patterns.
+    pub fn clear_patterns(&mut self) {
+        self.patterns = UnkPatterns::default();
+        self.entries.truncate(*self.offsets.last().unwrap());
     }
 }
 
@@ -268,7 +475,7 @@ mod tests {
     use super::*;
 
     #[cfg(feature = "train")]
-    const CHAR_DEF: &'static str = "\
+    const CHAR_DEF: &str = "\
 DEFAULT 0 1 0
 ALPHA   1 1 6
 NUMERIC 1 1 0
@@ -276,7 +483,7 @@ NUMERIC 1 1 0
 0x0041..0x005A ALPHA NUMERIC
 0x0061..0x007A ALPHA NUMERIC";
     #[cfg(feature = "train")]
-    const UNK_DEF: &'static str = "\
+    const UNK_DEF: &str = "\
 DEFAULT,0,0,0,補助記号,*
 ALPHA,0,0,0,名詞,*,変数
 ALPHA,0,0,0,動詞,*
@@ -286,14 +493,14 @@ NUMERIC,0,0,0,数字";
     #[test]
     fn test_compatible_unk_entry_1() {
         let prop = CharProperty::from_reader(CHAR_DEF.as_bytes()).unwrap();
-        let unk = UnkHandler::from_reader(UNK_DEF.as_bytes(), &prop).unwrap();
+        let unk = UnkHandler::from_reader(UNK_DEF.as_bytes(), &prop, CostWidth::Bits16).unwrap();
 
         let mut sent = Sentence::new();
         sent.set_sentence("変数var42を書き換えます");
         sent.compile(&prop);
 
         let unk_index = unk
-            .compatible_unk_index(&sent, 2, 7, "名詞,一般,変数,バーヨンジューニ")
+            .compatible_unk_index(&sent, &prop, 2, 7, "名詞,一般,変数,バーヨンジューニ")
             .unwrap();
         assert_eq!(unk.word_feature(unk_index), "名詞,*,変数");
     }
@@ -302,14 +509,14 @@ NUMERIC,0,0,0,数字";
     #[test]
     fn test_compatible_unk_entry_2() {
         let prop = CharProperty::from_reader(CHAR_DEF.as_bytes()).unwrap();
-        let unk = UnkHandler::from_reader(UNK_DEF.as_bytes(), &prop).unwrap();
+        let unk = UnkHandler::from_reader(UNK_DEF.as_bytes(), &prop, CostWidth::Bits16).unwrap();
 
         let mut sent = Sentence::new();
         sent.set_sentence("変数var42を書き換えます");
         sent.compile(&prop);
 
         let unk_index = unk
-            .compatible_unk_index(&sent, 2, 7, "動詞,一般,変数,バーヨンジューニ")
+            .compatible_unk_index(&sent, &prop, 2, 7, "動詞,一般,変数,バーヨンジューニ")
             .unwrap();
         assert_eq!(unk.word_feature(unk_index), "動詞,*");
     }
@@ -318,14 +525,14 @@ NUMERIC,0,0,0,数字";
     #[test]
     fn test_compatible_unk_entry_3() {
         let prop = CharProperty::from_reader(CHAR_DEF.as_bytes()).unwrap();
-        let unk = UnkHandler::from_reader(UNK_DEF.as_bytes(), &prop).unwrap();
+        let unk = UnkHandler::from_reader(UNK_DEF.as_bytes(), &prop, CostWidth::Bits16).unwrap();
 
         let mut sent = Sentence::new();
         sent.set_sentence("変数var42を書き換えます");
         sent.compile(&prop);
 
         let unk_index = unk
-            .compatible_unk_index(&sent, 5, 7, "数字,一般,変数末尾,ヨンジューニ")
+            .compatible_unk_index(&sent, &prop, 5, 7, "数字,一般,変数末尾,ヨンジューニ")
             .unwrap();
         assert_eq!(unk.word_feature(unk_index), "数字");
     }
@@ -334,27 +541,29 @@ NUMERIC,0,0,0,数字";
     #[test]
     fn test_compatible_unk_entry_undefined_1() {
         let prop = CharProperty::from_reader(CHAR_DEF.as_bytes()).unwrap();
-        let unk = UnkHandler::from_reader(UNK_DEF.as_bytes(), &prop).unwrap();
+        let unk = UnkHandler::from_reader(UNK_DEF.as_bytes(), &prop, CostWidth::Bits16).unwrap();
 
         let mut sent = Sentence::new();
         sent.set_sentence("変数var42を書き換えます");
         sent.compile(&prop);
 
-        assert!(unk.compatible_unk_index(&sent, 2, 7, "形容詞").is_none());
+        assert!(unk
+            .compatible_unk_index(&sent, &prop, 2, 7, "形容詞")
+            .is_none());
     }
 
     #[cfg(feature = "train")]
     #[test]
     fn test_compatible_unk_entry_undefined_2() {
         let prop = CharProperty::from_reader(CHAR_DEF.as_bytes()).unwrap();
-        let unk = UnkHandler::from_reader(UNK_DEF.as_bytes(), &prop).unwrap();
+        let unk = UnkHandler::from_reader(UNK_DEF.as_bytes(), &prop, CostWidth::Bits16).unwrap();
 
         let mut sent = Sentence::new();
         sent.set_sentence("変数var42を書き換えます");
         sent.compile(&prop);
 
         assert!(unk
-            .compatible_unk_index(&sent, 5, 7, "名詞,一般,変数,バーヨンジューニ")
+            .compatible_unk_index(&sent, &prop, 5, 7, "名詞,一般,変数,バーヨンジューニ")
             .is_none());
     }
 
@@ -363,7 +572,7 @@ NUMERIC,0,0,0,数字";
         let char_def = "DEFAULT 0 1 0\nSPACE 0 1 0\nALPHA 1 1 0";
         let unk_def = "DEFAULT,0,2,1,補助記号\nALPHA,1,0,-4,名詞\nALPHA,2,2,3,Meishi";
         let prop = CharProperty::from_reader(char_def.as_bytes()).unwrap();
-        let unk = UnkHandler::from_reader(unk_def.as_bytes(), &prop).unwrap();
+        let unk = UnkHandler::from_reader(unk_def.as_bytes(), &prop, CostWidth::Bits16).unwrap();
         assert_eq!(
             unk.offsets,
             vec![
@@ -405,7 +614,7 @@ NUMERIC,0,0,0,数字";
         let char_def = "DEFAULT 0 1 0";
         let unk_def = "DEFAULT,0,2";
         let prop = CharProperty::from_reader(char_def.as_bytes()).unwrap();
-        let result = UnkHandler::from_reader(unk_def.as_bytes(), &prop);
+        let result = UnkHandler::from_reader(unk_def.as_bytes(), &prop, CostWidth::Bits16);
         assert!(result.is_err());
     }
 
@@ -414,7 +623,38 @@ NUMERIC,0,0,0,数字";
         let char_def = "DEFAULT 0 1 0";
         let unk_def = "INVALID,0,2,1,補助記号";
         let prop = CharProperty::from_reader(char_def.as_bytes()).unwrap();
-        let result = UnkHandler::from_reader(unk_def.as_bytes(), &prop);
+        let result = UnkHandler::from_reader(unk_def.as_bytes(), &prop, CostWidth::Bits16);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_from_reader_invalid_cate_location() {
+        let char_def = "DEFAULT 0 1 0";
+        let unk_def = "DEFAULT,0,2,1,補助記号\nINVALID,0,2,1,補助記号";
+        let prop = CharProperty::from_reader(char_def.as_bytes()).unwrap();
+        let Err(VibratoError::InvalidFormat(e)) =
+            UnkHandler::from_reader(unk_def.as_bytes(), &prop, CostWidth::Bits16)
+        else {
+            panic!("must be an InvalidFormat error");
+        };
+        assert_eq!(e.line(), Some(2));
+        assert_eq!(e.column(), Some(1));
+    }
+
+    #[test]
+    fn test_expand_feature() {
+        assert_eq!(
+            expand_feature("名詞,固有名詞,{katakana},{lowercase}", "ヴぃぶらと"),
+            "名詞,固有名詞,ヴィブラト,ヴぃぶらと"
+        );
+        assert_eq!(
+            expand_feature("名詞,{hiragana},{lowercase}", "Vibrato"),
+            "名詞,Vibrato,vibrato"
+        );
+        assert_eq!(
+            expand_feature("名詞,{surface}-{lowercase},{unknown}", "A,B"),
+            "名詞,\"A,B-a,b\",{unknown}"
+        );
+        assert_eq!(expand_feature("名詞,*", "A,B"), "名詞,*");
+    }
 }
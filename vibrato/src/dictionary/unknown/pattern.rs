@@ -0,0 +1,141 @@
+use bincode::{
+    de::Decoder,
+    enc::Encoder,
+    error::{DecodeError, EncodeError},
+    Decode, Encode,
+};
+use regex::{Regex, RegexSet};
+
+/// The maximum number of characters of words matched with the patterns.
+pub const MAX_PATTERN_MATCH_LEN: usize = 256;
+
+/// Regular expressions recognizing unknown words such as URLs and numbers.
+///
+/// Every pattern is anchored at the start of the input text.
+pub struct UnkPatterns {
+    sources: Vec<String>,
+    set: RegexSet,
+    regexes: Vec<Regex>,
+}
+
+impl Default for UnkPatterns {
+    fn default() -> Self {
+        Self {
+            sources: vec![],
+            set: RegexSet::empty(),
+            regexes: vec![],
+        }
+    }
+}
+
+impl UnkPatterns {
+    /// Compiles the given patterns.
+    pub fn new(sources: Vec<String>) -> Result<Self, regex::Error> {
+        let anchored: Vec<_> = sources.iter().map(|s| format!("^(?:{s})")).collect();
+        let set = RegexSet::new(&anchored)?;
+        let regexes = anchored
+            .iter()
+            .map(|s| Regex::new(s))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            sources,
+            set,
+            regexes,
+        })
+    }
+
+    /// Compiles a single pattern to check its syntax.
+    pub fn check(source: &str) -> Result<(), regex::Error> {
+        Regex::new(&format!("^(?:{source})")).map(|_| ())
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    /// Calls `f` with the pattern index and the matched prefix of `text` for each pattern
+    /// matching a non-empty prefix.
+    #[inline(always)]
+    pub fn for_each_match<'t, F>(&self, text: &'t str, mut f: F)
+    where
+        F: FnMut(usize, &'t str),
+    {
+        if self.is_empty() {
+            return;
+        }
+        for i in self.set.matches(text).iter() {
+            if let Some(m) = self.regexes[i].find(text) {
+                if !m.is_empty() {
+                    f(i, m.as_str());
+                }
+            }
+        }
+    }
+
+    /// Returns the number of bytes of the heap memory.
+    ///
+    /// The memory of the compiled automata is not included.
+    pub fn memory_usage(&self) -> usize {
+        self.sources
+            .iter()
+            .map(|s| std::mem::size_of::<String>() + s.len())
+            .sum()
+    }
+}
+
+impl<Context> Decode<Context> for UnkPatterns {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        let sources: Vec<String> = Decode::decode(decoder)?;
+        Self::new(sources).map_err(|e| DecodeError::OtherString(e.to_string()))
+    }
+}
+bincode::impl_borrow_decode!(UnkPatterns);
+
+impl Encode for UnkPatterns {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        Encode::encode(&self.sources, encoder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_each_match() {
+        let patterns = UnkPatterns::new(vec![
+            r"[0-9]+".to_string(),
+            r"[0-9]{1,3}(,[0-9]{3})+".to_string(),
+        ])
+        .unwrap();
+        let mut matches = vec![];
+        patterns.for_each_match("1,234円", |i, m| matches.push((i, m)));
+        assert_eq!(matches, vec![(0, "1"), (1, "1,234")]);
+    }
+
+    #[test]
+    fn test_for_each_match_leftmost_first() {
+        let patterns = UnkPatterns::new(vec![
+            r"[0-9]+|[0-9]+\.[0-9]+".to_string(),
+            r"[0-9]+\.[0-9]+|[0-9]+".to_string(),
+        ])
+        .unwrap();
+        let mut matches = vec![];
+        patterns.for_each_match("1.5倍", |i, m| matches.push((i, m)));
+        assert_eq!(matches, vec![(0, "1"), (1, "1.5")]);
+    }
+
+    #[test]
+    fn test_anchored() {
+        let patterns = UnkPatterns::new(vec![r"[0-9]+|".to_string()]).unwrap();
+        let mut matches = vec![];
+        patterns.for_each_match("abc123", |i, m| matches.push((i, m)));
+        assert!(matches.is_empty());
+    }
+}
@@ -59,8 +59,63 @@ impl VibratoError {
         Self::InvalidFormat(InvalidFormatError {
             arg,
             msg: msg.into(),
+            line: None,
+            column: None,
         })
     }
+
+    pub(crate) fn invalid_format_at<S>(
+        arg: &'static str,
+        line: usize,
+        column: Option<usize>,
+        msg: S,
+    ) -> Self
+    where
+        S: Into<String>,
+    {
+        Self::InvalidFormat(InvalidFormatError {
+            arg,
+            msg: msg.into(),
+            line: Some(line),
+            column,
+        })
+    }
+
+    /// Creates an [`InvalidFormatError`] with only the column,
+    /// whose line is attached later with [`VibratoError::at`].
+    pub(crate) fn invalid_format_in_column<S>(arg: &'static str, column: usize, msg: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self::InvalidFormat(InvalidFormatError {
+            arg,
+            msg: msg.into(),
+            line: None,
+            column: Some(column),
+        })
+    }
+
+    /// Attaches a source location to the error.
+    ///
+    /// Errors in parsing numbers or strings are converted into [`InvalidFormatError`]
+    /// so that the location can be reported.
+    /// A location already attached is not overwritten.
+    pub(crate) fn at(self, arg: &'static str, line: usize, column: Option<usize>) -> Self {
+        match self {
+            Self::InvalidFormat(mut e) => {
+                if e.line.is_none() {
+                    e.line = Some(line);
+                    e.column = e.column.or(column);
+                }
+                Self::InvalidFormat(e)
+            }
+            Self::TryFromInt(e) => Self::invalid_format_at(arg, line, column, e.to_string()),
+            Self::ParseFloat(e) => Self::invalid_format_at(arg, line, column, e.to_string()),
+            Self::ParseInt(e) => Self::invalid_format_at(arg, line, column, e.to_string()),
+            Self::Utf8(e) => Self::invalid_format_at(arg, line, column, e.to_string()),
+            e => e,
+        }
+    }
 }
 
 impl fmt::Display for VibratoError {
@@ -110,11 +165,49 @@ pub struct InvalidFormatError {
 
     /// Error message.
     pub(crate) msg: String,
+
+    /// Line number starting from 1.
+    pub(crate) line: Option<usize>,
+
+    /// Column number starting from 1, i.e., the position of the field in the row.
+    pub(crate) column: Option<usize>,
+}
+
+impl InvalidFormatError {
+    /// Gets the name of the format.
+    pub const fn arg(&self) -> &'static str {
+        self.arg
+    }
+
+    /// Gets the error message.
+    pub fn msg(&self) -> &str {
+        &self.msg
+    }
+
+    /// Gets the line number starting from 1 at which the error occurs, if available.
+    pub const fn line(&self) -> Option<usize> {
+        self.line
+    }
+
+    /// Gets the column number starting from 1 at which the error occurs, if available.
+    ///
+    /// The column indicates the position of the field in the row
+    /// (e.g., 2 for `left_id` in `lex.csv`).
+    pub const fn column(&self) -> Option<usize> {
+        self.column
+    }
 }
 
 impl fmt::Display for InvalidFormatError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "InvalidFormatError: {}: {}", self.arg, self.msg)
+        write!(f, "InvalidFormatError: {}", self.arg)?;
+        if let Some(line) = self.line {
+            write!(f, ":{line}")?;
+            if let Some(column) = self.column {
+                write!(f, ":{column}")?;
+            }
+        }
+        write!(f, ": {}", self.msg)
     }
 }
 
@@ -52,6 +52,8 @@ compile_error!("`target_pointer_width` must be 32 or 64");
 pub mod common;
 pub mod dictionary;
 pub mod errors;
+pub mod lint;
+pub mod normalizer;
 mod num;
 mod sentence;
 pub mod token;
@@ -0,0 +1,848 @@
+//! Linter for dictionary sources in the MeCab format.
+//!
+//! [`Linter`] reports problems in `lex.csv`, `matrix.def`, `char.def`, and `unk.def`
+//! that do not prevent compilation but are likely to be mistakes,
+//! such as duplicate entries or connection costs left undefined.
+//!
+//! # Examples
+//!
+//! ```
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use vibrato::lint::{LintKind, Linter};
+//!
+//! let lex_csv = "自然,1,1,0,名詞\n自然,1,1,0,名詞";
+//! let matrix_def = "2 2\n0 0 0\n0 1 0\n1 0 0\n1 1 0";
+//! let char_def = "DEFAULT 0 1 0";
+//! let unk_def = "DEFAULT,1,1,100,補助記号";
+//!
+//! let diagnostics = Linter::new().lint_from_readers(
+//!     lex_csv.as_bytes(),
+//!     matrix_def.as_bytes(),
+//!     char_def.as_bytes(),
+//!     unk_def.as_bytes(),
+//! )?;
+//!
+//! assert_eq!(diagnostics.len(), 1);
+//! assert_eq!(diagnostics[0].kind, LintKind::DuplicateEntry);
+//! assert_eq!(diagnostics[0].line, 2);
+//! assert_eq!(
+//!     diagnostics[0].to_string(),
+//!     "lex.csv:2: duplicate-entry: Identical to the entry at line 1",
+//! );
+//! # Ok(())
+//! # }
+//! ```
+use std::fmt;
+use std::io::Read;
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::common::BOS_EOS_CONNECTION_ID;
+use crate::dictionary::lexicon::Lexicon;
+use crate::dictionary::{CostWidth, SourceEncoding};
+use crate::errors::{Result, VibratoError};
+
+/// Source file in which a problem is found.
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug, Hash)]
+pub enum SourceFile {
+    /// `lex.csv`.
+    Lexicon,
+    /// `matrix.def`.
+    Matrix,
+    /// `char.def`.
+    CharDef,
+    /// `unk.def`.
+    UnkDef,
+}
+
+impl SourceFile {
+    /// Gets the name of the file.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Lexicon => "lex.csv",
+            Self::Matrix => "matrix.def",
+            Self::CharDef => "char.def",
+            Self::UnkDef => "unk.def",
+        }
+    }
+}
+
+impl fmt::Display for SourceFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Kind of a problem.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Hash)]
+pub enum LintKind {
+    /// A line cannot be parsed.
+    InvalidFormat,
+    /// An entry has an empty surface.
+    EmptySurface,
+    /// An entry is identical to a previous one.
+    DuplicateEntry,
+    /// A connection id is out of the range defined in `matrix.def`.
+    OutOfRangeId,
+    /// A connection id is never referenced by any entry.
+    UnreferencedId,
+    /// Matrix cells are not defined and are implicitly set to 0.
+    ImplicitMatrixCell,
+    /// A matrix cell is defined more than once.
+    DuplicateMatrixCell,
+    /// A character category is defined but never mapped from any character.
+    UnmappedCategory,
+    /// An undefined character category is referenced.
+    UndefinedCategory,
+    /// A cost is close to the limit of its type.
+    SuspiciousCost,
+}
+
+impl LintKind {
+    /// Gets the name of the kind.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::InvalidFormat => "invalid-format",
+            Self::EmptySurface => "empty-surface",
+            Self::DuplicateEntry => "duplicate-entry",
+            Self::OutOfRangeId => "out-of-range-id",
+            Self::UnreferencedId => "unreferenced-id",
+            Self::ImplicitMatrixCell => "implicit-matrix-cell",
+            Self::DuplicateMatrixCell => "duplicate-matrix-cell",
+            Self::UnmappedCategory => "unmapped-category",
+            Self::UndefinedCategory => "undefined-category",
+            Self::SuspiciousCost => "suspicious-cost",
+        }
+    }
+}
+
+impl fmt::Display for LintKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Problem found by [`Linter`].
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Diagnostic {
+    /// File in which the problem is found.
+    pub file: SourceFile,
+    /// Line number starting from 1.
+    pub line: usize,
+    /// Kind of the problem.
+    pub kind: LintKind,
+    /// Description of the problem.
+    pub msg: String,
+}
+
+impl Diagnostic {
+    fn new<S>(file: SourceFile, line: usize, kind: LintKind, msg: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            file,
+            line,
+            kind,
+            msg: msg.into(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.file, self.line, self.kind, self.msg
+        )
+    }
+}
+
+/// Parsed word entry with its line number.
+struct Entry {
+    line: usize,
+    surface: String,
+    left_id: u16,
+    right_id: u16,
+}
+
+/// Linter for dictionary sources.
+#[derive(Clone, Debug)]
+pub struct Linter {
+    cost_width: CostWidth,
+    cost_threshold: Option<i32>,
+    encoding: SourceEncoding,
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Linter {
+    /// Creates a new instance with the default settings.
+    pub const fn new() -> Self {
+        Self {
+            cost_width: CostWidth::Bits16,
+            cost_threshold: None,
+            encoding: SourceEncoding::Utf8,
+        }
+    }
+
+    /// Specifies the character encoding of the sources.
+    /// The default is [`SourceEncoding::Utf8`].
+    ///
+    /// # Arguments
+    ///
+    ///  - `encoding`: Character encoding.
+    pub const fn encoding(mut self, encoding: SourceEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Specifies the width of costs with which the dictionary is compiled.
+    /// Costs out of the range are reported as [`LintKind::InvalidFormat`].
+    /// The default is [`CostWidth::Bits16`].
+    ///
+    /// # Arguments
+    ///
+    ///  - `cost_width`: Width of costs.
+    pub const fn cost_width(mut self, cost_width: CostWidth) -> Self {
+        self.cost_width = cost_width;
+        self
+    }
+
+    /// Specifies the threshold of suspicious costs.
+    ///
+    /// Word and connection costs whose absolute values are no less than the threshold
+    /// are reported. The default is close to the limit of the cost width,
+    /// 30000 for 16 bits and 2000000000 for 32 bits.
+    ///
+    /// # Arguments
+    ///
+    ///  - `cost_threshold`: Threshold of absolute costs.
+    pub const fn cost_threshold(mut self, cost_threshold: i32) -> Self {
+        self.cost_threshold = Some(cost_threshold);
+        self
+    }
+
+    /// Checks if the absolute cost is no less than the threshold.
+    fn is_suspicious_cost(&self, cost: i64) -> bool {
+        let threshold = self.cost_threshold.unwrap_or(match self.cost_width {
+            CostWidth::Bits16 => 30000,
+            CostWidth::Bits32 => 2_000_000_000,
+        });
+        cost.abs() >= i64::from(threshold)
+    }
+
+    /// Lints dictionary sources in the MeCab format.
+    ///
+    /// The returned diagnostics are sorted in order of the arguments and line numbers.
+    ///
+    /// # Arguments
+    ///
+    ///  - `system_lexicon_rdr`: A reader of a lexicon file `*.csv`.
+    ///  - `connector_rdr`: A reader of matrix file `matrix.def`.
+    ///  - `char_prop_rdr`: A reader of character definition file `char.def`.
+    ///  - `unk_handler_rdr`: A reader of unknown definition file `unk.def`.
+    ///
+    /// # Errors
+    ///
+    /// [`VibratoError`] is returned when reading fails
+    /// or when a source includes an invalid byte sequence in the encoding.
+    /// Problems in the contents are reported as [`Diagnostic`]s instead.
+    pub fn lint_from_readers<S, C, P, U>(
+        &self,
+        system_lexicon_rdr: S,
+        connector_rdr: C,
+        char_prop_rdr: P,
+        unk_handler_rdr: U,
+    ) -> Result<Vec<Diagnostic>>
+    where
+        S: Read,
+        C: Read,
+        P: Read,
+        U: Read,
+    {
+        let mut diags = vec![];
+
+        let lex_entries = self.lint_entries(
+            &self.read_source(system_lexicon_rdr, SourceFile::Lexicon)?,
+            SourceFile::Lexicon,
+            &mut diags,
+        )?;
+        let unk_entries = self.lint_entries(
+            &self.read_source(unk_handler_rdr, SourceFile::UnkDef)?,
+            SourceFile::UnkDef,
+            &mut diags,
+        )?;
+        let num_ids = self.lint_matrix(
+            &self.read_source(connector_rdr, SourceFile::Matrix)?,
+            &mut diags,
+        );
+        let categories = Self::lint_char_def(
+            &self.read_source(char_prop_rdr, SourceFile::CharDef)?,
+            &mut diags,
+        );
+
+        for e in &unk_entries {
+            if !categories.contains(&e.surface) {
+                diags.push(Diagnostic::new(
+                    SourceFile::UnkDef,
+                    e.line,
+                    LintKind::UndefinedCategory,
+                    format!("Category {} is not defined in char.def", e.surface),
+                ));
+            }
+        }
+
+        if let Some((num_right, num_left)) = num_ids {
+            let mut left_used = vec![false; num_left];
+            let mut right_used = vec![false; num_right];
+            for (file, entries) in [
+                (SourceFile::Lexicon, &lex_entries),
+                (SourceFile::UnkDef, &unk_entries),
+            ] {
+                for e in entries {
+                    let (left_id, right_id) = (usize::from(e.left_id), usize::from(e.right_id));
+                    if left_id < num_left && right_id < num_right {
+                        left_used[left_id] = true;
+                        right_used[right_id] = true;
+                    } else {
+                        diags.push(Diagnostic::new(
+                            file,
+                            e.line,
+                            LintKind::OutOfRangeId,
+                            format!(
+                                "left/right_id ({left_id}, {right_id}) must be less than \
+                                 ({num_left}, {num_right}) defined in matrix.def"
+                            ),
+                        ));
+                    }
+                }
+            }
+            let bos_eos = usize::from(BOS_EOS_CONNECTION_ID);
+            for (name, used) in [("left_id", &left_used), ("right_id", &right_used)] {
+                for (id, _) in used
+                    .iter()
+                    .enumerate()
+                    .filter(|&(id, u)| !u && id != bos_eos)
+                {
+                    diags.push(Diagnostic::new(
+                        SourceFile::Matrix,
+                        1,
+                        LintKind::UnreferencedId,
+                        format!("{name} {id} is not referenced by any entry"),
+                    ));
+                }
+            }
+        }
+
+        diags.sort_by_key(|d| (d.file, d.line));
+        Ok(diags)
+    }
+
+    /// Reads a source and transcodes it into UTF-8.
+    fn read_source<R>(&self, rdr: R, file: SourceFile) -> Result<String>
+    where
+        R: Read,
+    {
+        let bytes = self.encoding.read_to_utf8(rdr, file.name())?;
+        // The bytes are validated in the transcoding.
+        Ok(String::from_utf8(bytes).unwrap())
+    }
+
+    /// Lints `lex.csv` or `unk.def` and returns the parsed entries.
+    fn lint_entries(
+        &self,
+        text: &str,
+        file: SourceFile,
+        diags: &mut Vec<Diagnostic>,
+    ) -> Result<Vec<Entry>> {
+        let mut entries = vec![];
+        let mut lines = HashMap::new();
+
+        let result =
+            Lexicon::parse_csv_records(text.as_bytes(), file.name(), self.cost_width, |entry| {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(VibratoError::InvalidFormat(e)) => {
+                        diags.push(Diagnostic::new(
+                            file,
+                            e.line().unwrap_or(1),
+                            LintKind::InvalidFormat,
+                            e.msg(),
+                        ));
+                        return Ok(());
+                    }
+                    Err(e) => return Err(e),
+                };
+                let line_no = entry.line;
+                let param = entry.param;
+                let key = (
+                    entry.surface.clone(),
+                    param.left_id,
+                    param.right_id,
+                    param.word_cost,
+                    entry.feature,
+                );
+                if let Some(&prev) = lines.get(&key) {
+                    diags.push(Diagnostic::new(
+                        file,
+                        line_no,
+                        LintKind::DuplicateEntry,
+                        format!("Identical to the entry at line {prev}"),
+                    ));
+                    return Ok(());
+                }
+                lines.insert(key, line_no);
+
+                if entry.surface.is_empty() {
+                    diags.push(Diagnostic::new(
+                        file,
+                        line_no,
+                        LintKind::EmptySurface,
+                        "The surface is empty, and the entry will be skipped",
+                    ));
+                    return Ok(());
+                }
+                if self.is_suspicious_cost(i64::from(param.word_cost)) {
+                    diags.push(Diagnostic::new(
+                        file,
+                        line_no,
+                        LintKind::SuspiciousCost,
+                        format!("word_cost {} is close to the limit", param.word_cost),
+                    ));
+                }
+                entries.push(Entry {
+                    line: line_no,
+                    surface: entry.surface,
+                    left_id: param.left_id,
+                    right_id: param.right_id,
+                });
+                Ok(())
+            });
+        // A field too large to parse stops the parsing.
+        match result {
+            Ok(()) => {}
+            Err(VibratoError::InvalidFormat(e)) => diags.push(Diagnostic::new(
+                file,
+                e.line().unwrap_or(1),
+                LintKind::InvalidFormat,
+                e.msg(),
+            )),
+            Err(e) => return Err(e),
+        }
+
+        Ok(entries)
+    }
+
+    /// Parses a row of `matrix.def` other than the header.
+    fn parse_matrix_row(line: &str) -> Option<(usize, usize, i64)> {
+        let cols: Vec<_> = line.split(' ').collect();
+        match cols[..] {
+            [r, l, c] => match (r.parse(), l.parse(), c.parse()) {
+                (Ok(r), Ok(l), Ok(c)) => Some((r, l, c)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Lints `matrix.def` and returns the numbers of right and left ids.
+    fn lint_matrix(&self, text: &str, diags: &mut Vec<Diagnostic>) -> Option<(usize, usize)> {
+        let mut lines = text.lines();
+
+        let header = lines.next().unwrap_or_default();
+        let cols: Vec<_> = header.split(' ').collect();
+        let (num_right, num_left) = match cols[..] {
+            [r, l] => match (r.parse::<u16>(), l.parse::<u16>()) {
+                (Ok(r), Ok(l)) => (usize::from(r), usize::from(l)),
+                _ => {
+                    diags.push(Diagnostic::new(
+                        SourceFile::Matrix,
+                        1,
+                        LintKind::InvalidFormat,
+                        "The header must consist of two integers",
+                    ));
+                    return None;
+                }
+            },
+            _ => {
+                diags.push(Diagnostic::new(
+                    SourceFile::Matrix,
+                    1,
+                    LintKind::InvalidFormat,
+                    "The header must consist of two integers separated by a space",
+                ));
+                return None;
+            }
+        };
+
+        // Bit set of the defined cells.
+        let num_cells = num_right * num_left;
+        let mut defined = vec![0u64; num_cells.div_ceil(64)];
+        // Cells defined more than once and the indices of their diagnostics,
+        // whose messages are completed with the first lines at the end.
+        let mut duplicates = vec![];
+
+        for (i, line) in lines.enumerate() {
+            let line_no = i + 2;
+            if line.is_empty() {
+                continue;
+            }
+            let Some((right_id, left_id, cost)) = Self::parse_matrix_row(line) else {
+                diags.push(Diagnostic::new(
+                    SourceFile::Matrix,
+                    line_no,
+                    LintKind::InvalidFormat,
+                    "A row other than the header must consist of three integers \
+                     separated by spaces",
+                ));
+                continue;
+            };
+            if !self.cost_width.contains(cost) {
+                diags.push(Diagnostic::new(
+                    SourceFile::Matrix,
+                    line_no,
+                    LintKind::InvalidFormat,
+                    format!(
+                        "Connection cost {cost} overflows {} bits",
+                        self.cost_width.bits()
+                    ),
+                ));
+                continue;
+            }
+            if num_right <= right_id || num_left <= left_id {
+                diags.push(Diagnostic::new(
+                    SourceFile::Matrix,
+                    line_no,
+                    LintKind::OutOfRangeId,
+                    format!(
+                        "right/left_id ({right_id}, {left_id}) must be less than \
+                         ({num_right}, {num_left})"
+                    ),
+                ));
+                continue;
+            }
+            let cell = left_id * num_right + right_id;
+            let (word, bit) = (cell / 64, 1 << (cell % 64));
+            if defined[word] & bit != 0 {
+                duplicates.push((cell, diags.len()));
+                diags.push(Diagnostic::new(
+                    SourceFile::Matrix,
+                    line_no,
+                    LintKind::DuplicateMatrixCell,
+                    "",
+                ));
+            }
+            defined[word] |= bit;
+            if self.is_suspicious_cost(cost) {
+                diags.push(Diagnostic::new(
+                    SourceFile::Matrix,
+                    line_no,
+                    LintKind::SuspiciousCost,
+                    format!("Connection cost {cost} is close to the limit"),
+                ));
+            }
+        }
+
+        if !duplicates.is_empty() {
+            // Finds the first lines of the duplicate cells.
+            let mut first_lines: HashMap<_, _> =
+                duplicates.iter().map(|&(cell, _)| (cell, 0)).collect();
+            for (i, line) in text.lines().enumerate().skip(1) {
+                if let Some((right_id, left_id, _)) = Self::parse_matrix_row(line) {
+                    if right_id < num_right && left_id < num_left {
+                        if let Some(first) = first_lines.get_mut(&(left_id * num_right + right_id))
+                        {
+                            if *first == 0 {
+                                *first = i + 1;
+                            }
+                        }
+                    }
+                }
+            }
+            for (cell, idx) in duplicates {
+                let (right_id, left_id) = (cell % num_right, cell / num_right);
+                diags[idx].msg = format!(
+                    "The cell ({right_id}, {left_id}) is already defined at line {}",
+                    first_lines[&cell]
+                );
+            }
+        }
+
+        let num_defined: usize = defined.iter().map(|w| w.count_ones() as usize).sum();
+        if num_defined < num_cells {
+            let first = defined
+                .iter()
+                .enumerate()
+                .find(|&(_, &w)| w != u64::MAX)
+                .map(|(i, &w)| i * 64 + w.trailing_ones() as usize)
+                .unwrap();
+            diags.push(Diagnostic::new(
+                SourceFile::Matrix,
+                1,
+                LintKind::ImplicitMatrixCell,
+                format!(
+                    "{} cells are not defined and are implicitly set to 0, \
+                     e.g., ({}, {})",
+                    num_cells - num_defined,
+                    first % num_right,
+                    first / num_right
+                ),
+            ));
+        }
+
+        Some((num_right, num_left))
+    }
+
+    /// Lints `char.def` and returns the defined categories.
+    fn lint_char_def(text: &str, diags: &mut Vec<Diagnostic>) -> HashSet<String> {
+        // Category -> Line number of the definition.
+        let mut defined = HashMap::new();
+        // Category -> Line numbers of the references.
+        let mut mapped: HashMap<String, Vec<usize>> = HashMap::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let cols: Vec<_> = line.split_whitespace().collect();
+            if line.starts_with("0x") {
+                if cols.len() < 2 {
+                    diags.push(Diagnostic::new(
+                        SourceFile::CharDef,
+                        line_no,
+                        LintKind::InvalidFormat,
+                        "A character range must have two items at least",
+                    ));
+                    continue;
+                }
+                for &cate in cols[1..].iter().take_while(|col| !col.starts_with('#')) {
+                    mapped.entry(cate.to_string()).or_default().push(line_no);
+                }
+            } else {
+                if cols.len() < 4 {
+                    diags.push(Diagnostic::new(
+                        SourceFile::CharDef,
+                        line_no,
+                        LintKind::InvalidFormat,
+                        "A character category must consist of four items",
+                    ));
+                    continue;
+                }
+                defined.entry(cols[0].to_string()).or_insert(line_no);
+            }
+        }
+
+        for (cate, lines) in &mapped {
+            if !defined.contains_key(cate) {
+                for &line_no in lines {
+                    diags.push(Diagnostic::new(
+                        SourceFile::CharDef,
+                        line_no,
+                        LintKind::UndefinedCategory,
+                        format!("Category {cate} is not defined"),
+                    ));
+                }
+            }
+        }
+        for (cate, &line_no) in &defined {
+            // DEFAULT is assigned to all the characters not mapped explicitly.
+            if cate != "DEFAULT" && !mapped.contains_key(cate) {
+                diags.push(Diagnostic::new(
+                    SourceFile::CharDef,
+                    line_no,
+                    LintKind::UnmappedCategory,
+                    format!("Category {cate} is not mapped from any character"),
+                ));
+            }
+        }
+
+        defined.into_keys().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint(lex_csv: &str, matrix_def: &str, char_def: &str, unk_def: &str) -> Vec<Diagnostic> {
+        Linter::new()
+            .lint_from_readers(
+                lex_csv.as_bytes(),
+                matrix_def.as_bytes(),
+                char_def.as_bytes(),
+                unk_def.as_bytes(),
+            )
+            .unwrap()
+    }
+
+    fn summary(diags: &[Diagnostic]) -> Vec<(SourceFile, usize, LintKind)> {
+        diags.iter().map(|d| (d.file, d.line, d.kind)).collect()
+    }
+
+    #[test]
+    fn test_clean() {
+        let diags = lint(
+            "自然,1,1,0,名詞",
+            "2 2\n0 0 0\n0 1 0\n1 0 0\n1 1 0",
+            "DEFAULT 0 1 0\nKANJI 0 0 2\n0x4E00..0x9FFF KANJI",
+            "DEFAULT,1,1,100,*\nKANJI,1,1,100,*",
+        );
+        assert!(diags.is_empty(), "{diags:?}");
+    }
+
+    #[test]
+    fn test_lexicon() {
+        let diags = lint(
+            "自然,1,1,0,名詞\n,1,1,0,名詞\n自然,1,1,0,名詞\n言語,1,x,0,名詞\n言語,1,1,-32000,名詞\n言語,3,1,0,名詞",
+            "2 2\n0 0 0\n0 1 0\n1 0 0\n1 1 0",
+            "DEFAULT 0 1 0",
+            "DEFAULT,1,1,100,*",
+        );
+        assert_eq!(
+            summary(&diags),
+            vec![
+                (SourceFile::Lexicon, 2, LintKind::EmptySurface),
+                (SourceFile::Lexicon, 3, LintKind::DuplicateEntry),
+                (SourceFile::Lexicon, 4, LintKind::InvalidFormat),
+                (SourceFile::Lexicon, 5, LintKind::SuspiciousCost),
+                (SourceFile::Lexicon, 6, LintKind::OutOfRangeId),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_matrix() {
+        let diags = lint(
+            "自然,1,1,0,名詞",
+            "3 2\n0 0 0\n0 1 0\n1 0 0\n1 0 5\n1 1 31000\n",
+            "DEFAULT 0 1 0",
+            "DEFAULT,1,1,100,*",
+        );
+        assert_eq!(
+            summary(&diags),
+            vec![
+                (SourceFile::Matrix, 1, LintKind::ImplicitMatrixCell),
+                (SourceFile::Matrix, 1, LintKind::UnreferencedId),
+                (SourceFile::Matrix, 5, LintKind::DuplicateMatrixCell),
+                (SourceFile::Matrix, 6, LintKind::SuspiciousCost),
+            ]
+        );
+        assert_eq!(diags[2].msg, "The cell (1, 0) is already defined at line 4");
+        assert_eq!(diags[1].msg, "right_id 2 is not referenced by any entry");
+        assert_eq!(
+            diags[0].msg,
+            "2 cells are not defined and are implicitly set to 0, e.g., (2, 0)"
+        );
+    }
+
+    #[test]
+    fn test_quoted_fields() {
+        let diags = lint(
+            "\"自\n然\",1,1,0,名詞\n\"言,語\",1,1,0,名詞\n\"言,語\",1,1,0,名詞",
+            "2 2\n0 0 0\n0 1 0\n1 0 0\n1 1 0",
+            "DEFAULT 0 1 0",
+            "DEFAULT,1,1,100,*",
+        );
+        assert_eq!(
+            summary(&diags),
+            vec![(SourceFile::Lexicon, 4, LintKind::DuplicateEntry)]
+        );
+        assert_eq!(diags[0].msg, "Identical to the entry at line 3");
+    }
+
+    #[test]
+    fn test_encoding() {
+        let lex_csv = encoding_rs::SHIFT_JIS
+            .encode("自然,1,1,0,名詞\n自然,1,1,0,名詞")
+            .0;
+        let diags = Linter::new()
+            .encoding(SourceEncoding::ShiftJis)
+            .lint_from_readers(
+                &lex_csv[..],
+                "2 2\n0 0 0\n0 1 0\n1 0 0\n1 1 0".as_bytes(),
+                "DEFAULT 0 1 0".as_bytes(),
+                "DEFAULT,1,1,100,*".as_bytes(),
+            )
+            .unwrap();
+        assert_eq!(
+            summary(&diags),
+            vec![(SourceFile::Lexicon, 2, LintKind::DuplicateEntry)]
+        );
+
+        // Not in UTF-8
+        assert!(Linter::new()
+            .lint_from_readers(
+                &lex_csv[..],
+                "2 2".as_bytes(),
+                "DEFAULT 0 1 0".as_bytes(),
+                "DEFAULT,1,1,100,*".as_bytes(),
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_cost_width() {
+        let lex_csv = "自然,1,1,40000,名詞\n言語,1,1,2100000000,名詞";
+        let matrix_def = "2 2\n0 0 0\n0 1 0\n1 0 0\n1 1 -40000";
+        let lint_with = |linter: Linter| {
+            let diags = linter
+                .lint_from_readers(
+                    lex_csv.as_bytes(),
+                    matrix_def.as_bytes(),
+                    "DEFAULT 0 1 0".as_bytes(),
+                    "DEFAULT,1,1,100,*".as_bytes(),
+                )
+                .unwrap();
+            summary(&diags)
+        };
+        assert_eq!(
+            lint_with(Linter::new()),
+            vec![
+                (SourceFile::Lexicon, 1, LintKind::InvalidFormat),
+                (SourceFile::Lexicon, 2, LintKind::InvalidFormat),
+                (SourceFile::Matrix, 1, LintKind::ImplicitMatrixCell),
+                (SourceFile::Matrix, 5, LintKind::InvalidFormat),
+            ]
+        );
+        assert_eq!(
+            lint_with(Linter::new().cost_width(CostWidth::Bits32)),
+            vec![(SourceFile::Lexicon, 2, LintKind::SuspiciousCost)]
+        );
+    }
+
+    #[test]
+    fn test_categories() {
+        let diags = lint(
+            "自然,1,1,0,名詞",
+            "2 2\n0 0 0\n0 1 0\n1 0 0\n1 1 0",
+            "DEFAULT 0 1 0\nKANJI 0 0 2\nALPHA 1 1 0\n0x4E00..0x9FFF KANJI\n0x0041 ALPHA NUMERIC",
+            "DEFAULT,1,1,100,*\nKANJI,1,1,100,*\nALPHA,1,1,100,*\nSPACE,1,1,100,*",
+        );
+        assert_eq!(
+            summary(&diags),
+            vec![
+                (SourceFile::CharDef, 5, LintKind::UndefinedCategory),
+                (SourceFile::UnkDef, 4, LintKind::UndefinedCategory),
+            ]
+        );
+
+        let diags = lint(
+            "自然,1,1,0,名詞",
+            "2 2\n0 0 0\n0 1 0\n1 0 0\n1 1 0",
+            "DEFAULT 0 1 0\nKANJI 0 0 2",
+            "DEFAULT,1,1,100,*\nKANJI,1,1,100,*",
+        );
+        assert_eq!(
+            summary(&diags),
+            vec![(SourceFile::CharDef, 2, LintKind::UnmappedCategory)]
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! Normalization of input sentences.
+use std::ops::Range;
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+const SOFT_HYPHEN: char = '\u{AD}';
+
+/// Normalizer of input sentences applied before tokenization.
+///
+/// The input is normalized for each extended grapheme cluster,
+/// so that offsets in the normalized text can be mapped back to the original text.
+///
+/// # Examples
+///
+/// ```
+/// use vibrato::normalizer::Normalizer;
+///
+/// let normalizer = Normalizer::new().nfkc(true).lowercase(true);
+/// assert_eq!(normalizer.normalize("ＶＩＢＲＡＴＯ"), "vibrato");
+/// ```
+#[derive(Clone, Copy, Default, Debug, Eq, PartialEq)]
+pub struct Normalizer {
+    nfkc: bool,
+    fold_width: bool,
+    lowercase: bool,
+    remove_soft_hyphens: bool,
+}
+
+impl Normalizer {
+    /// Creates a new instance that changes nothing.
+    pub const fn new() -> Self {
+        Self {
+            nfkc: false,
+            fold_width: false,
+            lowercase: false,
+            remove_soft_hyphens: false,
+        }
+    }
+
+    /// Applies Unicode Normalization Form KC.
+    pub const fn nfkc(mut self, yes: bool) -> Self {
+        self.nfkc = yes;
+        self
+    }
+
+    /// Folds fullwidth ASCII and halfwidth katakana into halfwidth ASCII and fullwidth katakana,
+    /// respectively. This is implied by [`Self::nfkc()`].
+    pub const fn fold_width(mut self, yes: bool) -> Self {
+        self.fold_width = yes;
+        self
+    }
+
+    /// Converts characters into lowercase.
+    pub const fn lowercase(mut self, yes: bool) -> Self {
+        self.lowercase = yes;
+        self
+    }
+
+    /// Removes soft hyphens (U+00AD).
+    pub const fn remove_soft_hyphens(mut self, yes: bool) -> Self {
+        self.remove_soft_hyphens = yes;
+        self
+    }
+
+    /// Normalizes the input text.
+    pub fn normalize(&self, input: &str) -> String {
+        let mut output = String::new();
+        let mut alignment = Alignment::default();
+        self.normalize_with_alignment(input, &mut output, &mut alignment);
+        output
+    }
+
+    /// Normalizes the input text into `output`,
+    /// storing the mapping from the output characters to the input ranges in `alignment`.
+    pub(crate) fn normalize_with_alignment(
+        &self,
+        input: &str,
+        output: &mut String,
+        alignment: &mut Alignment,
+    ) {
+        output.clear();
+        alignment.clear();
+        let mut buf = String::new();
+        let mut start_char = 0;
+        for (start_byte, cluster) in input.grapheme_indices(true) {
+            let end_byte = start_byte + cluster.len();
+            let end_char = start_char + cluster.chars().count();
+            self.normalize_cluster(cluster, &mut buf);
+            for _ in buf.chars() {
+                alignment.bytes.push(start_byte..end_byte);
+                alignment.chars.push(start_char..end_char);
+            }
+            output.push_str(&buf);
+            start_char = end_char;
+        }
+    }
+
+    fn normalize_cluster(&self, cluster: &str, buf: &mut String) {
+        buf.clear();
+        buf.push_str(cluster);
+        if self.remove_soft_hyphens && buf.contains(SOFT_HYPHEN) {
+            buf.retain(|c| c != SOFT_HYPHEN);
+        }
+        if self.nfkc || (self.fold_width && buf.chars().all(is_width_variant)) {
+            *buf = buf.nfkc().collect();
+        }
+        if self.lowercase && buf.chars().any(char::is_uppercase) {
+            *buf = buf.to_lowercase();
+        }
+    }
+}
+
+/// Checks if the character is in the Halfwidth and Fullwidth Forms block or
+/// is the ideographic space.
+fn is_width_variant(c: char) -> bool {
+    matches!(c, '\u{3000}' | '\u{FF01}'..='\u{FFEF}')
+}
+
+/// Mapping from characters in a normalized text to ranges in the original text.
+#[derive(Default, Clone, Debug)]
+pub(crate) struct Alignment {
+    // Indexed by character positions in the normalized text.
+    bytes: Vec<Range<usize>>,
+    chars: Vec<Range<usize>>,
+}
+
+impl Alignment {
+    fn clear(&mut self) {
+        self.bytes.clear();
+        self.chars.clear();
+    }
+
+    /// Maps a non-empty range of characters in the normalized text to a byte range
+    /// in the original text.
+    ///
+    /// An original grapheme cluster is assigned to the range including the first character
+    /// of its normalized text, so ranges starting in the middle of the text are mapped
+    /// after the cluster, and the mapped ranges never overlap.
+    #[inline(always)]
+    pub fn range_byte(&self, range_char: Range<usize>) -> Range<usize> {
+        self.map_range(&self.bytes, range_char)
+    }
+
+    /// Checks if a character position in the normalized text is at the boundary of
+    /// grapheme clusters in the original text.
+    #[inline(always)]
+    pub fn is_cluster_boundary(&self, pos_char: usize) -> bool {
+        pos_char == 0
+            || pos_char == self.bytes.len()
+            || self.bytes[pos_char - 1] != self.bytes[pos_char]
+    }
+
+    /// Maps a character position in the normalized text to the byte position in the original
+    /// text of length `len_byte`.
+    #[inline(always)]
+    pub fn byte_position(&self, pos_char: usize, len_byte: usize) -> usize {
+        self.bytes.get(pos_char).map_or(len_byte, |r| r.start)
+    }
+
+    /// Maps a non-empty range of characters in the normalized text to a character range
+    /// in the original text.
+    ///
+    /// See [`Self::range_byte()`] for details.
+    #[inline(always)]
+    pub fn range_char(&self, range_char: Range<usize>) -> Range<usize> {
+        self.map_range(&self.chars, range_char)
+    }
+
+    #[inline(always)]
+    fn map_range(&self, ranges: &[Range<usize>], range_char: Range<usize>) -> Range<usize> {
+        let first = &ranges[range_char.start];
+        let start = if self.is_cluster_boundary(range_char.start) {
+            first.start
+        } else {
+            first.end
+        };
+        start..ranges[range_char.end - 1].end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let normalizer = Normalizer::new()
+            .nfkc(true)
+            .lowercase(true)
+            .remove_soft_hyphens(true);
+        assert_eq!(
+            normalizer.normalize("Ｖｉｂ\u{AD}ｒａｔｏ㍿ｶﾞ"),
+            "vibrato株式会社ガ"
+        );
+    }
+
+    #[test]
+    fn test_fold_width() {
+        let normalizer = Normalizer::new().fold_width(true);
+        assert_eq!(normalizer.normalize("ＡＢＣ　ｶﾞ㍿ﬁ"), "ABC ガ㍿ﬁ");
+    }
+
+    #[test]
+    fn test_alignment() {
+        let normalizer = Normalizer::new().nfkc(true).remove_soft_hyphens(true);
+        let mut output = String::new();
+        let mut alignment = Alignment::default();
+        normalizer.normalize_with_alignment("a\u{AD}㍿Ｂ", &mut output, &mut alignment);
+        assert_eq!(output, "a株式会社B");
+        assert_eq!(alignment.range_byte(0..1), 0..1);
+        assert_eq!(alignment.range_char(0..1), 0..1);
+        // "㍿" is assigned to the first part, and the rest is mapped to an empty range.
+        assert_eq!(alignment.range_byte(1..3), 3..6);
+        assert_eq!(alignment.range_char(1..3), 2..3);
+        assert_eq!(alignment.range_byte(3..5), 6..6);
+        assert_eq!(alignment.range_char(3..5), 3..3);
+        assert_eq!(alignment.range_byte(3..6), 6..9);
+        assert_eq!(alignment.range_char(3..6), 3..4);
+        assert_eq!(alignment.range_byte(1..6), 3..9);
+        assert_eq!(alignment.range_char(1..6), 2..4);
+    }
+}
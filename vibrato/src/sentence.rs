@@ -1,12 +1,67 @@
-use crate::dictionary::character::{CharInfo, CharProperty};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::dictionary::character::CharProperty;
+use crate::dictionary::Folding;
 
 #[derive(Default, Clone, Debug)]
 pub struct Sentence {
     input: String,
     chars: Vec<char>,
     c2b: Vec<usize>,
-    cinfos: Vec<CharInfo>,
+    // Ids of the character information in the character property.
+    cinfo_ids: Vec<u16>,
     groupable: Vec<usize>,
+    // Empty if grapheme clusters are not considered.
+    boundaries: Vec<bool>,
+    // Empty if the sentence is not folded.
+    folded: MappedChars,
+    // Empty if repeated characters are not collapsed.
+    collapsed: MappedChars,
+}
+
+/// Characters converted from those of a sentence, merging some of them.
+#[derive(Default, Clone, Debug)]
+pub struct MappedChars {
+    chars: Vec<char>,
+    // Positions in the sentence indexed by positions in `chars`, including the end position.
+    m2o: Vec<usize>,
+    // Positions in `chars` indexed by positions in the sentence, including the end position.
+    // `usize::MAX` if the position is inside merged characters.
+    o2m: Vec<usize>,
+}
+
+impl MappedChars {
+    fn clear(&mut self) {
+        self.chars.clear();
+        self.m2o.clear();
+        self.o2m.clear();
+    }
+
+    fn compute_o2m(&mut self, len_char: usize) {
+        self.o2m.resize(len_char + 1, usize::MAX);
+        for (pos_mapped, &pos_char) in self.m2o.iter().enumerate() {
+            self.o2m[pos_char] = pos_mapped;
+        }
+    }
+
+    #[inline(always)]
+    pub fn chars(&self) -> &[char] {
+        &self.chars
+    }
+
+    /// Maps a character position in the sentence to the position in `chars`,
+    /// returning `None` if the position is inside merged characters.
+    #[inline(always)]
+    pub fn position(&self, pos_char: usize) -> Option<usize> {
+        let pos_mapped = self.o2m[pos_char];
+        (pos_mapped != usize::MAX).then_some(pos_mapped)
+    }
+
+    /// Maps a position in `chars` to the character position in the sentence.
+    #[inline(always)]
+    pub fn original_position(&self, pos_mapped: usize) -> usize {
+        self.m2o[pos_mapped]
+    }
 }
 
 impl Sentence {
@@ -19,8 +74,11 @@ impl Sentence {
         self.input.clear();
         self.chars.clear();
         self.c2b.clear();
-        self.cinfos.clear();
+        self.cinfo_ids.clear();
         self.groupable.clear();
+        self.boundaries.clear();
+        self.folded.clear();
+        self.collapsed.clear();
     }
 
     pub fn set_sentence<S>(&mut self, input: S)
@@ -34,7 +92,39 @@ impl Sentence {
     pub fn compile(&mut self, char_prop: &CharProperty) {
         self.compute_basic();
         self.compute_categories(char_prop);
-        self.compute_groupable();
+        self.compute_groupable(char_prop);
+    }
+
+    /// Computes boundaries of extended grapheme clusters, which must be called after `compile()`.
+    pub fn compute_boundaries(&mut self) {
+        self.boundaries.resize(self.chars.len() + 1, false);
+        let mut pos_char = 0;
+        for cluster in self.input.graphemes(true) {
+            self.boundaries[pos_char] = true;
+            pos_char += cluster.chars().count();
+        }
+        self.boundaries[pos_char] = true;
+    }
+
+    /// Computes the folded characters, which must be called after `compile()`.
+    pub fn compute_folded(&mut self, folding: Folding) {
+        let folded = &mut self.folded;
+        folding.fold(&self.chars, &mut folded.chars, &mut folded.m2o);
+        folded.compute_o2m(self.chars.len());
+    }
+
+    /// Computes the characters collapsing runs of the same character into one,
+    /// which must be called after `compile()`.
+    pub fn compute_collapsed(&mut self) {
+        let collapsed = &mut self.collapsed;
+        for (i, &c) in self.chars.iter().enumerate() {
+            if collapsed.chars.last() != Some(&c) {
+                collapsed.chars.push(c);
+                collapsed.m2o.push(i);
+            }
+        }
+        collapsed.m2o.push(self.chars.len());
+        collapsed.compute_o2m(self.chars.len());
     }
 
     fn compute_basic(&mut self) {
@@ -48,22 +138,22 @@ impl Sentence {
     fn compute_categories(&mut self, char_prop: &CharProperty) {
         debug_assert!(!self.chars.is_empty());
 
-        self.cinfos.reserve(self.chars.len());
+        self.cinfo_ids.reserve(self.chars.len());
         for &c in &self.chars {
-            self.cinfos.push(char_prop.char_info(c));
+            self.cinfo_ids.push(char_prop.char_info_id(c));
         }
     }
 
-    fn compute_groupable(&mut self) {
+    fn compute_groupable(&mut self, char_prop: &CharProperty) {
         debug_assert!(!self.chars.is_empty());
-        debug_assert_eq!(self.chars.len(), self.cinfos.len());
+        debug_assert_eq!(self.chars.len(), self.cinfo_ids.len());
 
         self.groupable.resize(self.chars.len(), 1);
-        let mut rhs = self.cinfos.last().unwrap().cate_idset();
+        let mut rhs = *self.cinfo_ids.last().unwrap();
 
         for i in (1..self.chars.len()).rev() {
-            let lhs = self.cinfos[i - 1].cate_idset();
-            if (lhs & rhs) != 0 {
+            let lhs = self.cinfo_ids[i - 1];
+            if char_prop.shares_category(lhs, rhs) {
                 self.groupable[i - 1] = self.groupable[i] + 1;
             }
             rhs = lhs;
@@ -91,14 +181,41 @@ impl Sentence {
     }
 
     #[inline(always)]
-    pub fn char_info(&self, pos_char: usize) -> CharInfo {
-        self.cinfos[pos_char]
+    pub fn char_info_id(&self, pos_char: usize) -> u16 {
+        self.cinfo_ids[pos_char]
     }
 
     #[inline(always)]
     pub fn groupable(&self, pos_char: usize) -> usize {
         self.groupable[pos_char]
     }
+
+    /// Checks if a token can start or end at the position,
+    /// which is always `true` if the boundaries are not computed.
+    #[inline(always)]
+    pub fn is_boundary(&self, pos_char: usize) -> bool {
+        self.boundaries.is_empty() || self.boundaries[pos_char]
+    }
+
+    #[inline(always)]
+    pub const fn folded(&self) -> &MappedChars {
+        &self.folded
+    }
+
+    #[inline(always)]
+    pub const fn collapsed(&self) -> &MappedChars {
+        &self.collapsed
+    }
+
+    /// Returns the smallest position after `pos_char` at which a token can end.
+    #[inline(always)]
+    pub fn next_boundary(&self, pos_char: usize) -> usize {
+        let mut pos_char = pos_char + 1;
+        while !self.is_boundary(pos_char) {
+            pos_char += 1;
+        }
+        pos_char
+    }
 }
 
 #[cfg(test)]
@@ -115,4 +232,49 @@ mod tests {
         assert_eq!(sent.byte_position(1), 3);
         assert_eq!(sent.byte_position(2), 6);
     }
+
+    #[test]
+    fn test_boundaries() {
+        let mut sent = Sentence::new();
+        sent.set_sentence("e\u{301}🇯🇵a");
+        sent.compute_basic();
+        sent.compute_boundaries();
+        assert_eq!(sent.len_char(), 5);
+        let boundaries: Vec<_> = (0..=5).filter(|&i| sent.is_boundary(i)).collect();
+        assert_eq!(boundaries, vec![0, 2, 4, 5]);
+        assert_eq!(sent.next_boundary(0), 2);
+        assert_eq!(sent.next_boundary(2), 4);
+        assert_eq!(sent.next_boundary(4), 5);
+    }
+
+    #[test]
+    fn test_folded() {
+        let mut sent = Sentence::new();
+        sent.set_sentence("aｶﾞき");
+        sent.compute_basic();
+        sent.compute_folded(Folding::new().kana(true).width(true));
+        let folded = sent.folded();
+        assert_eq!(folded.chars(), &['a', 'ガ', 'キ']);
+        assert_eq!(folded.position(1), Some(1));
+        assert_eq!(folded.position(2), None);
+        assert_eq!(folded.position(3), Some(2));
+        assert_eq!(folded.position(4), Some(3));
+        assert_eq!(folded.original_position(2), 3);
+        assert_eq!(folded.original_position(3), 4);
+    }
+
+    #[test]
+    fn test_collapsed() {
+        let mut sent = Sentence::new();
+        sent.set_sentence("すごーーーい");
+        sent.compute_basic();
+        sent.compute_collapsed();
+        let collapsed = sent.collapsed();
+        assert_eq!(collapsed.chars(), &['す', 'ご', 'ー', 'い']);
+        assert_eq!(collapsed.position(2), Some(2));
+        assert_eq!(collapsed.position(3), None);
+        assert_eq!(collapsed.position(5), Some(3));
+        assert_eq!(collapsed.original_position(3), 5);
+        assert_eq!(collapsed.original_position(4), 6);
+    }
 }
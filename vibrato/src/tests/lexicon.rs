@@ -1,12 +1,19 @@
 use crate::dictionary::lexicon::{LexMatch, Lexicon, WordParam};
 use crate::dictionary::word_idx::WordIdx;
-use crate::dictionary::LexType;
+use crate::dictionary::{CostWidth, Folding, LexType};
 
 const LEX_CSV: &str = include_str!("./resources/lex.csv");
 
 #[test]
 fn test_common_prefix_iterator_1() {
-    let lexicon = Lexicon::from_reader(LEX_CSV.as_bytes(), LexType::System).unwrap();
+    let lexicon = Lexicon::from_reader(
+        LEX_CSV.as_bytes(),
+        LexType::System,
+        Folding::default(),
+        false,
+        CostWidth::Bits16,
+    )
+    .unwrap();
     let input: Vec<_> = "東京都に行く".chars().collect();
     let mut it = lexicon.common_prefix_iterator(&input);
     // 東
@@ -41,7 +48,14 @@ fn test_common_prefix_iterator_1() {
 
 #[test]
 fn test_common_prefix_iterator_2() {
-    let lexicon = Lexicon::from_reader(LEX_CSV.as_bytes(), LexType::System).unwrap();
+    let lexicon = Lexicon::from_reader(
+        LEX_CSV.as_bytes(),
+        LexType::System,
+        Folding::default(),
+        false,
+        CostWidth::Bits16,
+    )
+    .unwrap();
     let mut it = lexicon.common_prefix_iterator(&['X']);
     for word_id in 40..46 {
         assert_eq!(
@@ -58,7 +72,14 @@ fn test_common_prefix_iterator_2() {
 
 #[test]
 fn test_get_word_feature() {
-    let lexicon = Lexicon::from_reader(LEX_CSV.as_bytes(), LexType::System).unwrap();
+    let lexicon = Lexicon::from_reader(
+        LEX_CSV.as_bytes(),
+        LexType::System,
+        Folding::default(),
+        false,
+        CostWidth::Bits16,
+    )
+    .unwrap();
     assert_eq!(
         lexicon.word_feature(WordIdx::new(LexType::System, 0)),
         "た,助動詞,*,*,*,助動詞-タ,終止形-一般,タ,た,*,A,*,*,*,*"
@@ -1,4 +1,10 @@
-use crate::dictionary::SystemDictionaryBuilder;
+use crate::dictionary::{
+    ConnectorKind, CostWidth, CustomConnector, Folding, LexType, SystemDictionaryBuilder, WordParam,
+};
+use crate::normalizer::Normalizer;
+use crate::tokenizer::beam::BeamPruning;
+use crate::tokenizer::generator::{UnkCandidate, UnkGenerator, UnkInput};
+use crate::tokenizer::stream::TokenStream;
 use crate::Tokenizer;
 
 const LEX_CSV: &str = include_str!("./resources/lex.csv");
@@ -550,6 +556,10 @@ fn test_tokenize_repeat() {
     worker.tokenize();
     assert_eq!(worker.num_tokens(), 3);
 
+    worker.reset_sentence("東京に行く");
+    worker.try_tokenize().unwrap();
+    assert_eq!(worker.num_tokens(), 3);
+
     worker.reset_sentence("一橋大学大学院");
     worker.tokenize();
     assert_eq!(worker.num_tokens(), 1);
@@ -562,3 +572,1206 @@ fn test_tokenize_repeat() {
     worker.tokenize();
     assert_eq!(worker.num_tokens(), 1);
 }
+
+#[test]
+fn test_tokenize_with_unk_patterns() {
+    let pattern_def = "\
+\"[0-9]{1,3}(,[0-9]{3})+(\\.[0-9]+)?\",8,8,-10000,名詞,数,*,*,*,*,*
+\"https?://[!-~]+\",8,8,-10000,名詞,URL,*,*,*,*,*";
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap()
+    .reset_unk_patterns_from_reader(Some(pattern_def.as_bytes()))
+    .unwrap();
+    assert_eq!(dict.num_unk_patterns(), 2);
+
+    // Checks that the patterns survive serialization.
+    let mut data = vec![];
+    dict.write(&mut data).unwrap();
+    let dict = crate::Dictionary::read(data.as_slice()).unwrap();
+
+    let tokenizer = Tokenizer::new(dict);
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence("1,234.5東京https://example.com/");
+    worker.tokenize();
+    assert_eq!(worker.num_tokens(), 3);
+
+    {
+        let t = worker.token(0);
+        assert_eq!(t.surface(), "1,234.5");
+        assert_eq!(t.range_char(), 0..7);
+        assert_eq!(t.feature(), "名詞,数,*,*,*,*,*");
+    }
+    {
+        let t = worker.token(2);
+        assert_eq!(t.surface(), "https://example.com/");
+        assert_eq!(t.range_char(), 9..29);
+        assert_eq!(t.feature(), "名詞,URL,*,*,*,*,*");
+    }
+}
+
+#[test]
+fn test_tokenize_without_unk_patterns() {
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap()
+    .reset_unk_patterns_from_reader(Some("[0-9]+,8,8,-10000,名詞".as_bytes()))
+    .unwrap()
+    .reset_unk_patterns_from_reader(None::<&[u8]>)
+    .unwrap();
+    assert_eq!(dict.num_unk_patterns(), 0);
+
+    let tokenizer = Tokenizer::new(dict);
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence("1,234.5");
+    worker.tokenize();
+    assert!(worker.num_tokens() > 1);
+}
+
+#[test]
+fn test_tokenize_unk_patterns_long_match() {
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap()
+    .reset_unk_patterns_from_reader(Some("[0-9]+,8,8,-10000,名詞".as_bytes()))
+    .unwrap();
+
+    let tokenizer = Tokenizer::new(dict);
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence("1".repeat(300));
+    worker.tokenize();
+    // Patterns match at most 256 characters.
+    assert_eq!(worker.num_tokens(), 2);
+    assert_eq!(worker.token(0).range_char(), 0..256);
+    assert_eq!(worker.token(1).range_char(), 256..300);
+}
+
+#[test]
+fn test_unk_patterns_invalid() {
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+    let result = dict.reset_unk_patterns_from_reader(Some("[0-9]+,8,10,0,名詞".as_bytes()));
+    assert!(result.is_err());
+
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+    let Err(crate::errors::VibratoError::InvalidFormat(e)) =
+        dict.reset_unk_patterns_from_reader(Some("[0-9]+,8,8,0,名詞\n[0-9+,8,8,0,名詞".as_bytes()))
+    else {
+        panic!("must be an InvalidFormat error");
+    };
+    assert_eq!(e.arg(), "pattern.def");
+    assert_eq!(e.line(), Some(2));
+}
+
+/// Proposes the given spans as named entities.
+struct SpanProposer {
+    spans: Vec<(Vec<char>, String)>,
+}
+
+impl UnkGenerator for SpanProposer {
+    fn gen_words(
+        &self,
+        input: &UnkInput,
+        start_char: usize,
+        _has_matched: bool,
+        push: &mut dyn FnMut(UnkCandidate),
+    ) {
+        let suffix = &input.chars()[start_char..];
+        for (word_id, (span, _)) in self.spans.iter().enumerate() {
+            if suffix.starts_with(span) {
+                push(UnkCandidate {
+                    end_char: start_char + span.len(),
+                    word_id: u32::try_from(word_id).unwrap(),
+                    word_param: self.word_param(u32::try_from(word_id).unwrap()),
+                });
+            }
+        }
+    }
+
+    fn word_param(&self, _word_id: u32) -> WordParam {
+        WordParam::new(7, 7, -20000)
+    }
+
+    fn word_feature(&self, word_id: u32) -> &str {
+        &self.spans[usize::try_from(word_id).unwrap()].1
+    }
+}
+
+#[test]
+fn test_tokenize_kampersanda_with_generator() {
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+
+    let generator = SpanProposer {
+        spans: vec![
+            ("kamper".chars().collect(), "名詞,固有名詞,人名".to_string()),
+            ("sanda".chars().collect(), "名詞,固有名詞,地名".to_string()),
+        ],
+    };
+    let tokenizer = Tokenizer::new(dict).add_unk_generator(generator).unwrap();
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence("kampersanda");
+    worker.tokenize();
+    assert_eq!(worker.num_tokens(), 2);
+
+    {
+        let t = worker.token(0);
+        assert_eq!(t.surface(), "kamper");
+        assert_eq!(t.lex_type(), LexType::Generated);
+        assert_eq!(t.feature(), "名詞,固有名詞,人名");
+        assert_eq!(t.word_cost(), -20000);
+    }
+    {
+        let t = worker.token(1);
+        assert_eq!(t.surface(), "sanda");
+        assert_eq!(t.lex_type(), LexType::Generated);
+        assert_eq!(t.feature(), "名詞,固有名詞,地名");
+        assert_eq!(t.word_cost(), -20000);
+    }
+}
+
+#[test]
+fn test_feature_of_generated_word() {
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+
+    let generator = SpanProposer {
+        spans: vec![("kamper".chars().collect(), "名詞,固有名詞,人名".to_string())],
+    };
+    let tokenizer = Tokenizer::new(dict).add_unk_generator(generator).unwrap();
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence("kamper");
+    worker.tokenize();
+    let word_idx = worker.token(0).word_idx();
+    assert_eq!(word_idx.lex_type, LexType::Generated);
+    assert_eq!(tokenizer.dictionary().word_feature(word_idx), None);
+    assert_eq!(tokenizer.word_feature(word_idx), "名詞,固有名詞,人名");
+}
+
+#[test]
+fn test_tokenize_with_char_prop_override() {
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+
+    let tokenizer = Tokenizer::new(dict);
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence("kamper-sanda");
+    worker.tokenize();
+    assert_eq!(worker.num_tokens(), 3);
+
+    // Treats '-' as an alphabet.
+    let char_def = format!("{CHAR_DEF}\n0x002D ALPHA");
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap()
+    .reset_char_prop_from_reader(char_def.as_bytes())
+    .unwrap();
+
+    let tokenizer = Tokenizer::new(dict);
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence("kamper-sanda");
+    worker.tokenize();
+    assert_eq!(worker.num_tokens(), 1);
+    assert_eq!(worker.token(0).feature(), "名詞,普通名詞,一般,*,*,*");
+
+    // Patches only the range.
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap()
+    .patch_char_prop_from_reader("0x002D ALPHA".as_bytes())
+    .unwrap();
+
+    let tokenizer = Tokenizer::new(dict);
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence("kamper-sanda東京");
+    worker.tokenize();
+    assert_eq!(worker.num_tokens(), 2);
+    assert_eq!(worker.token(0).surface(), "kamper-sanda");
+}
+
+#[test]
+fn test_char_prop_override_undefined_category() {
+    let dict = || {
+        SystemDictionaryBuilder::from_readers(
+            LEX_CSV.as_bytes(),
+            MATRIX_DEF.as_bytes(),
+            CHAR_DEF.as_bytes(),
+            UNK_DEF.as_bytes(),
+        )
+        .unwrap()
+    };
+
+    let char_def = format!("{CHAR_DEF}\nHANGUL 1 1 0\n0xAC00..0xD7A3 HANGUL");
+    let result = dict().reset_char_prop_from_reader(char_def.as_bytes());
+    assert!(result.is_err());
+    let result =
+        dict().patch_char_prop_from_reader("HANGUL 1 1 0\n0xAC00..0xD7A3 HANGUL".as_bytes());
+    assert!(result.is_err());
+
+    // Unknown words are defined for KANJI.
+    let char_def: String = CHAR_DEF
+        .lines()
+        .filter(|line| !line.contains("KANJI"))
+        .map(|line| format!("{line}\n"))
+        .collect();
+    let result = dict().reset_char_prop_from_reader(char_def.as_bytes());
+    assert!(result.is_err());
+
+    // SPACE is used to ignore spaces.
+    let char_def: String = CHAR_DEF
+        .lines()
+        .filter(|line| !line.contains("SPACE"))
+        .map(|line| format!("{line}\n"))
+        .collect();
+    let result = dict().reset_char_prop_from_reader(char_def.as_bytes());
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_tokenize_grapheme_clusters() {
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+
+    // 'e' followed by a combining acute accent, and a flag of two regional indicators.
+    let input = "cafe\u{301}東京🇯🇵";
+
+    let tokenizer = Tokenizer::new(dict);
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence(input);
+    worker.tokenize();
+    let surfaces: Vec<_> = worker.token_iter().map(|t| t.surface()).collect();
+    assert_eq!(surfaces, vec!["cafe", "\u{301}", "東京", "🇯🇵"]);
+
+    let tokenizer = tokenizer.respect_grapheme_clusters(true);
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence(input);
+    worker.tokenize();
+    let surfaces: Vec<_> = worker.token_iter().map(|t| t.surface()).collect();
+    assert_eq!(surfaces, vec!["cafe\u{301}", "東京", "🇯🇵"]);
+    assert_eq!(worker.token(0).feature(), "名詞,普通名詞,一般,*,*,*");
+}
+
+#[test]
+fn test_tokenize_with_normalizer() {
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+
+    let normalizer = Normalizer::new()
+        .nfkc(true)
+        .lowercase(true)
+        .remove_soft_hyphens(true);
+    let tokenizer = Tokenizer::new(dict).normalizer(Some(normalizer));
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence("ＫＡＭＰＥＲＳＡＮＤＡ東\u{AD}京都");
+    worker.tokenize();
+    assert_eq!(worker.num_tokens(), 2);
+
+    {
+        let t = worker.token(0);
+        assert_eq!(t.surface(), "kampersanda");
+        assert_eq!(t.range_char(), 0..11);
+        assert_eq!(t.range_byte(), 0..11);
+        assert_eq!(t.surface_original(), "ＫＡＭＰＥＲＳＡＮＤＡ");
+        assert_eq!(t.range_char_original(), 0..11);
+        assert_eq!(t.range_byte_original(), 0..33);
+    }
+    {
+        let t = worker.token(1);
+        assert_eq!(t.surface(), "東京都");
+        assert_eq!(t.range_char(), 11..14);
+        assert_eq!(t.range_byte(), 11..20);
+        assert_eq!(t.surface_original(), "東\u{AD}京都");
+        assert_eq!(t.range_char_original(), 11..15);
+        assert_eq!(t.range_byte_original(), 33..44);
+        assert_eq!(
+            t.feature(),
+            "東京都,名詞,固有名詞,地名,一般,*,*,トウキョウト,東京都,*,B,5/9,*,5/9,*"
+        );
+    }
+
+    // Only soft hyphens
+    worker.reset_sentence("\u{AD}");
+    worker.tokenize();
+    assert_eq!(worker.num_tokens(), 0);
+}
+
+#[test]
+fn test_tokenize_with_normalizer_splitting_cluster() {
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap()
+    .reset_user_lexicon_from_reader(Some(
+        "株式,6,8,-10000,株式\n会社,6,8,-10000,会社".as_bytes(),
+    ))
+    .unwrap();
+
+    let normalizer = Normalizer::new().nfkc(true);
+    let tokenizer = Tokenizer::new(dict).normalizer(Some(normalizer));
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence("東京都㍿");
+    worker.tokenize();
+    assert_eq!(worker.num_tokens(), 3);
+
+    let surfaces: Vec<_> = worker.token_iter().map(|t| t.surface()).collect();
+    assert_eq!(surfaces, ["東京都", "株式", "会社"]);
+
+    // "㍿" is printed only once.
+    let surfaces: Vec<_> = worker.token_iter().map(|t| t.surface_original()).collect();
+    assert_eq!(surfaces, ["東京都", "㍿", ""]);
+    {
+        let t = worker.token(2);
+        assert_eq!(t.range_char_original(), 4..4);
+        assert_eq!(t.range_byte_original(), 12..12);
+    }
+}
+
+#[test]
+fn test_tokenize_without_normalizer() {
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+
+    let tokenizer = Tokenizer::new(dict);
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence("京都東京都");
+    worker.tokenize();
+    for t in worker.token_iter() {
+        assert_eq!(t.surface_original(), t.surface());
+        assert_eq!(t.range_char_original(), t.range_char());
+        assert_eq!(t.range_byte_original(), t.range_byte());
+    }
+}
+
+#[test]
+fn test_tokenize_folded_matching() {
+    let dict = SystemDictionaryBuilder::new()
+        .folding(Folding::new().kana(true).width(true))
+        .build_from_readers(
+            LEX_CSV.as_bytes(),
+            MATRIX_DEF.as_bytes(),
+            CHAR_DEF.as_bytes(),
+            UNK_DEF.as_bytes(),
+        )
+        .unwrap()
+        .reset_user_lexicon_from_reader(Some(USER_CSV.as_bytes()))
+        .unwrap();
+
+    let folding = Folding::new().kana(true).width(true);
+    let tokenizer = Tokenizer::new(dict).folded_matching(folding).unwrap();
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence("あいうｋａｍｐｅｒｓａｎｄａｳﾞｪﾈﾂｨｱ");
+    worker.tokenize();
+    assert_eq!(worker.num_tokens(), 3);
+
+    {
+        let t = worker.token(0);
+        assert_eq!(t.surface(), "あいう");
+        assert_eq!(t.dictionary_surface(), Some("アイウ"));
+        assert_eq!(t.range_char(), 0..3);
+        assert_eq!(t.lex_type(), LexType::System);
+        assert_eq!(
+            t.feature(),
+            "アイウ,名詞,普通名詞,一般,*,*,*,アイウ,アイウ,*,A,*,*,*,*"
+        );
+    }
+    {
+        let t = worker.token(1);
+        assert_eq!(t.surface(), "ｋａｍｐｅｒｓａｎｄａ");
+        assert_eq!(t.range_char(), 3..14);
+        assert_eq!(t.lex_type(), LexType::User);
+        assert_eq!(t.feature(), "カスタム名詞");
+    }
+    {
+        let t = worker.token(2);
+        assert_eq!(t.surface(), "ｳﾞｪﾈﾂｨｱ");
+        assert_eq!(t.range_char(), 14..21);
+        assert_eq!(t.lex_type(), LexType::User);
+        assert_eq!(t.feature(), "名詞,固有名詞,地名,一般");
+    }
+}
+
+#[test]
+fn test_folded_matching_without_folding() {
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+
+    let folding = Folding::new().kana(true);
+    assert!(Tokenizer::new(dict).folded_matching(folding).is_err());
+
+    let dict = SystemDictionaryBuilder::new()
+        .folding(Folding::new().kana(true))
+        .build_from_readers(
+            LEX_CSV.as_bytes(),
+            MATRIX_DEF.as_bytes(),
+            CHAR_DEF.as_bytes(),
+            UNK_DEF.as_bytes(),
+        )
+        .unwrap();
+    let folding = Folding::new().kana(true).width(true);
+    assert!(Tokenizer::new(dict).folded_matching(folding).is_err());
+}
+
+#[test]
+fn test_tokenize_folded_matching_subset() {
+    let dict = SystemDictionaryBuilder::new()
+        .folding(Folding::new().kana(true).width(true))
+        .build_from_readers(
+            LEX_CSV.as_bytes(),
+            MATRIX_DEF.as_bytes(),
+            CHAR_DEF.as_bytes(),
+            UNK_DEF.as_bytes(),
+        )
+        .unwrap()
+        .reset_user_lexicon_from_reader(Some(USER_CSV.as_bytes()))
+        .unwrap();
+
+    let tokenizer = Tokenizer::new(dict)
+        .folded_matching(Folding::new().kana(true))
+        .unwrap();
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence("あいうｋａｍｐｅｒｓａｎｄａ");
+    worker.tokenize();
+    assert_eq!(worker.token(0).surface(), "あいう");
+    assert_eq!(worker.token(0).lex_type(), LexType::System);
+    // Fullwidth letters are not folded.
+    assert!(worker.token_iter().all(|t| t.feature() != "カスタム名詞"));
+
+    // Exact matches are still found.
+    worker.reset_sentence("アイウkampersanda");
+    worker.tokenize();
+    assert_eq!(worker.num_tokens(), 2);
+    assert_eq!(worker.token(0).lex_type(), LexType::System);
+    assert_eq!(worker.token(1).feature(), "カスタム名詞");
+}
+
+/// Checks that both tokenizers split the sentences into the same tokens.
+fn assert_same_tokens(tokenizer: &Tokenizer, other: &Tokenizer, sentences: &[&str]) {
+    let mut worker = tokenizer.new_worker();
+    let mut other_worker = other.new_worker();
+    for sent in sentences {
+        worker.reset_sentence(sent);
+        worker.tokenize();
+        other_worker.reset_sentence(sent);
+        other_worker.tokenize();
+        assert_eq!(worker.num_tokens(), other_worker.num_tokens());
+        for i in 0..worker.num_tokens() {
+            let (t, u) = (worker.token(i), other_worker.token(i));
+            assert_eq!(t.range_char(), u.range_char());
+            assert_eq!(t.lex_type(), u.lex_type());
+            assert_eq!(t.feature(), u.feature());
+            assert_eq!(t.total_cost(), u.total_cost());
+        }
+    }
+}
+
+#[test]
+fn test_tokenize_connection_cache() {
+    // Dual connectors require at least eight feature templates.
+    let padding = ",*".repeat(8);
+    let right: String = (1..10)
+        .map(|i| format!("{i}\tR{i},{}{padding}\n", i % 3))
+        .collect();
+    let left: String = (1..10)
+        .map(|i| format!("{i}\tL{i},{}{padding}\n", i % 2))
+        .collect();
+    let mut cost = String::new();
+    for r in 1..10 {
+        for l in 1..10 {
+            cost.push_str(&format!(
+                "R{r}/L{l}\t{}\n",
+                (r * 7 + l * 3) % 11 * 100 - 500
+            ));
+        }
+    }
+
+    for dual in [false, true] {
+        let dict = || {
+            SystemDictionaryBuilder::from_readers_with_bigram_info(
+                LEX_CSV.as_bytes(),
+                right.as_bytes(),
+                left.as_bytes(),
+                cost.as_bytes(),
+                CHAR_DEF.as_bytes(),
+                UNK_DEF.as_bytes(),
+                dual,
+            )
+            .unwrap()
+        };
+        let sentences = ["東京都に行った", "京都東京都京都"];
+        let tokenizer = Tokenizer::new(dict());
+        let cached_tokenizer = Tokenizer::new(dict()).connection_cache(16);
+        assert_same_tokens(&tokenizer, &cached_tokenizer, &sentences.repeat(2));
+
+        let worker = tokenizer.new_worker();
+        let mut cached_worker = cached_tokenizer.new_worker();
+        assert_eq!(worker.connection_cache_stats(), None);
+        assert_eq!(cached_worker.connection_cache_stats().unwrap().hits, 0);
+        for sent in sentences.repeat(2) {
+            cached_worker.reset_sentence(sent);
+            cached_worker.tokenize();
+        }
+        let stats = cached_worker.connection_cache_stats().unwrap();
+        assert!(stats.hits > 0);
+        assert!(stats.misses > 0);
+
+        cached_worker.reset_connection_cache_stats();
+        assert_eq!(
+            cached_worker.connection_cache_stats().unwrap(),
+            Default::default()
+        );
+
+        // The verification of beam pruning does not use the cache.
+        let beam = BeamPruning::new().width(Some(1));
+        let stats: Vec<_> = [false, true]
+            .into_iter()
+            .map(|verify| {
+                let tokenizer = Tokenizer::new(dict())
+                    .connection_cache(16)
+                    .beam_pruning(Some(beam.verify(verify)));
+                let mut worker = tokenizer.new_worker();
+                for sent in sentences {
+                    worker.reset_sentence(sent);
+                    worker.tokenize();
+                }
+                assert_eq!(worker.changed_by_pruning().is_some(), verify);
+                worker.connection_cache_stats().unwrap()
+            })
+            .collect();
+        assert_eq!(stats[0], stats[1]);
+    }
+}
+
+#[test]
+fn test_connection_cache_with_matrix() {
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+
+    let tokenizer = Tokenizer::new(dict).connection_cache(16);
+    let worker = tokenizer.new_worker();
+    assert_eq!(worker.connection_cache_stats(), None);
+}
+
+#[test]
+fn test_tokenize_compressed_matrix() {
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+    let compressed_dict = SystemDictionaryBuilder::new()
+        .compress_matrix(Some(0))
+        .build_from_readers(
+            LEX_CSV.as_bytes(),
+            MATRIX_DEF.as_bytes(),
+            CHAR_DEF.as_bytes(),
+            UNK_DEF.as_bytes(),
+        )
+        .unwrap();
+    assert_eq!(compressed_dict.connection_cost_error(), 0);
+    assert_eq!(
+        compressed_dict.memory_usage().connector.kind,
+        ConnectorKind::CompressedMatrix
+    );
+
+    assert_same_tokens(
+        &Tokenizer::new(dict),
+        &Tokenizer::new(compressed_dict),
+        &["東京都に行った", "京都東京都京都"],
+    );
+}
+
+fn tokenize_ranges(tokenizer: &Tokenizer, sent: &str) -> Vec<std::ops::Range<usize>> {
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence(sent);
+    worker.tokenize();
+    worker.token_iter().map(|t| t.range_char()).collect()
+}
+
+#[test]
+fn test_tokenize_beam_pruning() {
+    let sentences = ["東京都に行った", "京都東京都京都", "kampersanda東京都"];
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+    let tokenizer = Tokenizer::new(dict);
+    let exact: Vec<_> = sentences
+        .iter()
+        .map(|sent| tokenize_ranges(&tokenizer, sent))
+        .collect();
+
+    // A wide beam never prunes nodes, so the results are exact.
+    let tokenizer = tokenizer.beam_pruning(Some(BeamPruning::new().width(None)));
+    let mut worker = tokenizer.new_worker();
+    for (sent, exact) in sentences.iter().zip(&exact) {
+        worker.reset_sentence(sent);
+        worker.tokenize();
+        let ranges: Vec<_> = worker.token_iter().map(|t| t.range_char()).collect();
+        assert_eq!(&ranges, exact);
+        assert_eq!(worker.changed_by_pruning(), Some(false));
+    }
+    let stats = worker.pruning_stats();
+    assert_eq!(stats.sentences, 3);
+    assert_eq!(stats.pruned_nodes, 0);
+
+    // The narrowest beam is compared with the exact search.
+    let tokenizer = tokenizer.beam_pruning(Some(
+        BeamPruning::new()
+            .width(Some(1))
+            .threshold(Some(0))
+            .verify(true),
+    ));
+    let mut worker = tokenizer.new_worker();
+    let mut num_changed = 0;
+    for (sent, exact) in sentences.iter().zip(&exact) {
+        worker.reset_sentence(sent);
+        worker.tokenize();
+        let ranges: Vec<_> = worker.token_iter().map(|t| t.range_char()).collect();
+        let changed = worker.changed_by_pruning().unwrap();
+        if changed {
+            num_changed += 1;
+        } else {
+            assert_eq!(&ranges, exact);
+        }
+    }
+    let stats = worker.pruning_stats();
+    assert_eq!(stats.sentences, 3);
+    assert_eq!(stats.pruned_sentences, 3);
+    assert!(stats.pruned_nodes > 0);
+    assert_eq!(stats.changed_sentences, num_changed);
+
+    worker.reset_pruning_stats();
+    assert_eq!(worker.pruning_stats().sentences, 0);
+}
+
+#[test]
+fn test_tokenize_beam_pruning_zero_threshold() {
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+    // Only the threshold prunes nodes, keeping the best one at each position.
+    let tokenizer =
+        Tokenizer::new(dict).beam_pruning(Some(BeamPruning::new().width(None).threshold(Some(0))));
+    let mut worker = tokenizer.new_worker();
+    for sent in ["東京都に行った", "京都東京都京都", "kampersanda東京都"] {
+        worker.reset_sentence(sent);
+        worker.tokenize();
+        let ranges: Vec<_> = worker.token_iter().map(|t| t.range_char()).collect();
+        assert_eq!(ranges.first().unwrap().start, 0);
+        assert_eq!(ranges.last().unwrap().end, sent.chars().count());
+        assert!(ranges.windows(2).all(|w| w[0].end == w[1].start));
+        assert!(worker
+            .token_iter()
+            .all(|t| t.total_cost() < i64::from(i32::MAX)));
+    }
+    assert!(worker.pruning_stats().pruned_nodes > 0);
+}
+
+#[test]
+fn test_beam_pruning_without_verification() {
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+    let tokenizer = Tokenizer::new(dict).beam_pruning(Some(BeamPruning::new().width(Some(1))));
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence("京都東京都京都");
+    worker.tokenize();
+    assert!(worker.num_tokens() > 0);
+    assert_eq!(worker.changed_by_pruning(), None);
+    assert_eq!(worker.pruning_stats().changed_sentences, 0);
+}
+
+#[test]
+fn test_reorder_connection_ids_by_corpus() {
+    let sentences = ["東京都に行く", "", "京都東京都京都", "kampersanda"];
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+    let (reordered_dict, report) = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap()
+    .reorder_connection_ids_by_corpus(sentences)
+    .unwrap();
+    assert_eq!(report.num_sentences, 3);
+    assert!(report.hot_cells_after() <= report.hot_cells_before());
+    assert_eq!(report.left.span_after, report.left.hot_ids + 1);
+
+    // Reorders twice to check that user lexicons are mapped from the original ids.
+    let (reordered_dict, _) = reordered_dict
+        .reorder_connection_ids_by_corpus(["京都"])
+        .unwrap();
+    let dict = dict
+        .reset_user_lexicon_from_reader(Some(USER_CSV.as_bytes()))
+        .unwrap();
+    let reordered_dict = reordered_dict
+        .reset_user_lexicon_from_reader(Some(USER_CSV.as_bytes()))
+        .unwrap();
+
+    assert_same_tokens(
+        &Tokenizer::new(dict),
+        &Tokenizer::new(reordered_dict),
+        &sentences,
+    );
+}
+
+#[test]
+fn test_map_connection_ids_twice() {
+    let matrix_def: String = std::iter::once("3 3\n".to_string())
+        .chain((0..3).flat_map(|r| (0..3).map(move |l| format!("{r} {l} 0\n"))))
+        .collect();
+    let dict = SystemDictionaryBuilder::from_readers(
+        "自然,1,2,0,sizen".as_bytes(),
+        matrix_def.as_bytes(),
+        "DEFAULT 0 1 0".as_bytes(),
+        "DEFAULT,2,1,0,*".as_bytes(),
+    )
+    .unwrap()
+    // Swaps left ids 1 and 2.
+    .map_connection_ids_from_iter([2, 1], [1, 2])
+    .unwrap()
+    // Swaps right ids 1 and 2 in the mapped id space.
+    .map_connection_ids_from_iter([1, 2], [2, 1])
+    .unwrap()
+    // The user lexicon is written with the original ids.
+    .reset_user_lexicon_from_reader(Some("言語,2,1,0,gengo".as_bytes()))
+    .unwrap();
+
+    let tokenizer = Tokenizer::new(dict);
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence("自然言語あ");
+    worker.tokenize();
+    let ids: Vec<_> = worker
+        .token_iter()
+        .map(|t| (t.lex_type(), t.left_id(), t.right_id()))
+        .collect();
+    assert_eq!(
+        ids,
+        vec![
+            (LexType::System, 2, 1),
+            (LexType::User, 1, 2),
+            (LexType::Unknown, 1, 2),
+        ]
+    );
+}
+
+#[test]
+fn test_reorder_connection_ids_by_empty_corpus() {
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+    assert!(dict.reorder_connection_ids_by_corpus([""]).is_err());
+}
+
+#[test]
+fn test_tokenize_wide_costs() {
+    let user_csv = "京都東京都,6,8,-100000,名詞";
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+    assert_eq!(dict.cost_width(), CostWidth::Bits16);
+    assert!(dict
+        .reset_user_lexicon_from_reader(Some(user_csv.as_bytes()))
+        .is_err());
+
+    let wide_dict = SystemDictionaryBuilder::new()
+        .cost_width(CostWidth::Bits32)
+        .build_from_readers(
+            LEX_CSV.as_bytes(),
+            MATRIX_DEF.as_bytes(),
+            CHAR_DEF.as_bytes(),
+            UNK_DEF.as_bytes(),
+        )
+        .unwrap()
+        .reset_user_lexicon_from_reader(Some(user_csv.as_bytes()))
+        .unwrap();
+
+    // Checks that the width survives serialization.
+    let mut data = vec![];
+    wide_dict.write(&mut data).unwrap();
+    let wide_dict = crate::Dictionary::read(data.as_slice()).unwrap();
+    assert_eq!(wide_dict.cost_width(), CostWidth::Bits32);
+
+    let tokenizer = Tokenizer::new(wide_dict);
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence("京都東京都");
+    worker.tokenize();
+    assert_eq!(worker.num_tokens(), 1);
+    assert_eq!(worker.token(0).word_cost(), -100000);
+    assert_eq!(worker.token(0).lex_type(), LexType::User);
+}
+
+#[test]
+fn test_tokenize_costs_near_i32_max() {
+    let lex_csv = "東,1,1,2147483000,東\n京,1,1,2147483000,京\n東京,1,1,2147483647,東京";
+    let matrix_def = "2 2\n0 0 0\n0 1 0\n1 0 0\n1 1 2147483000";
+    let char_def = "DEFAULT 0 1 0";
+    let unk_def = "DEFAULT,0,0,0,*";
+    let dict = SystemDictionaryBuilder::new()
+        .cost_width(CostWidth::Bits32)
+        .build_from_readers(
+            lex_csv.as_bytes(),
+            matrix_def.as_bytes(),
+            char_def.as_bytes(),
+            unk_def.as_bytes(),
+        )
+        .unwrap();
+
+    let tokenizer = Tokenizer::new(dict);
+    let mut worker = tokenizer.new_worker();
+    worker.reset_sentence("東京東京");
+    worker.tokenize();
+    // Wrapping sums of the costs would prefer the single-character words.
+    assert_eq!(worker.num_tokens(), 2);
+    assert_eq!(worker.token(0).surface(), "東京");
+    assert_eq!(worker.token(0).total_cost(), 2147483647);
+    assert_eq!(worker.token(1).surface(), "東京");
+    assert_eq!(worker.token(1).total_cost(), 2 * 2147483647 + 2147483000);
+}
+
+#[test]
+fn test_compress_wide_matrix() {
+    let result = SystemDictionaryBuilder::new()
+        .cost_width(CostWidth::Bits32)
+        .compress_matrix(Some(0))
+        .build_from_readers(
+            LEX_CSV.as_bytes(),
+            MATRIX_DEF.as_bytes(),
+            CHAR_DEF.as_bytes(),
+            UNK_DEF.as_bytes(),
+        );
+    assert!(result.is_err());
+}
+
+/// Custom connector holding the costs of `matrix.def`.
+struct TableConnector {
+    costs: Vec<Vec<i32>>,
+}
+
+impl TableConnector {
+    fn new() -> Self {
+        let mut lines = MATRIX_DEF.lines();
+        let size: usize = lines
+            .next()
+            .unwrap()
+            .split(' ')
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let mut costs = vec![vec![0; size]; size];
+        for line in lines {
+            let cols: Vec<i32> = line.split(' ').map(|c| c.parse().unwrap()).collect();
+            costs[cols[0] as usize][cols[1] as usize] = cols[2];
+        }
+        Self { costs }
+    }
+}
+
+impl CustomConnector for TableConnector {
+    fn num_left(&self) -> usize {
+        self.costs[0].len()
+    }
+
+    fn num_right(&self) -> usize {
+        self.costs.len()
+    }
+
+    fn cost(&self, right_id: u16, left_id: u16) -> i32 {
+        self.costs[usize::from(right_id)][usize::from(left_id)]
+    }
+
+    fn map_connection_ids(&mut self, left_map: &[u16], right_map: &[u16]) {
+        let mut costs = self.costs.clone();
+        for (right_id, row) in self.costs.iter().enumerate() {
+            for (left_id, &cost) in row.iter().enumerate() {
+                costs[usize::from(right_map[right_id])][usize::from(left_map[left_id])] = cost;
+            }
+        }
+        self.costs = costs;
+    }
+}
+
+#[test]
+fn test_tokenize_custom_connector() {
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+    let custom_dict = SystemDictionaryBuilder::new()
+        .build_from_readers_with_connector(
+            LEX_CSV.as_bytes(),
+            TableConnector::new(),
+            CHAR_DEF.as_bytes(),
+            UNK_DEF.as_bytes(),
+        )
+        .unwrap();
+    assert_eq!(
+        custom_dict.memory_usage().connector.kind,
+        ConnectorKind::Custom
+    );
+    assert!(custom_dict.write(vec![]).is_err());
+
+    // The connector is mapped in the same manner as the dictionary.
+    let lmap = [2, 3, 4, 5, 6, 7, 8, 9, 1];
+    let rmap = [9, 8, 7, 6, 5, 4, 3, 2, 1];
+    let mapped_dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap()
+    .map_connection_ids_from_iter(lmap, rmap)
+    .unwrap()
+    .reset_connector(TableConnector::new())
+    .unwrap();
+
+    let tokenizer = Tokenizer::new(dict);
+    for custom_dict in [custom_dict, mapped_dict] {
+        assert_same_tokens(
+            &tokenizer,
+            &Tokenizer::new(custom_dict),
+            &["東京都に行った", "京都東京都京都"],
+        );
+    }
+}
+
+#[test]
+fn test_custom_connector_with_few_ids() {
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+    let mut connector = TableConnector::new();
+    connector.costs.truncate(5);
+
+    assert!(dict.reset_connector(connector).is_err());
+}
+
+/// Tokenizes each line of `input`, returning the byte ranges in `input` and the features.
+fn tokenize_lines(tokenizer: &Tokenizer, input: &str) -> Vec<(std::ops::Range<usize>, String)> {
+    let mut worker = tokenizer.new_worker();
+    let mut tokens = vec![];
+    let mut offset = 0;
+    for line in input.split_inclusive('\n') {
+        worker.reset_sentence(line.trim_end_matches('\n'));
+        worker.tokenize();
+        for t in worker.token_iter() {
+            let range = t.range_byte_original();
+            tokens.push((
+                offset + range.start..offset + range.end,
+                t.feature_expanded().into_owned(),
+            ));
+        }
+        offset += line.len();
+    }
+    tokens
+}
+
+#[test]
+fn test_token_stream() {
+    let line = "東京都に行く。京都に行った。東京 都。".repeat(50);
+    let input = format!("{line}\n\nkampersanda\n{line}");
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+    let tokenizer = Tokenizer::new(dict);
+    let expected = tokenize_lines(&tokenizer, &input);
+
+    // A small buffer splits UTF-8 sequences.
+    let reader = std::io::BufReader::with_capacity(5, input.as_bytes());
+    let mut stream = TokenStream::new(tokenizer.new_worker(), reader)
+        .chunk_len(20)
+        .lookahead(8);
+    let mut tokens = vec![];
+    let mut num_chunks = 0;
+    let mut num_lines = 0;
+    while stream.next_chunk().unwrap() {
+        for i in 0..stream.num_tokens() {
+            let range = stream.range_byte(i);
+            assert_eq!(&input[range.clone()], stream.token(i).surface());
+            tokens.push((range, stream.token(i).feature_expanded().into_owned()));
+        }
+        num_chunks += 1;
+        num_lines += usize::from(stream.ends_line());
+    }
+    assert_eq!(tokens, expected);
+    assert_eq!(num_lines, 4);
+    assert!(num_chunks > 10);
+    assert_eq!(stream.num_forced_cuts(), 0);
+}
+
+#[test]
+fn test_token_stream_with_options() {
+    let line = "ＫＡＭＰＥＲＳＡＮＤＡ東京都  に 行く。".repeat(30);
+    let input = format!("{line}\r\n{line}");
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+    let tokenizer = Tokenizer::new(dict)
+        .ignore_space(true)
+        .unwrap()
+        .normalizer(Some(Normalizer::new().nfkc(true).lowercase(true)));
+    let expected = tokenize_lines(&tokenizer, &input.replace('\r', ""));
+
+    let mut stream = TokenStream::new(tokenizer.new_worker(), input.as_bytes())
+        .chunk_len(30)
+        .lookahead(12);
+    let mut tokens = vec![];
+    let mut num_chunks = 0;
+    while stream.next_chunk().unwrap() {
+        for i in 0..stream.num_tokens() {
+            tokens.push((
+                stream.range_byte(i),
+                stream.token(i).feature_expanded().into_owned(),
+            ));
+        }
+        num_chunks += 1;
+    }
+    // Removes the carriage return.
+    let cr = line.len();
+    let tokens: Vec<_> = tokens
+        .into_iter()
+        .map(|(r, f)| {
+            if r.start > cr {
+                (r.start - 1..r.end - 1, f)
+            } else {
+                (r, f)
+            }
+        })
+        .collect();
+    assert_eq!(tokens, expected);
+    assert!(num_chunks > 4);
+}
+
+#[test]
+fn test_token_stream_invalid_utf8() {
+    let dict = SystemDictionaryBuilder::from_readers(
+        LEX_CSV.as_bytes(),
+        MATRIX_DEF.as_bytes(),
+        CHAR_DEF.as_bytes(),
+        UNK_DEF.as_bytes(),
+    )
+    .unwrap();
+    let tokenizer = Tokenizer::new(dict);
+    let input = "東京都".as_bytes();
+    let mut stream = TokenStream::new(tokenizer.new_worker(), &input[..4]);
+    assert!(stream.next_chunk().is_err());
+}
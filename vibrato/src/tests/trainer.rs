@@ -1,5 +1,6 @@
 use std::io::BufRead;
 
+use crate::dictionary::{CostWidth, SystemDictionaryBuilder};
 use crate::trainer::{Corpus, Trainer, TrainerConfig};
 use crate::utils;
 
@@ -240,3 +241,56 @@ fn test_user_lex_format() {
         assert_eq!(result_row[4..], ["名詞", "固有名詞", "地名", "一般"]);
     }
 }
+
+#[test]
+fn test_cost_width_32() {
+    let config = TrainerConfig::from_readers(
+        TRAIN_LEX_CSV,
+        CHAR_DEF,
+        TRAIN_UNK_DEF,
+        FEATURE_DEF,
+        REWRITE_DEF,
+    )
+    .unwrap();
+    let corpus = Corpus::from_reader(CORPUS_TXT).unwrap();
+    let trainer = Trainer::new(config).unwrap().max_iter(5);
+
+    let mut lex = vec![];
+    let mut matrix = vec![];
+    let mut unk = vec![];
+    let mut user_lex = vec![];
+    let mut model = trainer.train(corpus).unwrap();
+    model.set_cost_width(CostWidth::Bits32);
+    model.read_user_lexicon(USER_CSV).unwrap();
+    model
+        .write_dictionary(&mut lex, &mut matrix, &mut unk, &mut user_lex)
+        .unwrap();
+
+    // Weights are scaled beyond the 16-bit range.
+    let max_cost = matrix
+        .lines()
+        .skip(1)
+        .map(|line| {
+            let line = line.unwrap();
+            line.split(' ')
+                .nth(2)
+                .unwrap()
+                .parse::<i32>()
+                .unwrap()
+                .abs()
+        })
+        .max()
+        .unwrap();
+    assert!(max_cost > i32::from(i16::MAX));
+
+    assert!(
+        SystemDictionaryBuilder::from_readers(&lex[..], &matrix[..], CHAR_DEF, &unk[..]).is_err()
+    );
+    let dict = SystemDictionaryBuilder::new()
+        .cost_width(CostWidth::Bits32)
+        .build_from_readers(&lex[..], &matrix[..], CHAR_DEF, &unk[..])
+        .unwrap()
+        .reset_user_lexicon_from_reader(Some(&user_lex[..]))
+        .unwrap();
+    assert_eq!(dict.cost_width(), CostWidth::Bits32);
+}
@@ -1,7 +1,8 @@
 //! Container of resultant tokens.
+use std::borrow::Cow;
 use std::ops::Range;
 
-use crate::dictionary::{word_idx::WordIdx, LexType};
+use crate::dictionary::{unknown, word_idx::WordIdx, LexType};
 use crate::tokenizer::worker::Worker;
 
 /// Resultant token.
@@ -20,7 +21,7 @@ impl<'w, 't> Token<'w, 't> {
     #[inline(always)]
     pub fn range_char(&self) -> Range<usize> {
         let (end_word, node) = &self.worker.top_nodes[self.index];
-        node.start_word..*end_word
+        node.start_word as usize..*end_word
     }
 
     /// Gets the position range of the token in bytes.
@@ -28,7 +29,7 @@ impl<'w, 't> Token<'w, 't> {
     pub fn range_byte(&self) -> Range<usize> {
         let sent = &self.worker.sent;
         let (end_word, node) = &self.worker.top_nodes[self.index];
-        sent.byte_position(node.start_word)..sent.byte_position(*end_word)
+        sent.byte_position(node.start_word as usize)..sent.byte_position(*end_word)
     }
 
     /// Gets the surface string of the token.
@@ -37,6 +38,47 @@ impl<'w, 't> Token<'w, 't> {
         let sent = &self.worker.sent;
         &sent.raw()[self.range_byte()]
     }
+    /// Gets the position range of the token in characters of the original input.
+    ///
+    /// This is different from [`Self::range_char()`] only if a normalizer is set
+    /// with [`Tokenizer::normalizer()`](crate::Tokenizer::normalizer).
+    /// If the normalized text of an original grapheme cluster is split into several tokens,
+    /// the cluster is assigned to the first one,
+    /// and the others are mapped to the empty range at the end of the cluster.
+    #[inline(always)]
+    pub fn range_char_original(&self) -> Range<usize> {
+        let range_char = self.range_char();
+        if self.worker.tokenizer.normalizer_ref().is_some() {
+            self.worker.alignment.range_char(range_char)
+        } else {
+            range_char
+        }
+    }
+
+    /// Gets the position range of the token in bytes of the original input.
+    ///
+    /// See [`Self::range_char_original()`] for details.
+    #[inline(always)]
+    pub fn range_byte_original(&self) -> Range<usize> {
+        if self.worker.tokenizer.normalizer_ref().is_some() {
+            self.worker.alignment.range_byte(self.range_char())
+        } else {
+            self.range_byte()
+        }
+    }
+
+    /// Gets the surface string of the token in the original input.
+    ///
+    /// See [`Self::range_char_original()`] for details.
+    #[inline(always)]
+    pub fn surface_original(&self) -> &'w str {
+        if self.worker.tokenizer.normalizer_ref().is_some() {
+            &self.worker.original[self.range_byte_original()]
+        } else {
+            self.surface()
+        }
+    }
+
     /// Gets the word index of the token.
     #[inline(always)]
     pub fn word_idx(&self) -> WordIdx {
@@ -45,12 +87,48 @@ impl<'w, 't> Token<'w, 't> {
     }
 
     /// Gets the feature string of the token.
+    ///
+    /// Placeholders in the features of unknown words, such as `{surface}`, are returned
+    /// as they are. Use [`Self::feature_expanded()`] to expand them.
     #[inline(always)]
     pub fn feature(&self) -> &'t str {
+        self.worker.tokenizer.word_feature(self.word_idx())
+    }
+
+    /// Gets the feature string of the token, expanding placeholders.
+    ///
+    /// For unknown words, the following placeholders in the features of `unk.def`
+    /// are expanded with the surface:
+    ///
+    ///  - `{surface}`: the surface as it is,
+    ///  - `{hiragana}`: the surface whose katakana are converted into hiragana,
+    ///  - `{katakana}`: the surface whose hiragana are converted into katakana, and
+    ///  - `{lowercase}`: the lowercased surface.
+    ///
+    /// The feature is borrowed unless it contains a placeholder.
+    pub fn feature_expanded(&self) -> Cow<'t, str> {
+        let feature = self.feature();
+        if self.lex_type() == LexType::Unknown {
+            unknown::expand_feature(feature, self.surface())
+        } else {
+            Cow::Borrowed(feature)
+        }
+    }
+
+    /// Gets the surface of the dictionary entry, which can differ from [`Self::surface()`]
+    /// for a token matched with
+    /// [`Tokenizer::typo_tolerance()`](crate::Tokenizer::typo_tolerance) or
+    /// [`Tokenizer::folded_matching()`](crate::Tokenizer::folded_matching).
+    ///
+    /// `None` is returned for unknown words or if the dictionary is built without
+    /// [`SystemDictionaryBuilder::typo_index()`](crate::SystemDictionaryBuilder::typo_index)
+    /// or [`SystemDictionaryBuilder::folding()`](crate::SystemDictionaryBuilder::folding).
+    #[inline(always)]
+    pub fn dictionary_surface(&self) -> Option<&'t str> {
         self.worker
             .tokenizer
             .dictionary()
-            .word_feature(self.word_idx())
+            .word_surface(self.word_idx())
     }
 
     /// Gets the lexicon type where the token is from.
@@ -75,18 +153,14 @@ impl<'w, 't> Token<'w, 't> {
 
     /// Gets the word cost of the token's node.
     #[inline(always)]
-    pub fn word_cost(&self) -> i16 {
+    pub fn word_cost(&self) -> i32 {
         let (_, node) = &self.worker.top_nodes[self.index];
-        self.worker
-            .tokenizer
-            .dictionary()
-            .word_param(node.word_idx())
-            .word_cost
+        self.worker.tokenizer.word_param(node.word_idx()).word_cost
     }
 
     /// Gets the total cost from BOS to the token's node.
     #[inline(always)]
-    pub fn total_cost(&self) -> i32 {
+    pub fn total_cost(&self) -> i64 {
         let (_, node) = &self.worker.top_nodes[self.index];
         node.min_cost
     }
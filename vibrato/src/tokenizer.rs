@@ -1,20 +1,43 @@
 //! Viterbi-based tokenizer.
+pub mod beam;
+pub mod cache;
+pub mod generator;
 pub(crate) mod lattice;
+pub mod stream;
+pub mod typo;
 pub mod worker;
 
+use crate::common::MAX_SENTENCE_LENGTH;
 use crate::dictionary::connector::{ConnectorCost, ConnectorWrapper};
-use crate::dictionary::Dictionary;
+use crate::dictionary::lexicon::{LexMatch, Lexicon};
+use crate::dictionary::word_idx::WordIdx;
+use crate::dictionary::{Dictionary, Folding, LexType, WordParam};
 use crate::errors::{Result, VibratoError};
+use crate::normalizer::Normalizer;
 use crate::sentence::Sentence;
+use crate::tokenizer::beam::BeamPruning;
+use crate::tokenizer::cache::{CachedConnector, ConnectionCache};
+use crate::tokenizer::generator::{UnkGenerator, UnkInput, MAX_GENERATED_WORD_ID};
 use crate::tokenizer::lattice::Lattice;
+use crate::tokenizer::typo::TypoTolerance;
 use crate::tokenizer::worker::Worker;
 
+/// The maximum number of generators added to a tokenizer.
+pub const MAX_UNK_GENERATORS: usize = 256;
+
 /// Tokenizer.
 pub struct Tokenizer {
     dict: Dictionary,
+    unk_generators: Vec<Box<dyn UnkGenerator>>,
+    grapheme_clusters: bool,
+    folding: Folding,
+    typo_tolerance: Option<TypoTolerance>,
+    normalizer: Option<Normalizer>,
     // For the MeCab compatibility
-    space_cateset: Option<u32>,
+    space_cate_id: Option<u32>,
     max_grouping_len: Option<usize>,
+    connection_cache: usize,
+    beam_pruning: Option<BeamPruning>,
 }
 
 impl Tokenizer {
@@ -26,8 +49,15 @@ impl Tokenizer {
     pub const fn new(dict: Dictionary) -> Self {
         Self {
             dict,
-            space_cateset: None,
+            unk_generators: vec![],
+            grapheme_clusters: false,
+            folding: Folding::new(),
+            typo_tolerance: None,
+            normalizer: None,
+            space_cate_id: None,
             max_grouping_len: None,
+            connection_cache: 0,
+            beam_pruning: None,
         }
     }
 
@@ -47,9 +77,9 @@ impl Tokenizer {
                     "SPACE is not defined in the input dictionary (i.e., char.def).",
                 )
             })?;
-            self.space_cateset = Some(1 << cate_id);
+            self.space_cate_id = Some(cate_id);
         } else {
-            self.space_cateset = None;
+            self.space_cate_id = None;
         }
         Ok(self)
     }
@@ -73,29 +103,305 @@ impl Tokenizer {
         self
     }
 
+    /// Prevents token boundaries from being placed inside extended grapheme clusters,
+    /// such as emoji ZWJ sequences, flags, and characters with combining marks.
+    ///
+    /// Words in the lexicons that end inside a cluster are ignored,
+    /// and unknown words are extended to the end of the cluster if necessary.
+    /// By default, this option is disabled, and tokens are split at any character.
+    pub const fn respect_grapheme_clusters(mut self, yes: bool) -> Self {
+        self.grapheme_clusters = yes;
+        self
+    }
+
+    #[inline(always)]
+    pub(crate) const fn respects_grapheme_clusters(&self) -> bool {
+        self.grapheme_clusters
+    }
+
+    /// Additionally matches words in the lexicons that are the same as the input after
+    /// folding, e.g., finding katakana entries from hiragana input.
+    ///
+    /// Words exactly matching the input are still found. Unlike [`Self::normalizer()`],
+    /// the input sentence is not changed; the surfaces of tokens are those in the input,
+    /// and the features are those of the matched entries.
+    /// Unknown words are handled with the input characters.
+    ///
+    /// # Arguments
+    ///
+    ///  - `folding`: Folding used for matching, which must be a subset of that specified in
+    ///    [`SystemDictionaryBuilder::folding()`](crate::SystemDictionaryBuilder::folding).
+    ///    If it is empty, only exact matches are used.
+    ///
+    /// # Errors
+    ///
+    /// [`VibratoError`] is returned when the dictionary is built without the folding.
+    pub fn folded_matching(mut self, folding: Folding) -> Result<Self> {
+        if !self.dict.folding().contains(folding) {
+            return Err(VibratoError::invalid_argument(
+                "folding",
+                "The dictionary is built without the folding.",
+            ));
+        }
+        self.folding = folding;
+        Ok(self)
+    }
+
+    #[inline(always)]
+    pub(crate) const fn matches_folded(&self) -> bool {
+        !self.folding.is_empty()
+    }
+
+    /// Inserts words in the lexicons matching the input approximately into the lattice,
+    /// for noisy text with typos and elongations.
+    ///
+    /// The surfaces of the matched entries are available via
+    /// [`Token::dictionary_surface()`](crate::token::Token::dictionary_surface).
+    /// The candidates do not prevent unknown words from being generated.
+    ///
+    /// # Arguments
+    ///
+    ///  - `typo`: Settings of the lookup. If `None`, only exact matches are used.
+    ///
+    /// # Errors
+    ///
+    /// [`VibratoError`] is returned when the dictionary is built without
+    /// [`SystemDictionaryBuilder::typo_index()`](crate::SystemDictionaryBuilder::typo_index).
+    pub fn typo_tolerance(mut self, typo: Option<TypoTolerance>) -> Result<Self> {
+        if typo.is_some() && !self.dict.has_typo_index() {
+            return Err(VibratoError::invalid_argument(
+                "dict",
+                "The dictionary is built without the typo index.",
+            ));
+        }
+        self.typo_tolerance = typo;
+        Ok(self)
+    }
+
+    #[inline(always)]
+    pub(crate) const fn typo_tolerance_ref(&self) -> Option<&TypoTolerance> {
+        self.typo_tolerance.as_ref()
+    }
+
+    /// Normalizes input sentences before tokenization.
+    ///
+    /// The offsets and surfaces of tokens refer to the normalized text, and those in the
+    /// original text are available via [`Token::range_byte_original()`] and so on.
+    ///
+    /// # Arguments
+    ///
+    ///  - `normalizer`: Normalizer to be applied. If `None`, sentences are not normalized.
+    ///
+    /// [`Token::range_byte_original()`]: crate::token::Token::range_byte_original
+    pub const fn normalizer(mut self, normalizer: Option<Normalizer>) -> Self {
+        self.normalizer = normalizer;
+        self
+    }
+
+    #[inline(always)]
+    pub(crate) const fn normalizer_ref(&self) -> Option<&Normalizer> {
+        self.normalizer.as_ref()
+    }
+
+    /// Enables a cache of connection costs in each worker.
+    ///
+    /// Costs of the connectors built with bigram information, which are computed from
+    /// feature weights on every lookup, are cached for pairs of connection ids.
+    /// Costs of custom connectors are cached in the same manner.
+    /// Since a small set of pairs appears frequently in real text, the cache reduces
+    /// the tokenization time without the memory of the full matrix.
+    /// The statistics are available via [`Worker::connection_cache_stats()`].
+    /// The cache is not used for dictionaries with a (compressed) connection matrix.
+    ///
+    /// # Arguments
+    ///
+    ///  - `capacity`: Number of cached pairs, rounded up to a power of two.
+    ///    If `0`, the cache is disabled (default).
+    pub const fn connection_cache(mut self, capacity: usize) -> Self {
+        self.connection_cache = capacity;
+        self
+    }
+
+    /// Prunes the lattice with a beam to bound the time and memory per character
+    /// for very long inputs.
+    ///
+    /// The statistics are available via [`Worker::pruning_stats()`].
+    ///
+    /// # Arguments
+    ///
+    ///  - `beam`: Settings of the pruning. If `None`, the exact search is performed (default).
+    pub const fn beam_pruning(mut self, beam: Option<BeamPruning>) -> Self {
+        self.beam_pruning = beam;
+        self
+    }
+
+    #[inline(always)]
+    pub(crate) const fn beam_pruning_ref(&self) -> Option<&BeamPruning> {
+        self.beam_pruning.as_ref()
+    }
+
+    /// Creates a cache of connection costs if enabled and useful for the connector.
+    pub(crate) fn new_connection_cache(&self) -> Option<ConnectionCache> {
+        match self.dict.connector() {
+            ConnectorWrapper::Matrix(_)
+            | ConnectorWrapper::CompressedMatrix(_)
+            | ConnectorWrapper::WideMatrix(_) => None,
+            ConnectorWrapper::Raw(_) | ConnectorWrapper::Dual(_) | ConnectorWrapper::Custom(_) => {
+                (self.connection_cache != 0).then(|| ConnectionCache::new(self.connection_cache))
+            }
+        }
+    }
+
+    /// Adds a generator of unknown-word candidates.
+    ///
+    /// Words generated by the generator have [`LexType::Generated`].
+    ///
+    /// # Arguments
+    ///
+    ///  - `generator`: Generator whose words have left/right ids valid in the dictionary.
+    ///    The ids are those before editing with
+    ///    [`Dictionary::map_connection_ids_from_iter()`].
+    ///
+    /// # Errors
+    ///
+    /// [`VibratoError`] is returned when [`MAX_UNK_GENERATORS`] generators have already
+    /// been added.
+    pub fn add_unk_generator<G>(mut self, generator: G) -> Result<Self>
+    where
+        G: UnkGenerator + 'static,
+    {
+        if self.unk_generators.len() == MAX_UNK_GENERATORS {
+            return Err(VibratoError::invalid_argument(
+                "generator",
+                format!("The number of generators must be no more than {MAX_UNK_GENERATORS}."),
+            ));
+        }
+        self.unk_generators.push(Box::new(generator));
+        Ok(self)
+    }
+
     /// Gets the reference to the dictionary.
     pub const fn dictionary(&self) -> &Dictionary {
         &self.dict
     }
 
+    /// Gets the word parameter, including those of generated words.
+    #[inline(always)]
+    pub(crate) fn word_param(&self, word_idx: WordIdx) -> WordParam {
+        self.dict.word_param(word_idx).unwrap_or_else(|| {
+            let (generator, word_id) = self.generated_word(word_idx);
+            generator.word_param(word_id)
+        })
+    }
+
+    /// Gets the feature string of the word, including those of words generated by
+    /// [`Self::add_unk_generator()`].
+    ///
+    /// # Panics
+    ///
+    /// It panics if `word_idx` is not given by a worker of this tokenizer.
+    #[inline(always)]
+    pub fn word_feature(&self, word_idx: WordIdx) -> &str {
+        self.dict.word_feature(word_idx).unwrap_or_else(|| {
+            let (generator, word_id) = self.generated_word(word_idx);
+            generator.word_feature(word_id)
+        })
+    }
+
+    #[inline(always)]
+    fn generated_word(&self, word_idx: WordIdx) -> (&dyn UnkGenerator, u32) {
+        let i = usize::try_from(word_idx.word_id / MAX_GENERATED_WORD_ID).unwrap();
+        (
+            self.unk_generators[i].as_ref(),
+            word_idx.word_id % MAX_GENERATED_WORD_ID,
+        )
+    }
+
     /// Creates a new worker.
     pub fn new_worker(&self) -> Worker<'_> {
         Worker::new(self)
     }
 
-    pub(crate) fn build_lattice(&self, sent: &Sentence, lattice: &mut Lattice) {
-        match self.dict.connector() {
-            ConnectorWrapper::Matrix(c) => self.build_lattice_inner(sent, lattice, c),
-            ConnectorWrapper::Raw(c) => self.build_lattice_inner(sent, lattice, c),
-            ConnectorWrapper::Dual(c) => self.build_lattice_inner(sent, lattice, c),
+    /// Takes back the dictionary.
+    pub(crate) fn into_dictionary(self) -> Dictionary {
+        self.dict
+    }
+
+    pub(crate) fn build_lattice(
+        &self,
+        sent: &Sentence,
+        lattice: &mut Lattice,
+        typo_candidates: &mut Vec<(WordIdx, usize)>,
+        cache: Option<&ConnectionCache>,
+        beam: Option<BeamPruning>,
+    ) -> Result<()> {
+        if sent.len_char() > MAX_SENTENCE_LENGTH {
+            return Err(VibratoError::invalid_argument(
+                "input",
+                format!("The sentence must be at most {MAX_SENTENCE_LENGTH} characters."),
+            ));
         }
+        match (self.dict.connector(), cache) {
+            (ConnectorWrapper::Matrix(c), _) => {
+                self.build_lattice_inner(sent, lattice, typo_candidates, c, beam)
+            }
+            (ConnectorWrapper::CompressedMatrix(c), _) => {
+                self.build_lattice_inner(sent, lattice, typo_candidates, c, beam)
+            }
+            (ConnectorWrapper::WideMatrix(c), _) => {
+                self.build_lattice_inner(sent, lattice, typo_candidates, c, beam)
+            }
+            (ConnectorWrapper::Raw(c), None) => {
+                self.build_lattice_inner(sent, lattice, typo_candidates, c, beam)
+            }
+            (ConnectorWrapper::Raw(c), Some(cache)) => self.build_lattice_inner(
+                sent,
+                lattice,
+                typo_candidates,
+                &CachedConnector::new(c, cache),
+                beam,
+            ),
+            (ConnectorWrapper::Dual(c), None) => {
+                self.build_lattice_inner(sent, lattice, typo_candidates, c, beam)
+            }
+            (ConnectorWrapper::Dual(c), Some(cache)) => self.build_lattice_inner(
+                sent,
+                lattice,
+                typo_candidates,
+                &CachedConnector::new(c, cache),
+                beam,
+            ),
+            (ConnectorWrapper::Custom(c), None) => {
+                self.build_lattice_inner(sent, lattice, typo_candidates, c, beam)
+            }
+            (ConnectorWrapper::Custom(c), Some(cache)) => self.build_lattice_inner(
+                sent,
+                lattice,
+                typo_candidates,
+                &CachedConnector::new(c, cache),
+                beam,
+            ),
+        }
+        if lattice.overflowed() {
+            return Err(VibratoError::invalid_argument(
+                "input",
+                "The sentence has too many candidate words to be tokenized.",
+            ));
+        }
+        Ok(())
     }
 
-    fn build_lattice_inner<C>(&self, sent: &Sentence, lattice: &mut Lattice, connector: &C)
-    where
+    fn build_lattice_inner<C>(
+        &self,
+        sent: &Sentence,
+        lattice: &mut Lattice,
+        typo_candidates: &mut Vec<(WordIdx, usize)>,
+        connector: &C,
+        beam: Option<BeamPruning>,
+    ) where
         C: ConnectorCost,
     {
-        lattice.reset(sent.len_char());
+        lattice.reset(sent.len_char(), beam);
 
         // These variables indicate the starting character positions of words currently stored
         // in the lattice. If ignore_space() is unset, these always have the same values, and
@@ -112,10 +418,16 @@ impl Tokenizer {
                 start_node = start_word;
                 continue;
             }
+            lattice.finalize(start_node);
 
             // on mecab compatible mode
-            if let Some(space_cateset) = self.space_cateset {
-                let is_space = (sent.char_info(start_node).cate_idset() & space_cateset) != 0;
+            if let Some(space_cate_id) = self.space_cate_id {
+                // Spaces followed by combining marks are not skipped.
+                let is_space = self
+                    .dict
+                    .char_prop()
+                    .has_category(sent.char_info_id(start_node), space_cate_id)
+                    && sent.is_boundary(start_node + sent.groupable(start_node));
                 start_word += if !is_space {
                     0
                 } else {
@@ -129,73 +441,245 @@ impl Tokenizer {
                 break;
             }
 
-            self.add_lattice_edges(sent, lattice, start_node, start_word, connector);
+            self.add_lattice_edges(
+                sent,
+                lattice,
+                typo_candidates,
+                start_node,
+                start_word,
+                connector,
+            );
 
             start_word += 1;
             start_node = start_word;
+            if lattice.overflowed() {
+                return;
+            }
         }
 
         lattice.insert_eos(start_node, connector);
     }
 
-    fn add_lattice_edges<C>(
+    /// Inserts words in the lexicon starting at `start_word`,
+    /// returning `true` if any word is inserted.
+    #[allow(clippy::too_many_arguments)]
+    fn add_lexicon_edges<C>(
         &self,
+        lexicon: &Lexicon,
         sent: &Sentence,
         lattice: &mut Lattice,
+        typo_candidates: &mut Vec<(WordIdx, usize)>,
         start_node: usize,
         start_word: usize,
         connector: &C,
-    ) where
+    ) -> bool
+    where
         C: ConnectorCost,
     {
         let mut has_matched = false;
+        let mut insert = |m: LexMatch, end_word: usize| {
+            debug_assert!(end_word <= sent.len_char());
+            if !sent.is_boundary(end_word) {
+                return;
+            }
+            lattice.insert_node(
+                start_node,
+                start_word,
+                end_word,
+                m.word_idx,
+                m.word_param,
+                connector,
+            );
+            has_matched = true;
+        };
 
         let suffix = &sent.chars()[start_word..];
+        for m in lexicon.common_prefix_iterator(suffix) {
+            let end_word = start_word + m.end_char;
+            insert(m, end_word);
+        }
 
-        if let Some(user_lexicon) = self.dict.user_lexicon() {
-            for m in user_lexicon.common_prefix_iterator(suffix) {
-                debug_assert!(start_word + m.end_char <= sent.len_char());
-                lattice.insert_node(
-                    start_node,
-                    start_word,
-                    start_word + m.end_char,
-                    m.word_idx,
-                    m.word_param,
-                    connector,
-                );
-                has_matched = true;
+        if self.matches_folded() {
+            let folded = sent.folded();
+            if let Some(start_folded) = folded.position(start_word) {
+                let suffix = &folded.chars()[start_folded..];
+                for m in lexicon.folded_common_prefix_iterator(suffix) {
+                    let end_word = folded.original_position(start_folded + m.end_char);
+                    let input = &sent.chars()[start_word..end_word];
+                    // Surfaces are always stored with a folded map.
+                    let surface = lexicon.word_surface(m.word_idx).unwrap();
+                    // Exact matches are inserted above.
+                    if surface.chars().eq(input.iter().copied()) {
+                        continue;
+                    }
+                    // The folded map is built with a superset of the folding.
+                    if self.folding != lexicon.folding() && !self.folding.eq_folded(surface, input)
+                    {
+                        continue;
+                    }
+                    insert(m, end_word);
+                }
             }
         }
 
-        for m in self.dict.system_lexicon().common_prefix_iterator(suffix) {
-            debug_assert!(start_word + m.end_char <= sent.len_char());
-            lattice.insert_node(
+        if let Some(typo) = self.typo_tolerance.as_ref() {
+            Self::add_typo_edges(
+                lexicon,
+                typo,
+                sent,
+                lattice,
+                typo_candidates,
                 start_node,
                 start_word,
-                start_word + m.end_char,
-                m.word_idx,
-                m.word_param,
                 connector,
             );
-            has_matched = true;
         }
+        has_matched
+    }
+
+    /// Inserts words in the lexicon approximately matching the input at `start_word`.
+    ///
+    /// Edits are searched on both the input and the collapsed input.
+    /// `candidates` is a scratch buffer to deduplicate the matches.
+    #[allow(clippy::too_many_arguments)]
+    fn add_typo_edges<C>(
+        lexicon: &Lexicon,
+        typo: &TypoTolerance,
+        sent: &Sentence,
+        lattice: &mut Lattice,
+        candidates: &mut Vec<(WordIdx, usize)>,
+        start_node: usize,
+        start_word: usize,
+        connector: &C,
+    ) where
+        C: ConnectorCost,
+    {
+        candidates.clear();
+        if typo.max_distance() != 0 {
+            lexicon.for_each_typo_candidate(
+                &sent.chars()[start_word..],
+                typo.max_distance(),
+                |m, _| candidates.push((m.word_idx, start_word + m.end_char)),
+            );
+        }
+        if typo.collapses_repeats() {
+            let collapsed = sent.collapsed();
+            // Words do not start inside a run of the same character.
+            if let Some(start) = collapsed.position(start_word) {
+                let suffix = &collapsed.chars()[start..];
+                // Without any run, the collapsed input is the same as the input.
+                if typo.max_distance() == 0 || suffix.len() != sent.len_char() - start_word {
+                    lexicon.for_each_typo_candidate(suffix, typo.max_distance(), |m, _| {
+                        let end_word = collapsed.original_position(start + m.end_char);
+                        candidates.push((m.word_idx, end_word));
+                    });
+                }
+            }
+        }
+        if candidates.is_empty() {
+            return;
+        }
+        candidates.sort_unstable_by_key(|&(word_idx, end_word)| (word_idx.word_id, end_word));
+        candidates.dedup();
+
+        for &(word_idx, end_word) in candidates.iter() {
+            if !sent.is_boundary(end_word) {
+                continue;
+            }
+            // Exact matches are inserted without the penalty.
+            let surface = lexicon.word_surface(word_idx).unwrap();
+            if surface
+                .chars()
+                .eq(sent.chars()[start_word..end_word].iter().copied())
+            {
+                continue;
+            }
+            lattice.insert_node_with_penalty(
+                start_node,
+                start_word,
+                end_word,
+                word_idx,
+                lexicon.word_param(word_idx),
+                typo.penalty_cost(),
+                connector,
+            );
+        }
+    }
 
-        self.dict.unk_handler().gen_unk_words(
+    fn add_lattice_edges<C>(
+        &self,
+        sent: &Sentence,
+        lattice: &mut Lattice,
+        typo_candidates: &mut Vec<(WordIdx, usize)>,
+        start_node: usize,
+        start_word: usize,
+        connector: &C,
+    ) where
+        C: ConnectorCost,
+    {
+        let mut has_matched = false;
+
+        if let Some(user_lexicon) = self.dict.user_lexicon() {
+            has_matched |= self.add_lexicon_edges(
+                user_lexicon,
+                sent,
+                lattice,
+                typo_candidates,
+                start_node,
+                start_word,
+                connector,
+            );
+        }
+        has_matched |= self.add_lexicon_edges(
+            self.dict.system_lexicon(),
             sent,
+            lattice,
+            typo_candidates,
+            start_node,
             start_word,
-            has_matched,
-            self.max_grouping_len,
-            |w| {
-                lattice.insert_node(
-                    start_node,
-                    w.start_char(),
-                    w.end_char(),
-                    w.word_idx(),
-                    w.word_param(),
-                    connector,
-                );
-            },
+            connector,
         );
+
+        let input = UnkInput::new(sent, self.dict.char_prop(), self.max_grouping_len);
+        let mut add_generated_edges =
+            |generator: &dyn UnkGenerator, lex_type: LexType, offset: u32, has_matched: bool| {
+                // Words of the built-in generator are stored in the dictionary with mapped ids.
+                let mapper = if lex_type == LexType::Generated {
+                    self.dict.mapper()
+                } else {
+                    None
+                };
+                let mut matched = false;
+                generator.gen_words(&input, start_word, has_matched, &mut |c| {
+                    assert!(c.word_id < MAX_GENERATED_WORD_ID);
+                    assert!(start_word < c.end_char && c.end_char <= sent.len_char());
+                    if !sent.is_boundary(c.end_char) {
+                        return;
+                    }
+                    let mut word_param = c.word_param;
+                    if let Some(mapper) = mapper {
+                        word_param.left_id = mapper.left(word_param.left_id);
+                        word_param.right_id = mapper.right(word_param.right_id);
+                    }
+                    lattice.insert_node(
+                        start_node,
+                        start_word,
+                        c.end_char,
+                        WordIdx::new(lex_type, offset + c.word_id),
+                        word_param,
+                        connector,
+                    );
+                    matched = true;
+                });
+                matched
+            };
+
+        for (i, generator) in self.unk_generators.iter().enumerate() {
+            let offset = u32::try_from(i).unwrap() * MAX_GENERATED_WORD_ID;
+            has_matched |=
+                add_generated_edges(generator.as_ref(), LexType::Generated, offset, has_matched);
+        }
+        add_generated_edges(self.dict.unk_handler(), LexType::Unknown, 0, has_matched);
     }
 }
 
@@ -359,4 +843,132 @@ mod tests {
         worker.tokenize();
         assert_eq!(worker.num_tokens(), 0);
     }
+
+    #[test]
+    fn test_tokenize_unk_feature_placeholders() {
+        let lexicon_csv = "自然,0,0,1,sizen";
+        let matrix_def = "1 1\n0 0 0";
+        let char_def = "DEFAULT 0 1 0
+ALPHA 1 1 0
+KATAKANA 1 1 0
+0x0041..0x005A ALPHA
+0x0061..0x007A ALPHA
+0x30A1..0x30FF KATAKANA";
+        let unk_def = "DEFAULT,0,0,100,*
+ALPHA,0,0,100,{lowercase}
+KATAKANA,0,0,100,{surface},{hiragana}";
+
+        let dict = SystemDictionaryBuilder::from_readers(
+            lexicon_csv.as_bytes(),
+            matrix_def.as_bytes(),
+            char_def.as_bytes(),
+            unk_def.as_bytes(),
+        )
+        .unwrap();
+
+        let tokenizer = Tokenizer::new(dict);
+        let mut worker = tokenizer.new_worker();
+        worker.reset_sentence("Vibratoバイブラート自然");
+        worker.tokenize();
+        assert_eq!(worker.num_tokens(), 3);
+
+        {
+            let t = worker.token(0);
+            assert_eq!(t.surface(), "Vibrato");
+            assert_eq!(t.feature(), "{lowercase}");
+            assert_eq!(t.feature_expanded(), "vibrato");
+        }
+        {
+            let t = worker.token(1);
+            assert_eq!(t.surface(), "バイブラート");
+            assert_eq!(t.feature_expanded(), "バイブラート,ばいぶらーと");
+        }
+        {
+            let t = worker.token(2);
+            assert_eq!(t.surface(), "自然");
+            assert_eq!(t.feature_expanded(), "sizen");
+        }
+    }
+
+    #[test]
+    fn test_tokenize_typo_tolerance() {
+        let lexicon_csv = "ありがとう,0,0,1,arigatou
+すごい,0,0,1,sugoi
+いいえ,0,0,1,iie";
+        let matrix_def = "1 1\n0 0 0";
+        let char_def = "DEFAULT 0 1 0";
+        let unk_def = "DEFAULT,0,0,1000,*";
+
+        let dict = SystemDictionaryBuilder::new()
+            .typo_index(true)
+            .build_from_readers(
+                lexicon_csv.as_bytes(),
+                matrix_def.as_bytes(),
+                char_def.as_bytes(),
+                unk_def.as_bytes(),
+            )
+            .unwrap();
+
+        let typo = TypoTolerance::new().penalty(50);
+        let tokenizer = Tokenizer::new(dict).typo_tolerance(Some(typo)).unwrap();
+        let mut worker = tokenizer.new_worker();
+        worker.reset_sentence("すごーーーいありがとぉ");
+        worker.tokenize();
+        assert_eq!(worker.num_tokens(), 2);
+
+        {
+            let t = worker.token(0);
+            assert_eq!(t.surface(), "すごーーーい");
+            assert_eq!(t.dictionary_surface(), Some("すごい"));
+            assert_eq!(t.feature(), "sugoi");
+            assert_eq!(t.word_cost(), 1);
+            assert_eq!(t.total_cost(), 51);
+        }
+        {
+            let t = worker.token(1);
+            assert_eq!(t.surface(), "ありがとぉ");
+            assert_eq!(t.dictionary_surface(), Some("ありがとう"));
+            assert_eq!(t.feature(), "arigatou");
+            assert_eq!(t.total_cost(), 102);
+        }
+
+        // Edits are also searched on the input without collapsing.
+        worker.reset_sentence("いいや");
+        worker.tokenize();
+        assert_eq!(worker.num_tokens(), 1);
+        assert_eq!(worker.token(0).feature(), "iie");
+
+        // Only collapsing
+        let typo = typo.edits(false);
+        let tokenizer = tokenizer.typo_tolerance(Some(typo)).unwrap();
+        let mut worker = tokenizer.new_worker();
+        worker.reset_sentence("すごいいい");
+        worker.tokenize();
+        assert_eq!(worker.num_tokens(), 1);
+        assert_eq!(worker.token(0).feature(), "sugoi");
+        worker.reset_sentence("ありがとぉ");
+        worker.tokenize();
+        assert_eq!(worker.num_tokens(), 1);
+        assert_eq!(worker.token(0).feature(), "*");
+
+        // Exact matches have no penalty.
+        worker.reset_sentence("すごい");
+        worker.tokenize();
+        assert_eq!(worker.num_tokens(), 1);
+        assert_eq!(worker.token(0).total_cost(), 1);
+    }
+
+    #[test]
+    fn test_typo_tolerance_without_index() {
+        let dict = SystemDictionaryBuilder::from_readers(
+            "すごい,0,0,1,sugoi".as_bytes(),
+            "1 1\n0 0 0".as_bytes(),
+            "DEFAULT 0 1 0".as_bytes(),
+            "DEFAULT,0,0,1000,*".as_bytes(),
+        )
+        .unwrap();
+        assert!(Tokenizer::new(dict)
+            .typo_tolerance(Some(TypoTolerance::new()))
+            .is_err());
+    }
 }
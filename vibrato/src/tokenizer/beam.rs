@@ -0,0 +1,103 @@
+//! Beam pruning of the lattice.
+
+/// Settings of beam pruning enabled with
+/// [`Tokenizer::beam_pruning()`](crate::Tokenizer::beam_pruning).
+///
+/// For long inputs without punctuation, such as OCR output and logs, the numbers of nodes
+/// ending at each position can be large, and the Viterbi search scans all of them.
+/// Beam pruning keeps at most [`Self::width()`] nodes with the smallest accumulated costs
+/// at each position, and drops nodes whose accumulated costs exceed the best one at the same
+/// position by more than [`Self::threshold()`].
+/// This bounds the time and memory per character at the risk of a slightly different result
+/// from the exact search, which can be detected with [`Self::verify()`].
+///
+/// # Examples
+///
+/// ```
+/// use vibrato::tokenizer::beam::BeamPruning;
+///
+/// let beam = BeamPruning::new().width(Some(8)).threshold(Some(10000));
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BeamPruning {
+    width: Option<usize>,
+    threshold: Option<u32>,
+    verify: bool,
+}
+
+impl Default for BeamPruning {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BeamPruning {
+    /// Creates a new instance with the default settings,
+    /// which keeps `16` nodes at each position without a threshold.
+    pub const fn new() -> Self {
+        Self {
+            width: Some(16),
+            threshold: None,
+            verify: false,
+        }
+    }
+
+    /// Specifies the maximum number of nodes kept at each position, which is at least `1`.
+    /// If `None`, the number of nodes is not limited.
+    pub const fn width(mut self, width: Option<usize>) -> Self {
+        self.width = match width {
+            Some(0) => Some(1),
+            width => width,
+        };
+        self
+    }
+
+    /// Specifies the maximum difference of the accumulated cost of a node from the best one
+    /// ending at the same position. If `None`, nodes are not pruned by costs.
+    ///
+    /// The best node is always kept, so `Some(0)` keeps only the nodes tied with it.
+    pub const fn threshold(mut self, threshold: Option<u32>) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Compares the result with that of the exact search whenever a node is pruned,
+    /// which is reported via [`Worker::changed_by_pruning()`](crate::tokenizer::worker::Worker::changed_by_pruning)
+    /// and [`Worker::pruning_stats()`](crate::tokenizer::worker::Worker::pruning_stats).
+    ///
+    /// This is intended for tuning the settings, since the exact search is also performed.
+    pub const fn verify(mut self, yes: bool) -> Self {
+        self.verify = yes;
+        self
+    }
+
+    #[inline(always)]
+    pub(crate) const fn max_nodes(&self) -> Option<usize> {
+        self.width
+    }
+
+    #[inline(always)]
+    pub(crate) const fn max_cost_diff(&self) -> Option<u32> {
+        self.threshold
+    }
+
+    #[inline(always)]
+    pub(crate) const fn verifies(&self) -> bool {
+        self.verify
+    }
+}
+
+/// Statistics of beam pruning enabled with
+/// [`Tokenizer::beam_pruning()`](crate::Tokenizer::beam_pruning).
+#[derive(Clone, Copy, Default, Debug, Eq, PartialEq)]
+pub struct PruningStats {
+    /// Number of tokenized sentences.
+    pub sentences: u64,
+    /// Number of sentences in which at least one node is pruned.
+    pub pruned_sentences: u64,
+    /// Number of pruned nodes.
+    pub pruned_nodes: u64,
+    /// Number of sentences whose results differ from those of the exact search,
+    /// counted only if [`BeamPruning::verify()`] is enabled.
+    pub changed_sentences: u64,
+}
@@ -0,0 +1,150 @@
+//! Cache of connection costs.
+use std::cell::Cell;
+
+use crate::dictionary::connector::ConnectorCost;
+
+// An empty slot. The entry of ids `u16::MAX` with cost `-1` equals this value and never hits.
+const EMPTY: u64 = u64::MAX;
+
+/// Statistics of a connection cost cache enabled with
+/// [`Tokenizer::connection_cache()`](crate::Tokenizer::connection_cache).
+#[derive(Clone, Copy, Default, Debug, Eq, PartialEq)]
+pub struct CacheStats {
+    /// Number of lookups answered from the cache.
+    pub hits: u64,
+    /// Number of lookups computing the cost.
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// Returns the ratio of hits to all lookups, or `0` if there is no lookup.
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+/// Direct-mapped cache from pairs of connection ids to costs owned by a worker.
+pub(crate) struct ConnectionCache {
+    // Each slot stores a key in the upper 32 bits and a cost in the lower 32 bits.
+    slots: Vec<Cell<u64>>,
+    shift: u32,
+    hits: Cell<u64>,
+    misses: Cell<u64>,
+}
+
+impl ConnectionCache {
+    /// Creates a cache with the given number of slots rounded up to a power of two.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(2).next_power_of_two();
+        Self {
+            slots: (0..capacity).map(|_| Cell::new(EMPTY)).collect(),
+            shift: u64::BITS - capacity.trailing_zeros(),
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+        }
+    }
+
+    #[inline(always)]
+    fn get_or_insert_with<F>(&self, right_id: u16, left_id: u16, f: F) -> i32
+    where
+        F: FnOnce() -> i32,
+    {
+        let key = u64::from(right_id) << 16 | u64::from(left_id);
+        // Fibonacci hashing
+        let slot = &self.slots[(key.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> self.shift) as usize];
+        let entry = slot.get();
+        if entry != EMPTY && entry >> 32 == key {
+            self.hits.set(self.hits.get() + 1);
+            return entry as u32 as i32;
+        }
+        self.misses.set(self.misses.get() + 1);
+        let cost = f();
+        slot.set(key << 32 | u64::from(cost as u32));
+        cost
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.get(),
+            misses: self.misses.get(),
+        }
+    }
+
+    pub fn reset_stats(&self) {
+        self.hits.set(0);
+        self.misses.set(0);
+    }
+}
+
+/// Connector looking up a cache before computing costs.
+pub(crate) struct CachedConnector<'a, C> {
+    connector: &'a C,
+    cache: &'a ConnectionCache,
+}
+
+impl<'a, C> CachedConnector<'a, C> {
+    pub const fn new(connector: &'a C, cache: &'a ConnectionCache) -> Self {
+        Self { connector, cache }
+    }
+}
+
+impl<C> ConnectorCost for CachedConnector<'_, C>
+where
+    C: ConnectorCost,
+{
+    #[inline(always)]
+    fn cost(&self, right_id: u16, left_id: u16) -> i32 {
+        self.cache
+            .get_or_insert_with(right_id, left_id, || self.connector.cost(right_id, left_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct CountingConnector {
+        calls: Cell<usize>,
+    }
+
+    impl ConnectorCost for CountingConnector {
+        fn cost(&self, right_id: u16, left_id: u16) -> i32 {
+            self.calls.set(self.calls.get() + 1);
+            i32::from(right_id) - i32::from(left_id)
+        }
+    }
+
+    #[test]
+    fn test_cached_cost() {
+        let connector = CountingConnector {
+            calls: Cell::new(0),
+        };
+        let cache = ConnectionCache::new(16);
+        let cached = CachedConnector::new(&connector, &cache);
+        assert_eq!(cached.cost(1, 2), -1);
+        assert_eq!(cached.cost(1, 2), -1);
+        assert_eq!(cached.cost(2, 1), 1);
+        assert_eq!(cached.cost(u16::MAX, u16::MAX), 0);
+        assert_eq!(cached.cost(u16::MAX, u16::MAX), 0);
+        assert_eq!(cached.cost(1, 2), -1);
+        let stats = cache.stats();
+        assert_eq!(stats.hits + stats.misses, 6);
+        assert_eq!(stats.misses, connector.calls.get() as u64);
+        assert!(stats.hits >= 2);
+
+        cache.reset_stats();
+        assert_eq!(cache.stats(), CacheStats::default());
+        assert_eq!(cache.stats().hit_rate(), 0.0);
+    }
+
+    #[test]
+    fn test_hit_rate() {
+        let stats = CacheStats { hits: 3, misses: 1 };
+        assert_eq!(stats.hit_rate(), 0.75);
+    }
+}
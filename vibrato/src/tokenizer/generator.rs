@@ -0,0 +1,149 @@
+//! Generators of unknown-word candidates.
+use crate::dictionary::character::CharProperty;
+use crate::dictionary::unknown::{UnkHandler, UnkWord};
+use crate::dictionary::{LexType, WordIdx, WordParam};
+use crate::sentence::Sentence;
+
+/// Input sentence given to [`UnkGenerator`].
+pub struct UnkInput<'a> {
+    pub(crate) sent: &'a Sentence,
+    pub(crate) char_prop: &'a CharProperty,
+    pub(crate) max_grouping_len: Option<usize>,
+}
+
+impl<'a> UnkInput<'a> {
+    pub(crate) const fn new(
+        sent: &'a Sentence,
+        char_prop: &'a CharProperty,
+        max_grouping_len: Option<usize>,
+    ) -> Self {
+        Self {
+            sent,
+            char_prop,
+            max_grouping_len,
+        }
+    }
+
+    /// Gets the input string.
+    #[inline(always)]
+    pub fn raw(&self) -> &'a str {
+        self.sent.raw()
+    }
+
+    /// Gets the characters of the input string.
+    #[inline(always)]
+    pub fn chars(&self) -> &'a [char] {
+        self.sent.chars()
+    }
+
+    /// Gets the number of characters.
+    #[inline(always)]
+    pub fn len_char(&self) -> usize {
+        self.sent.len_char()
+    }
+
+    /// Checks if a token can start or end at the given character position.
+    ///
+    /// This is `false` only inside an extended grapheme cluster when
+    /// [`Tokenizer::respect_grapheme_clusters()`](crate::Tokenizer::respect_grapheme_clusters)
+    /// is enabled. Candidates ending at such positions are ignored.
+    #[inline(always)]
+    pub fn is_boundary(&self, pos_char: usize) -> bool {
+        self.sent.is_boundary(pos_char)
+    }
+
+    /// Gets the byte position of the given character position.
+    ///
+    /// # Arguments
+    ///
+    ///  - `pos_char`: Character position, which can be equal to [`Self::len_char()`].
+    #[inline(always)]
+    pub fn byte_position(&self, pos_char: usize) -> usize {
+        self.sent.byte_position(pos_char)
+    }
+}
+
+/// Candidate of an unknown word.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UnkCandidate {
+    /// Ending character position (exclusive), which must be larger than the starting one
+    /// and no more than [`UnkInput::len_char()`].
+    pub end_char: usize,
+    /// Word id local to the generator, which must be less than [`MAX_GENERATED_WORD_ID`].
+    pub word_id: u32,
+    /// Parameter of the word, which should be equal to [`UnkGenerator::word_param()`].
+    pub word_param: WordParam,
+}
+
+/// Upper bound (exclusive) of word ids of [`UnkCandidate`].
+pub const MAX_GENERATED_WORD_ID: u32 = 1 << 24;
+
+/// Generator of unknown-word candidates.
+///
+/// At every position of a sentence, the tokenizer calls generators added with
+/// [`Tokenizer::add_unk_generator()`](crate::Tokenizer::add_unk_generator) in the added order
+/// and then the built-in generator defined with `unk.def`, which implements this trait
+/// and whose words have [`LexType::Unknown`].
+/// Candidates from the added generators suppress unknown words of categories
+/// whose `INVOKE` is `0`, in the same manner as lexicon words.
+/// A generator can define its own words with connection ids of the dictionary,
+/// e.g., to plug in a character n-gram model or an external span proposer.
+pub trait UnkGenerator: Send + Sync {
+    /// Generates candidates starting at `start_char`.
+    ///
+    /// # Arguments
+    ///
+    ///  - `input`: Input sentence.
+    ///  - `start_char`: Starting character position of the candidates.
+    ///  - `has_matched`: `true` if a word in the lexicons or a candidate of the preceding
+    ///    generators starts at `start_char`.
+    ///  - `push`: Function receiving the candidates.
+    fn gen_words(
+        &self,
+        input: &UnkInput,
+        start_char: usize,
+        has_matched: bool,
+        push: &mut dyn FnMut(UnkCandidate),
+    );
+
+    /// Gets the parameter of the word.
+    fn word_param(&self, word_id: u32) -> WordParam;
+
+    /// Gets the feature string of the word.
+    fn word_feature(&self, word_id: u32) -> &str;
+}
+
+impl UnkGenerator for UnkHandler {
+    fn gen_words(
+        &self,
+        input: &UnkInput,
+        start_char: usize,
+        has_matched: bool,
+        push: &mut dyn FnMut(UnkCandidate),
+    ) {
+        let mut f = |w: UnkWord| {
+            push(UnkCandidate {
+                end_char: w.end_char(),
+                word_id: w.word_idx().word_id,
+                word_param: w.word_param(),
+            });
+        };
+        let has_matched = self.gen_pattern_words(input.sent, start_char, &mut f) || has_matched;
+        self.gen_unk_words(
+            input.sent,
+            input.char_prop,
+            start_char,
+            has_matched,
+            input.max_grouping_len,
+            f,
+        );
+    }
+
+    fn word_param(&self, word_id: u32) -> WordParam {
+        Self::word_param(self, WordIdx::new(LexType::Unknown, word_id))
+    }
+
+    fn word_feature(&self, word_id: u32) -> &str {
+        Self::word_feature(self, WordIdx::new(LexType::Unknown, word_id))
+    }
+}
@@ -5,21 +5,22 @@ use crate::dictionary::word_idx::WordIdx;
 use crate::dictionary::LexType;
 
 use crate::common::{BOS_EOS_CONNECTION_ID, MAX_SENTENCE_LENGTH};
+use crate::tokenizer::beam::BeamPruning;
+use crate::utils::FromU32;
 
-const MAX_COST: i32 = i32::MAX;
-const INVALID_IDX: u16 = u16::MAX;
+// Path costs are accumulated in 64 bits so that 32-bit word and connection costs never overflow.
+const MAX_COST: i64 = i64::MAX;
+const INVALID_IDX: u32 = u32::MAX;
 
-/// 160 bits of each without extra padding.
+/// Node on the best path, extracted from the lattice.
 #[derive(Default, Debug, Clone)]
 pub struct Node {
     pub word_id: u32,
-    pub lex_type: LexType, // 8 bits
-    pub start_node: usize,
-    pub start_word: usize,
+    pub lex_type: LexType,
+    pub start_word: u32,
     pub left_id: u16,
     pub right_id: u16,
-    pub min_idx: u16,
-    pub min_cost: i32,
+    pub min_cost: i64,
 }
 
 impl Node {
@@ -27,40 +28,117 @@ impl Node {
     pub const fn word_idx(&self) -> WordIdx {
         WordIdx::new(self.lex_type, self.word_id)
     }
+}
+
+/// Node stored in the lattice, linked to the next one ending at the same position
+/// until the position is finalized.
+#[derive(Default, Debug, Clone, Copy)]
+struct Entry {
+    word_id: u32,
+    lex_type: LexType,
+    start_node: u32,
+    start_word: u32,
+    left_id: u16,
+    right_id: u16,
+    // Index of the best previous entry.
+    min_idx: u32,
+    min_cost: i64,
+    next: u32,
+}
 
+impl Entry {
     #[inline(always)]
-    pub const fn is_connected_to_bos(&self) -> bool {
-        self.min_cost != MAX_COST
+    const fn to_node(self) -> Node {
+        Node {
+            word_id: self.word_id,
+            lex_type: self.lex_type,
+            start_word: self.start_word,
+            left_id: self.left_id,
+            right_id: self.right_id,
+            min_cost: self.min_cost,
+        }
     }
 }
 
+/// Nodes ending at each position.
+#[derive(Debug, Clone, Copy)]
+struct Position {
+    // Linked list of the entries, from which pruned ones are removed on finalization.
+    head: u32,
+    tail: u32,
+    len: u32,
+    finalized: bool,
+}
+
+impl Position {
+    const EMPTY: Self = Self {
+        head: INVALID_IDX,
+        tail: INVALID_IDX,
+        len: 0,
+        finalized: false,
+    };
+}
+
 /// This implementation inspired by sudachi.rs.
+///
+/// Nodes are appended to a flat pool of entries and linked per end position.
+/// Once all the nodes ending at a position are inserted, the position is finalized,
+/// and the right ids and costs of its nodes are copied to dense scratch arrays,
+/// so that the search of the best previous node for the nodes starting there
+/// does not chase the links. All the buffers are reused across sentences.
 #[derive(Default)]
 pub struct Lattice {
-    ends: Vec<Vec<Node>>,
-    eos: Option<Node>,
-    len_char: usize, // needed for avoiding to free ends
+    entries: Vec<Entry>,
+    positions: Vec<Position>,
+    // Nodes ending at `scratch_node`, which is the last finalized position.
+    scratch_node: usize,
+    right_ids: Vec<u16>,
+    min_costs: Vec<i64>,
+    entry_ids: Vec<u32>,
+    eos: Option<Entry>,
+    // Right id of BOS, which is that of the last token of the preceding text if any.
+    bos_right_id: u16,
+    // Used only to find cut points.
+    reach: Vec<u32>,
+    len_char: usize,
+    beam: Option<BeamPruning>,
+    num_pruned: usize,
+    // Set if the entries cannot be indexed in 32 bits, after which nodes are not inserted.
+    overflowed: bool,
 }
 
 impl Lattice {
-    pub fn reset(&mut self, len_char: usize) {
-        Self::reset_vec(&mut self.ends, len_char + 1);
+    pub fn reset(&mut self, len_char: usize, beam: Option<BeamPruning>) {
+        debug_assert!(len_char <= MAX_SENTENCE_LENGTH);
+        self.entries.clear();
+        self.positions.clear();
+        self.positions.resize(len_char + 1, Position::EMPTY);
         self.len_char = len_char;
         self.eos = None;
+        self.beam = beam;
+        self.num_pruned = 0;
+        self.overflowed = false;
         self.insert_bos();
     }
 
-    fn reset_vec<T>(data: &mut Vec<Vec<T>>, new_len: usize) {
-        for v in data.iter_mut() {
-            v.clear();
-        }
-        let cur_len = data.len();
-        if cur_len <= new_len {
-            data.reserve(new_len - cur_len);
-            for _ in cur_len..new_len {
-                data.push(Vec::with_capacity(16))
-            }
-        }
+    /// Sets the right id of BOS used after the next reset, which is that of the last token
+    /// of the preceding text, or [`BOS_EOS_CONNECTION_ID`] if there is no such text.
+    #[inline(always)]
+    pub fn set_bos_right_id(&mut self, right_id: u16) {
+        self.bos_right_id = right_id;
+    }
+
+    /// Checks if nodes are dropped because the number of nodes exceeds the limit
+    /// since the last reset.
+    #[inline(always)]
+    pub const fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+
+    /// Returns the number of nodes pruned by the beam since the last reset.
+    #[inline(always)]
+    pub const fn num_pruned(&self) -> usize {
+        self.num_pruned
     }
 
     /// Returns the number of characters of the set sentence.
@@ -70,36 +148,44 @@ impl Lattice {
     }
 
     fn insert_bos(&mut self) {
-        self.ends[0].push(Node {
-            word_id: u32::MAX,
-            lex_type: LexType::default(),
-            start_node: MAX_SENTENCE_LENGTH,
-            start_word: MAX_SENTENCE_LENGTH,
-            left_id: u16::MAX,
-            right_id: BOS_EOS_CONNECTION_ID,
-            min_idx: INVALID_IDX,
-            min_cost: 0,
-        });
+        self.push_entry(
+            0,
+            Entry {
+                word_id: u32::MAX,
+                lex_type: LexType::default(),
+                start_node: INVALID_IDX,
+                start_word: INVALID_IDX,
+                left_id: u16::MAX,
+                right_id: self.bos_right_id,
+                min_idx: INVALID_IDX,
+                min_cost: 0,
+                next: INVALID_IDX,
+            },
+        );
+        self.finalize(0);
     }
 
     pub fn insert_eos<C>(&mut self, start_node: usize, connector: &C)
     where
         C: ConnectorCost,
     {
+        self.finalize(start_node);
         let (min_idx, min_cost) =
             self.search_min_node(start_node, BOS_EOS_CONNECTION_ID, connector);
-        self.eos = Some(Node {
+        self.eos = Some(Entry {
             word_id: u32::MAX,
             lex_type: LexType::default(),
-            start_node,
-            start_word: self.len_char(),
+            start_node: u32::try_from(start_node).unwrap(),
+            start_word: u32::try_from(self.len_char).unwrap(),
             left_id: BOS_EOS_CONNECTION_ID,
             right_id: u16::MAX,
             min_idx,
             min_cost,
+            next: INVALID_IDX,
         });
     }
 
+    #[inline(always)]
     pub fn insert_node<C>(
         &mut self,
         start_node: usize,
@@ -110,38 +196,169 @@ impl Lattice {
         connector: &C,
     ) where
         C: ConnectorCost,
+    {
+        self.insert_node_with_penalty(
+            start_node, start_word, end_word, word_idx, word_param, 0, connector,
+        );
+    }
+
+    /// Inserts a node whose word cost is increased by `penalty`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn insert_node_with_penalty<C>(
+        &mut self,
+        start_node: usize,
+        start_word: usize,
+        end_word: usize,
+        word_idx: WordIdx,
+        word_param: WordParam,
+        penalty: i32,
+        connector: &C,
+    ) where
+        C: ConnectorCost,
     {
         debug_assert!(start_node <= start_word);
         debug_assert!(start_word < end_word);
+        debug_assert!(!self.positions[end_word].finalized);
         let (min_idx, min_cost) = self.search_min_node(start_node, word_param.left_id, connector);
-        self.ends[end_word].push(Node {
+        let entry = Entry {
             word_id: word_idx.word_id,
             lex_type: word_idx.lex_type,
-            start_node,
-            start_word,
+            start_node: u32::try_from(start_node).unwrap(),
+            start_word: u32::try_from(start_word).unwrap(),
             left_id: word_param.left_id,
             right_id: word_param.right_id,
             min_idx,
-            min_cost: min_cost + i32::from(word_param.word_cost),
-        });
+            min_cost: min_cost + i64::from(word_param.word_cost) + i64::from(penalty),
+            next: INVALID_IDX,
+        };
+        match self.beam.as_ref().and_then(BeamPruning::max_nodes) {
+            Some(max_nodes) if usize::from_u32(self.positions[end_word].len) >= max_nodes => {
+                // Nodes ending at `end_word` are not referred to until the position is
+                // finalized, so the worst one can be replaced in place.
+                let mut worst_idx = self.positions[end_word].head;
+                let mut i = worst_idx;
+                while i != INVALID_IDX {
+                    let e = &self.entries[usize::from_u32(i)];
+                    if e.min_cost >= self.entries[usize::from_u32(worst_idx)].min_cost {
+                        worst_idx = i;
+                    }
+                    i = e.next;
+                }
+                let worst = &mut self.entries[usize::from_u32(worst_idx)];
+                if entry.min_cost < worst.min_cost {
+                    *worst = Entry {
+                        next: worst.next,
+                        ..entry
+                    };
+                }
+                self.num_pruned += 1;
+            }
+            _ => self.push_entry(end_word, entry),
+        }
+    }
+
+    #[inline(always)]
+    fn push_entry(&mut self, end_word: usize, entry: Entry) {
+        let idx = match u32::try_from(self.entries.len()) {
+            Ok(idx) if idx != INVALID_IDX => idx,
+            _ => {
+                self.overflowed = true;
+                return;
+            }
+        };
+        self.entries.push(entry);
+        let pos = &mut self.positions[end_word];
+        if pos.tail == INVALID_IDX {
+            pos.head = idx;
+        } else {
+            self.entries[usize::from_u32(pos.tail)].next = idx;
+        }
+        pos.tail = idx;
+        pos.len += 1;
+    }
+
+    /// Copies the nodes ending at `end_node` to the scratch arrays, dropping nodes whose costs
+    /// exceed the best one by more than the threshold of the beam on the first call.
+    ///
+    /// This must be called after all the nodes ending at `end_node` are inserted
+    /// and before searching the best previous node among them.
+    #[inline(always)]
+    pub fn finalize(&mut self, end_node: usize) {
+        let pos = self.positions[end_node];
+        if pos.finalized && self.scratch_node == end_node {
+            return;
+        }
+        let max_cost = match self.beam.as_ref().and_then(BeamPruning::max_cost_diff) {
+            Some(threshold) if !pos.finalized => {
+                let mut best = MAX_COST;
+                let mut i = pos.head;
+                while i != INVALID_IDX {
+                    let e = &self.entries[usize::from_u32(i)];
+                    best = best.min(e.min_cost);
+                    i = e.next;
+                }
+                // The threshold is non-negative, so the best node is always kept.
+                best.saturating_add(i64::from(threshold))
+            }
+            _ => MAX_COST,
+        };
+        self.scratch_node = end_node;
+        self.right_ids.clear();
+        self.min_costs.clear();
+        self.entry_ids.clear();
+        // Relinks the remaining entries.
+        let (mut head, mut tail) = (INVALID_IDX, INVALID_IDX);
+        let mut i = pos.head;
+        while i != INVALID_IDX {
+            let e = self.entries[usize::from_u32(i)];
+            if e.min_cost <= max_cost {
+                if tail == INVALID_IDX {
+                    head = i;
+                } else {
+                    self.entries[usize::from_u32(tail)].next = i;
+                }
+                tail = i;
+                self.right_ids.push(e.right_id);
+                self.min_costs.push(e.min_cost);
+                self.entry_ids.push(i);
+            }
+            i = e.next;
+        }
+        if tail != INVALID_IDX {
+            self.entries[usize::from_u32(tail)].next = INVALID_IDX;
+        }
+        let len = u32::try_from(self.entry_ids.len()).unwrap();
+        self.num_pruned += usize::from_u32(pos.len - len);
+        self.positions[end_node] = Position {
+            head,
+            tail,
+            len,
+            finalized: true,
+        };
     }
 
-    fn search_min_node<C>(&self, start_node: usize, left_id: u16, connector: &C) -> (u16, i32)
+    fn search_min_node<C>(&self, start_node: usize, left_id: u16, connector: &C) -> (u32, i64)
     where
         C: ConnectorCost,
     {
-        debug_assert!(!self.ends[start_node].is_empty());
+        debug_assert_eq!(self.scratch_node, start_node);
+        debug_assert!(!self.entry_ids.is_empty());
 
         let mut min_idx = INVALID_IDX;
         let mut min_cost = MAX_COST;
-        for (i, left_node) in self.ends[start_node].iter().enumerate() {
-            debug_assert!(left_node.is_connected_to_bos());
-            let conn_cost = connector.cost(left_node.right_id, left_id);
-            let new_cost = left_node.min_cost + conn_cost;
+        for ((&right_id, &cost), &idx) in self
+            .right_ids
+            .iter()
+            .zip(&self.min_costs)
+            .zip(&self.entry_ids)
+        {
+            debug_assert_ne!(cost, MAX_COST);
+            let conn_cost = connector.cost(right_id, left_id);
+            let new_cost = cost + i64::from(conn_cost);
             // Depending on the order of tie-breaking, the result can be different from MeCab.
             // Using <= (not <) will produce results identical to MeCab in most case (empirically).
             if new_cost <= min_cost {
-                min_idx = i as u16;
+                min_idx = idx;
                 min_cost = new_cost;
             }
         }
@@ -153,31 +370,90 @@ impl Lattice {
     /// Checks if there exist at least one at the word end boundary
     #[inline(always)]
     pub fn has_previous_node(&self, i: usize) -> bool {
-        self.ends.get(i).map(|d| !d.is_empty()).unwrap_or(false)
+        self.positions.get(i).is_some_and(|pos| pos.len != 0)
     }
 
     pub fn append_top_nodes(&self, top_nodes: &mut Vec<(usize, Node)>) {
         let eos = self.eos.as_ref().unwrap();
-        let mut end_node = eos.start_node;
-        let mut min_idx = eos.min_idx;
+        self.append_path(usize::from_u32(eos.start_node), eos.min_idx, top_nodes);
+    }
+
+    /// Appends the best path to the single node ending at a cut point.
+    ///
+    /// See [`Self::last_cut_point()`].
+    pub fn append_top_nodes_to(&self, end_node: usize, top_nodes: &mut Vec<(usize, Node)>) {
+        let pos = &self.positions[end_node];
+        debug_assert!(pos.finalized && pos.len == 1);
+        self.append_path(end_node, pos.head, top_nodes);
+    }
+
+    fn append_path(
+        &self,
+        mut end_node: usize,
+        mut min_idx: u32,
+        top_nodes: &mut Vec<(usize, Node)>,
+    ) {
         while end_node != 0 {
-            let node = &self.ends[end_node][usize::from(min_idx)];
-            top_nodes.push((end_node, node.clone()));
-            (end_node, min_idx) = (node.start_node, node.min_idx);
+            let entry = &self.entries[usize::from_u32(min_idx)];
+            top_nodes.push((end_node, entry.to_node()));
+            (end_node, min_idx) = (usize::from_u32(entry.start_node), entry.min_idx);
         }
     }
 
+    /// Returns the last position in `1..=limit` satisfying `accept` through which every path
+    /// passes with the same node, i.e., only one node ends at the position and no node spans it.
+    ///
+    /// The best path to such a position never changes with the subsequent text, as long as
+    /// the nodes ending up to `limit` are not affected by the text after the sentence.
+    pub fn last_cut_point<F>(&mut self, limit: usize, mut accept: F) -> Option<usize>
+    where
+        F: FnMut(usize) -> bool,
+    {
+        // The furthest end positions of nodes indexed by their start positions.
+        let mut reach = std::mem::take(&mut self.reach);
+        reach.clear();
+        reach.resize(self.len_char + 1, 0);
+        for end_node in 1..=self.len_char {
+            for i in self.entry_indices(end_node) {
+                let start_node = usize::from_u32(self.entries[usize::from_u32(i)].start_node);
+                reach[start_node] = reach[start_node].max(u32::try_from(end_node).unwrap());
+            }
+        }
+        let mut max_reach = 0;
+        let mut cut_point = None;
+        for end_node in 1..=limit.min(self.len_char) {
+            max_reach = max_reach.max(usize::from_u32(reach[end_node - 1]));
+            let pos = &self.positions[end_node];
+            if max_reach <= end_node && pos.finalized && pos.len == 1 && accept(end_node) {
+                cut_point = Some(end_node);
+            }
+        }
+        self.reach = reach;
+        cut_point
+    }
+
+    /// Returns the indices of the entries ending at `end_node`.
+    fn entry_indices(&self, end_node: usize) -> impl Iterator<Item = u32> + '_ {
+        let head = self.positions[end_node].head;
+        std::iter::successors((head != INVALID_IDX).then_some(head), |&i| {
+            let next = self.entries[usize::from_u32(i)].next;
+            (next != INVALID_IDX).then_some(next)
+        })
+    }
+
     pub fn add_connid_counts(&self, counter: &mut ConnIdCounter) {
         for end_char in 1..=self.len_char() {
-            for r_node in &self.ends[end_char] {
-                let start_node = r_node.start_node;
-                for l_node in &self.ends[start_node] {
+            for r_idx in self.entry_indices(end_char) {
+                let r_node = &self.entries[usize::from_u32(r_idx)];
+                for l_idx in self.entry_indices(usize::from_u32(r_node.start_node)) {
+                    let l_node = &self.entries[usize::from_u32(l_idx)];
                     counter.add(r_node.left_id, l_node.right_id, 1);
                 }
             }
         }
         let r_node = self.eos.as_ref().unwrap();
-        for l_node in &self.ends[self.len_char()] {
+        for l_idx in self.entry_indices(self.len_char()) {
+            let l_node = &self.entries[usize::from_u32(l_idx)];
             counter.add(r_node.left_id, l_node.right_id, 1);
         }
     }
@@ -186,8 +462,12 @@ impl Lattice {
 impl std::fmt::Debug for Lattice {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "Lattice {{ eos: {:?}, ends: [", &self.eos)?;
-        for (i, e) in self.ends[..=self.len_char()].iter().enumerate() {
-            writeln!(f, "{i} => {e:?}")?;
+        for i in 0..self.positions.len() {
+            let entries: Vec<_> = self
+                .entry_indices(i)
+                .map(|j| self.entries[usize::from_u32(j)])
+                .collect();
+            writeln!(f, "{i} => {entries:?}")?;
         }
         writeln!(f, "]}}")
     }
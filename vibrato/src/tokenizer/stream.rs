@@ -0,0 +1,271 @@
+//! Tokenization of unbounded input streams.
+use std::io::BufRead;
+use std::ops::Range;
+
+use crate::common::BOS_EOS_CONNECTION_ID;
+use crate::errors::Result;
+use crate::token::{Token, TokenIter};
+use crate::tokenizer::worker::Worker;
+
+/// Default number of characters tokenized at once in a long line.
+pub const DEFAULT_CHUNK_LEN: usize = 4096;
+
+/// Default number of characters read ahead of a cut point.
+pub const DEFAULT_LOOKAHEAD: usize = 256;
+
+/// A long line is cut at a token boundary if no cut point is found in this many times
+/// the chunk length.
+const FORCED_CUT_FACTOR: usize = 4;
+
+/// Tokenizer of an unbounded input stream, reading text from a [`BufRead`] and
+/// tokenizing it in chunks, so that the memory does not grow with the length of a line.
+///
+/// Each line is tokenized as a sentence as with [`Worker::reset_sentence()`].
+/// A line longer than [`Self::chunk_len()`] characters is cut at cut points,
+/// where only one node ends and no node spans in the lattice. Such points typically appear
+/// after sentence punctuation, and the tokens before them are the same as those of the whole
+/// line, since the next chunk is tokenized following the last token of the previous one.
+/// If no cut point is found in four times the chunk length, the line is cut at a token
+/// boundary of the best path instead, which is counted by [`Self::num_forced_cuts()`].
+///
+/// # Examples
+///
+/// ```
+/// use std::fs::File;
+///
+/// use vibrato::dictionary::SystemDictionaryBuilder;
+/// use vibrato::tokenizer::stream::TokenStream;
+/// use vibrato::Tokenizer;
+///
+/// let dict = SystemDictionaryBuilder::from_readers(
+///     File::open("src/tests/resources/lex.csv")?,
+///     File::open("src/tests/resources/matrix.def")?,
+///     File::open("src/tests/resources/char.def")?,
+///     File::open("src/tests/resources/unk.def")?,
+/// )?;
+/// let tokenizer = Tokenizer::new(dict);
+///
+/// let input = "自然言語処理\n東京都";
+/// let mut stream = TokenStream::new(tokenizer.new_worker(), input.as_bytes());
+/// let mut ranges = vec![];
+/// while stream.next_chunk()? {
+///     for i in 0..stream.num_tokens() {
+///         ranges.push(stream.range_byte(i));
+///     }
+/// }
+/// assert_eq!(ranges, [0..6, 6..12, 12..18, 19..28]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct TokenStream<'t, R> {
+    worker: Worker<'t>,
+    reader: R,
+    chunk_len: usize,
+    lookahead: usize,
+    // Text of the current line not tokenized yet.
+    buffer: String,
+    len_char: usize,
+    // Trailing bytes of an incomplete UTF-8 sequence.
+    incomplete: Vec<u8>,
+    has_line_end: bool,
+    eof: bool,
+    // Byte offsets of the buffer and the current chunk in the input stream.
+    buffer_offset: usize,
+    offset: usize,
+    // Right id of the last token of the previous chunk in the same line.
+    bos_right_id: u16,
+    ends_line: bool,
+    num_forced_cuts: u64,
+}
+
+impl<'t, R> TokenStream<'t, R>
+where
+    R: BufRead,
+{
+    /// Creates a new instance.
+    ///
+    /// # Arguments
+    ///
+    ///  - `worker`: Worker used for tokenization.
+    ///  - `reader`: Reader of the input text in UTF-8.
+    pub fn new(worker: Worker<'t>, reader: R) -> Self {
+        Self {
+            worker,
+            reader,
+            chunk_len: DEFAULT_CHUNK_LEN,
+            lookahead: DEFAULT_LOOKAHEAD,
+            buffer: String::new(),
+            len_char: 0,
+            incomplete: vec![],
+            has_line_end: false,
+            eof: false,
+            buffer_offset: 0,
+            offset: 0,
+            bos_right_id: BOS_EOS_CONNECTION_ID,
+            ends_line: false,
+            num_forced_cuts: 0,
+        }
+    }
+
+    /// Specifies the number of characters tokenized at once in a long line, which is at least `1`.
+    /// The default value is [`DEFAULT_CHUNK_LEN`].
+    pub const fn chunk_len(mut self, chunk_len: usize) -> Self {
+        self.chunk_len = if chunk_len == 0 { 1 } else { chunk_len };
+        self
+    }
+
+    /// Specifies the number of characters read ahead of a cut point.
+    /// The default value is [`DEFAULT_LOOKAHEAD`].
+    ///
+    /// This must be at least the length of the longest word in the lexicons,
+    /// because a word crossing the end of the text read so far is not found.
+    pub const fn lookahead(mut self, lookahead: usize) -> Self {
+        self.lookahead = lookahead;
+        self
+    }
+
+    /// Reads and tokenizes the next chunk, returning `false` at the end of the input.
+    ///
+    /// # Errors
+    ///
+    /// [`VibratoError`](crate::errors::VibratoError) is returned when
+    ///
+    ///  - reading the input fails,
+    ///  - the input is not in UTF-8, or
+    ///  - a chunk is too long to be tokenized (see [`Worker::try_tokenize()`]).
+    pub fn next_chunk(&mut self) -> Result<bool> {
+        let mut target = self.chunk_len + self.lookahead;
+        loop {
+            while !self.has_line_end && !self.eof && self.len_char < target {
+                self.fill_buffer(target - self.len_char)?;
+            }
+            if self.has_line_end || self.eof {
+                if !self.has_line_end && self.buffer.is_empty() {
+                    return Ok(false);
+                }
+                self.tokenize_line_end()?;
+                return Ok(true);
+            }
+
+            let line = &self.buffer;
+            self.worker.reset_sentence(line);
+            self.worker.tokenize_after(self.bos_right_id)?;
+            let len_char = self.worker.sent.len_char();
+            let limit = len_char.saturating_sub(self.lookahead);
+            let mut cut_point = self.worker.truncate_at_cut_point(limit);
+            if cut_point.is_none() && len_char >= FORCED_CUT_FACTOR * self.chunk_len {
+                cut_point = self.worker.truncate_at_token_boundary(limit);
+                self.num_forced_cuts += u64::from(cut_point.is_some());
+            }
+            if let Some(cut_point) = cut_point {
+                let len_byte = self.worker.original_byte_position(cut_point);
+                self.bos_right_id = self.worker.token(self.worker.num_tokens() - 1).right_id();
+                self.len_char -= self.buffer[..len_byte].chars().count();
+                self.buffer.drain(..len_byte);
+                self.offset = self.buffer_offset;
+                self.buffer_offset += len_byte;
+                self.ends_line = false;
+                return Ok(true);
+            }
+            target = self.len_char + self.chunk_len;
+        }
+    }
+
+    /// Tokenizes the rest of the current line.
+    fn tokenize_line_end(&mut self) -> Result<()> {
+        let line = self
+            .buffer
+            .strip_suffix('\n')
+            .map_or(self.buffer.as_str(), |line| {
+                line.strip_suffix('\r').unwrap_or(line)
+            });
+        self.worker.reset_sentence(line);
+        self.worker.tokenize_after(self.bos_right_id)?;
+        self.offset = self.buffer_offset;
+        self.buffer_offset += self.buffer.len();
+        self.buffer.clear();
+        self.len_char = 0;
+        self.has_line_end = false;
+        self.bos_right_id = BOS_EOS_CONNECTION_ID;
+        self.ends_line = true;
+        Ok(())
+    }
+
+    /// Appends at most `max_len` bytes up to the end of a line from the reader to the buffer.
+    fn fill_buffer(&mut self, max_len: usize) -> Result<()> {
+        let buf = self.reader.fill_buf()?;
+        if buf.is_empty() {
+            self.eof = true;
+            if !self.incomplete.is_empty() {
+                std::str::from_utf8(&self.incomplete)?;
+            }
+            return Ok(());
+        }
+        let buf = &buf[..buf.len().min(max_len)];
+        let (len, has_line_end) = buf
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or((buf.len(), false), |i| (i + 1, true));
+        self.incomplete.extend_from_slice(&buf[..len]);
+        self.reader.consume(len);
+        self.has_line_end = has_line_end;
+        let valid_len = match std::str::from_utf8(&self.incomplete) {
+            Ok(text) => text.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(e.into()),
+        };
+        let text = std::str::from_utf8(&self.incomplete[..valid_len])?;
+        self.len_char += text.chars().count();
+        self.buffer.push_str(text);
+        self.incomplete.drain(..valid_len);
+        Ok(())
+    }
+
+    /// Checks if the current chunk ends a line or the input.
+    pub const fn ends_line(&self) -> bool {
+        self.ends_line
+    }
+
+    /// Gets the byte offset of the current chunk in the input stream.
+    pub const fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Gets the number of resultant tokens of the current chunk.
+    #[inline(always)]
+    pub fn num_tokens(&self) -> usize {
+        self.worker.num_tokens()
+    }
+
+    /// Gets the `i`-th resultant token of the current chunk.
+    ///
+    /// The positions of the token are relative to the chunk, and
+    /// [`Token::total_cost()`] is accumulated from the beginning of the chunk.
+    #[inline(always)]
+    pub fn token<'w>(&'w self, i: usize) -> Token<'w, 't> {
+        self.worker.token(i)
+    }
+
+    /// Creates an iterator of resultant tokens of the current chunk.
+    #[inline(always)]
+    pub const fn token_iter<'w>(&'w self) -> TokenIter<'w, 't> {
+        self.worker.token_iter()
+    }
+
+    /// Gets the byte range of the `i`-th resultant token in the input stream.
+    #[inline(always)]
+    pub fn range_byte(&self, i: usize) -> Range<usize> {
+        let range = self.token(i).range_byte_original();
+        self.offset + range.start..self.offset + range.end
+    }
+
+    /// Gets the number of cuts at token boundaries made because no cut point is found,
+    /// where the result can differ from that of the whole line.
+    pub const fn num_forced_cuts(&self) -> u64 {
+        self.num_forced_cuts
+    }
+
+    /// Takes back the worker.
+    pub fn into_worker(self) -> Worker<'t> {
+        self.worker
+    }
+}
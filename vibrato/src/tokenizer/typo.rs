@@ -0,0 +1,82 @@
+//! Typo-tolerant lookup of the lexicons.
+
+/// Settings of typo-tolerant lookup enabled with
+/// [`Tokenizer::typo_tolerance()`](crate::Tokenizer::typo_tolerance).
+///
+/// In addition to exact matches, words in the lexicons are inserted into the lattice if
+/// their surfaces match the input within one edit (i.e., insertion, deletion, or substitution
+/// of a character) and/or after collapsing runs of the same character into one,
+/// e.g., `ありがとぉ` and `すごーーーい` for `ありがとう` and `すごーい`.
+/// Such candidates have the additional cost specified with [`Self::penalty()`].
+/// Words shorter than three characters are not matched with an edit.
+/// With both enabled, edits are searched on the input both as is and after collapsing,
+/// so words containing a run, such as `いいえ`, are still matched with an edit.
+///
+/// # Examples
+///
+/// ```
+/// use vibrato::tokenizer::typo::TypoTolerance;
+///
+/// let typo = TypoTolerance::new().penalty(5000).edits(false);
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TypoTolerance {
+    penalty: i16,
+    edits: bool,
+    collapse_repeats: bool,
+}
+
+impl Default for TypoTolerance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TypoTolerance {
+    /// Creates a new instance with the default settings,
+    /// which enables both edits and collapsing with a penalty of `3000`.
+    pub const fn new() -> Self {
+        Self {
+            penalty: 3000,
+            edits: true,
+            collapse_repeats: true,
+        }
+    }
+
+    /// Specifies the additional cost of a candidate.
+    pub const fn penalty(mut self, penalty: i16) -> Self {
+        self.penalty = penalty;
+        self
+    }
+
+    /// Matches words within one edit.
+    pub const fn edits(mut self, yes: bool) -> Self {
+        self.edits = yes;
+        self
+    }
+
+    /// Matches words after collapsing runs of the same character in the input into one.
+    pub const fn collapse_repeats(mut self, yes: bool) -> Self {
+        self.collapse_repeats = yes;
+        self
+    }
+
+    #[inline(always)]
+    pub(crate) const fn penalty_cost(&self) -> i32 {
+        self.penalty as i32
+    }
+
+    #[inline(always)]
+    pub(crate) const fn max_distance(&self) -> usize {
+        if self.edits {
+            1
+        } else {
+            0
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) const fn collapses_repeats(&self) -> bool {
+        self.collapse_repeats
+    }
+}
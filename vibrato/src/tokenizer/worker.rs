@@ -1,8 +1,14 @@
 //! Provider of a routine for tokenization.
+use crate::common::BOS_EOS_CONNECTION_ID;
 use crate::dictionary::connector::Connector;
 use crate::dictionary::mapper::{ConnIdCounter, ConnIdProbs};
+use crate::dictionary::word_idx::WordIdx;
+use crate::errors::Result;
+use crate::normalizer::Alignment;
 use crate::sentence::Sentence;
 use crate::token::{Token, TokenIter};
+use crate::tokenizer::beam::PruningStats;
+use crate::tokenizer::cache::{CacheStats, ConnectionCache};
 use crate::tokenizer::lattice::{Lattice, Node};
 use crate::tokenizer::Tokenizer;
 
@@ -13,9 +19,21 @@ use crate::tokenizer::Tokenizer;
 pub struct Worker<'t> {
     pub(crate) tokenizer: &'t Tokenizer,
     pub(crate) sent: Sentence,
+    // The original input and the alignment to it, used only if a normalizer is set.
+    pub(crate) original: String,
+    pub(crate) normalized: String,
+    pub(crate) alignment: Alignment,
     pub(crate) lattice: Lattice,
     pub(crate) top_nodes: Vec<(usize, Node)>,
     pub(crate) counter: Option<ConnIdCounter>,
+    // Scratch buffer to deduplicate typo candidates at each position.
+    typo_candidates: Vec<(WordIdx, usize)>,
+    connection_cache: Option<ConnectionCache>,
+    pruning_stats: PruningStats,
+    changed_by_pruning: Option<bool>,
+    // Used only to verify the results of beam pruning.
+    exact_lattice: Lattice,
+    exact_top_nodes: Vec<(usize, Node)>,
 }
 
 impl<'t> Worker<'t> {
@@ -24,34 +42,204 @@ impl<'t> Worker<'t> {
         Self {
             tokenizer,
             sent: Sentence::new(),
+            original: String::new(),
+            normalized: String::new(),
+            alignment: Alignment::default(),
             lattice: Lattice::default(),
             top_nodes: vec![],
             counter: None,
+            typo_candidates: vec![],
+            connection_cache: tokenizer.new_connection_cache(),
+            pruning_stats: PruningStats::default(),
+            changed_by_pruning: None,
+            exact_lattice: Lattice::default(),
+            exact_top_nodes: vec![],
         }
     }
 
     /// Resets the input sentence to be tokenized.
+    ///
+    /// If a normalizer is set with [`Tokenizer::normalizer()`],
+    /// the normalized sentence is tokenized.
     pub fn reset_sentence<S>(&mut self, input: S)
     where
         S: AsRef<str>,
     {
         self.sent.clear();
         self.top_nodes.clear();
-        let input = input.as_ref();
+        let mut input = input.as_ref();
+        if let Some(normalizer) = self.tokenizer.normalizer_ref() {
+            self.original.clear();
+            self.original.push_str(input);
+            normalizer.normalize_with_alignment(input, &mut self.normalized, &mut self.alignment);
+            input = &self.normalized;
+        }
         if !input.is_empty() {
             self.sent.set_sentence(input);
             self.sent.compile(self.tokenizer.dictionary().char_prop());
+            if self.tokenizer.respects_grapheme_clusters() {
+                self.sent.compute_boundaries();
+            }
+            if self.tokenizer.matches_folded() {
+                self.sent
+                    .compute_folded(self.tokenizer.dictionary().folding());
+            }
+            if self
+                .tokenizer
+                .typo_tolerance_ref()
+                .is_some_and(|typo| typo.collapses_repeats())
+            {
+                self.sent.compute_collapsed();
+            }
         }
     }
 
     /// Tokenizes the input sentence set in `state`,
     /// returning the result through `state`.
+    ///
+    /// # Panics
+    ///
+    /// It panics if the sentence is too long to be tokenized, i.e., when
+    /// [`Self::try_tokenize()`] returns an error.
     pub fn tokenize(&mut self) {
+        self.try_tokenize().unwrap();
+    }
+
+    /// Tokenizes the input sentence set in `state`,
+    /// returning the result through `state`.
+    ///
+    /// # Errors
+    ///
+    /// [`VibratoError`](crate::errors::VibratoError) is returned when the sentence has more than
+    /// [`MAX_SENTENCE_LENGTH`](crate::common::MAX_SENTENCE_LENGTH) characters or
+    /// the candidate words cannot be indexed in 32 bits.
+    /// No token is given then.
+    pub fn try_tokenize(&mut self) -> Result<()> {
+        self.tokenize_after(BOS_EOS_CONNECTION_ID)
+    }
+
+    /// Tokenizes the input sentence following a token whose right id is `bos_right_id`,
+    /// or following BOS if it is [`BOS_EOS_CONNECTION_ID`].
+    pub(crate) fn tokenize_after(&mut self, bos_right_id: u16) -> Result<()> {
+        self.changed_by_pruning = None;
+        self.lattice.set_bos_right_id(bos_right_id);
+        self.exact_lattice.set_bos_right_id(bos_right_id);
+        self.top_nodes.clear();
         if self.sent.chars().is_empty() {
-            return;
+            return Ok(());
         }
-        self.tokenizer.build_lattice(&self.sent, &mut self.lattice);
+        let beam = self.tokenizer.beam_pruning_ref().copied();
+        self.tokenizer.build_lattice(
+            &self.sent,
+            &mut self.lattice,
+            &mut self.typo_candidates,
+            self.connection_cache.as_ref(),
+            beam,
+        )?;
         self.lattice.append_top_nodes(&mut self.top_nodes);
+
+        if let Some(beam) = beam {
+            let num_pruned = self.lattice.num_pruned();
+            self.pruning_stats.sentences += 1;
+            self.pruning_stats.pruned_nodes += num_pruned as u64;
+            if num_pruned == 0 {
+                self.changed_by_pruning = Some(false);
+            } else {
+                self.pruning_stats.pruned_sentences += 1;
+                if beam.verifies() {
+                    let changed = self.differs_from_exact_search()?;
+                    self.pruning_stats.changed_sentences += u64::from(changed);
+                    self.changed_by_pruning = Some(changed);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks if the result differs from that of the exact search.
+    fn differs_from_exact_search(&mut self) -> Result<bool> {
+        // The cache is not used so as not to affect its statistics and contents.
+        self.tokenizer.build_lattice(
+            &self.sent,
+            &mut self.exact_lattice,
+            &mut self.typo_candidates,
+            None,
+            None,
+        )?;
+        self.exact_top_nodes.clear();
+        self.exact_lattice
+            .append_top_nodes(&mut self.exact_top_nodes);
+        Ok(self.top_nodes.len() != self.exact_top_nodes.len()
+            || self.top_nodes.iter().zip(&self.exact_top_nodes).any(
+                |((end, n), (exact_end, e))| {
+                    end != exact_end || n.start_word != e.start_word || n.word_idx() != e.word_idx()
+                },
+            ))
+    }
+
+    /// Truncates the result to the tokens before the last cut point up to `limit` characters,
+    /// returning the position of the cut point.
+    ///
+    /// At a cut point, only one node ends and no node spans in the lattice, so the tokens
+    /// before it are the same as those of the whole text containing the sentence.
+    pub(crate) fn truncate_at_cut_point(&mut self, limit: usize) -> Option<usize> {
+        let chars = self.sent.chars();
+        let alignment = &self.alignment;
+        let normalized = self.tokenizer.normalizer_ref().is_some();
+        let cut_point = self.lattice.last_cut_point(limit, |pos| {
+            // Repeated characters are not separated since they can be collapsed.
+            chars.get(pos).is_some_and(|&c| c != chars[pos - 1])
+                && (!normalized || alignment.is_cluster_boundary(pos))
+        })?;
+        self.top_nodes.clear();
+        self.lattice
+            .append_top_nodes_to(cut_point, &mut self.top_nodes);
+        Some(cut_point)
+    }
+
+    /// Truncates the result to the tokens ending up to `limit` characters,
+    /// returning the end position of the last token.
+    pub(crate) fn truncate_at_token_boundary(&mut self, limit: usize) -> Option<usize> {
+        // The tokens are stored in reverse order.
+        let i = self.top_nodes.iter().position(|&(end, _)| {
+            end <= limit
+                && (self.tokenizer.normalizer_ref().is_none()
+                    || self.alignment.is_cluster_boundary(end))
+        })?;
+        self.top_nodes.drain(..i);
+        Some(self.top_nodes[0].0)
+    }
+
+    /// Gets the byte position in the original input of a character position in the sentence.
+    pub(crate) fn original_byte_position(&self, pos_char: usize) -> usize {
+        if self.tokenizer.normalizer_ref().is_some() {
+            self.alignment.byte_position(pos_char, self.original.len())
+        } else {
+            self.sent.byte_position(pos_char)
+        }
+    }
+
+    /// Checks if beam pruning changed the result of the last tokenization.
+    ///
+    /// `Some(false)` is returned if no node is pruned, since the result is exact.
+    /// Otherwise, the result is compared with that of the exact search only if
+    /// [`BeamPruning::verify()`](crate::tokenizer::beam::BeamPruning::verify) is enabled,
+    /// and `None` is returned if it is disabled or beam pruning is not used.
+    pub const fn changed_by_pruning(&self) -> Option<bool> {
+        self.changed_by_pruning
+    }
+
+    /// Gets the statistics of beam pruning since the creation of the worker
+    /// or the last call of [`Self::reset_pruning_stats()`].
+    ///
+    /// See [`Tokenizer::beam_pruning()`].
+    pub const fn pruning_stats(&self) -> PruningStats {
+        self.pruning_stats
+    }
+
+    /// Resets the statistics of beam pruning.
+    pub fn reset_pruning_stats(&mut self) {
+        self.pruning_stats = PruningStats::default();
     }
 
     /// Gets the number of resultant tokens.
@@ -73,6 +261,22 @@ impl<'t> Worker<'t> {
         TokenIter::new(self, 0)
     }
 
+    /// Gets the statistics of the connection cost cache since the creation of the worker
+    /// or the last call of [`Self::reset_connection_cache_stats()`].
+    ///
+    /// `None` is returned if the cache is not used.
+    /// See [`Tokenizer::connection_cache()`].
+    pub fn connection_cache_stats(&self) -> Option<CacheStats> {
+        self.connection_cache.as_ref().map(ConnectionCache::stats)
+    }
+
+    /// Resets the statistics of the connection cost cache.
+    pub fn reset_connection_cache_stats(&mut self) {
+        if let Some(cache) = self.connection_cache.as_ref() {
+            cache.reset_stats();
+        }
+    }
+
     /// Initializes a counter to compute occurrence probabilities of connection ids.
     pub fn init_connid_counter(&mut self) {
         let connector = self.tokenizer.dictionary().connector();
@@ -79,7 +79,7 @@ use hashbrown::{HashMap, HashSet};
 use rucrf::{Edge, FeatureProvider, FeatureSet, Lattice};
 
 use crate::dictionary::word_idx::WordIdx;
-use crate::dictionary::LexType;
+use crate::dictionary::{CostWidth, LexType};
 use crate::errors::Result;
 pub use crate::trainer::config::TrainerConfig;
 pub use crate::trainer::corpus::{Corpus, Example, Word};
@@ -283,7 +283,13 @@ impl Trainer {
                     self.config
                         .dict
                         .unk_handler()
-                        .compatible_unk_index(sentence, pos, pos + len, token.feature())
+                        .compatible_unk_index(
+                            sentence,
+                            self.config.dict.char_prop(),
+                            pos,
+                            pos + len,
+                            token.feature(),
+                        )
                         .map_or_else(
                             || {
                                 eprintln!(
@@ -338,6 +344,7 @@ impl Trainer {
 
             self.config.dict.unk_handler().gen_unk_words(
                 sentence,
+                self.config.dict.char_prop(),
                 start_word,
                 has_matched,
                 self.max_grouping_len,
@@ -464,6 +471,7 @@ impl Trainer {
             },
             merged_model: None,
             user_entries: vec![],
+            cost_width: CostWidth::Bits16,
         })
     }
 }
@@ -11,7 +11,7 @@ use crate::dictionary::character::CharProperty;
 use crate::dictionary::connector::{ConnectorWrapper, MatrixConnector};
 use crate::dictionary::lexicon::Lexicon;
 use crate::dictionary::unknown::UnkHandler;
-use crate::dictionary::{Dictionary, SystemDictionaryBuilder};
+use crate::dictionary::{CostWidth, Dictionary, Folding, SourceEncoding, SystemDictionaryBuilder};
 use crate::errors::{Result, VibratoError};
 use crate::trainer::feature_extractor::FeatureExtractor;
 use crate::trainer::feature_rewriter::{FeatureRewriter, FeatureRewriterBuilder};
@@ -70,9 +70,10 @@ impl TrainerConfig {
         let mut unigram_templates = vec![];
         let mut bigram_templates = vec![];
 
-        for line in reader.lines() {
+        for (i, line) in reader.lines().enumerate() {
             let line = line?;
             let line = line.trim();
+            let line_no = i + 1;
 
             if line.is_empty() || line.starts_with('#') {
                 continue;
@@ -87,13 +88,20 @@ impl TrainerConfig {
                 if let (Some(left), Some(right), None) = (left, right, rest) {
                     bigram_templates.push((left.to_string(), right.to_string()));
                 } else {
-                    return Err(VibratoError::invalid_format(
+                    return Err(VibratoError::invalid_format_at(
                         "feature.def",
+                        line_no,
+                        None,
                         "Invalid bigram template",
                     ));
                 }
             } else {
-                return Err(VibratoError::invalid_format("feature", ""));
+                return Err(VibratoError::invalid_format_at(
+                    "feature.def",
+                    line_no,
+                    None,
+                    "A template must start with UNIGRAM or BIGRAM",
+                ));
             }
         }
 
@@ -128,9 +136,10 @@ impl TrainerConfig {
         let mut right_rewriter_builder = FeatureRewriterBuilder::new();
 
         let mut builder = None;
-        for line in reader.lines() {
+        for (i, line) in reader.lines().enumerate() {
             let line = line?;
             let line = line.trim();
+            let line_no = i + 1;
 
             if line.is_empty() || line.starts_with('#') {
                 continue;
@@ -142,12 +151,15 @@ impl TrainerConfig {
                 "[right rewrite]" => builder = Some(&mut right_rewriter_builder),
                 line => {
                     if let Some(builder) = builder.as_mut() {
-                        let (pattern, rewrite) = Self::parse_rewrite_rule(line)?;
+                        let (pattern, rewrite) = Self::parse_rewrite_rule(line)
+                            .map_err(|e| e.at("rewrite.def", line_no, None))?;
                         builder.add_rule(&pattern, &rewrite);
                     } else {
-                        return Err(VibratoError::invalid_format(
+                        return Err(VibratoError::invalid_format_at(
                             "rewrite.def",
-                            "Invalid rewrite rule",
+                            line_no,
+                            None,
+                            "A rewrite rule must follow a section header",
                         ));
                     }
                 }
@@ -173,7 +185,7 @@ impl TrainerConfig {
     ///
     /// [`VibratoError`] is returned when an input format is invalid.
     pub fn from_readers<L, C, U, F, R>(
-        mut lexicon_rdr: L,
+        lexicon_rdr: L,
         char_prop_rdr: C,
         unk_handler_rdr: U,
         feature_templates_rdr: F,
@@ -186,22 +198,67 @@ impl TrainerConfig {
         F: Read,
         R: Read,
     {
-        let feature_extractor = Self::parse_feature_config(feature_templates_rdr)?;
+        Self::from_readers_with_encoding(
+            lexicon_rdr,
+            char_prop_rdr,
+            unk_handler_rdr,
+            feature_templates_rdr,
+            rewrite_rules_rdr,
+            SourceEncoding::Utf8,
+        )
+    }
+
+    /// Loads a training configuration from readers in the given character encoding.
+    ///
+    /// All the inputs are transcoded into UTF-8 on read.
+    /// See [`from_readers()`](Self::from_readers) for the other arguments.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoding` - Character encoding of the inputs.
+    ///
+    /// # Errors
+    ///
+    /// [`VibratoError`] is returned when an input format is invalid
+    /// or when an input includes an invalid byte sequence.
+    pub fn from_readers_with_encoding<L, C, U, F, R>(
+        lexicon_rdr: L,
+        char_prop_rdr: C,
+        unk_handler_rdr: U,
+        feature_templates_rdr: F,
+        rewrite_rules_rdr: R,
+        encoding: SourceEncoding,
+    ) -> Result<Self>
+    where
+        L: Read,
+        C: Read,
+        U: Read,
+        F: Read,
+        R: Read,
+    {
+        let feature_templates_data = encoding.read_to_utf8(feature_templates_rdr, "feature.def")?;
+        let feature_extractor = Self::parse_feature_config(feature_templates_data.as_slice())?;
+        let rewrite_rules_data = encoding.read_to_utf8(rewrite_rules_rdr, "rewrite.def")?;
         let (unigram_rewriter, left_rewriter, right_rewriter) =
-            Self::parse_rewrite_config(rewrite_rules_rdr)?;
+            Self::parse_rewrite_config(rewrite_rules_data.as_slice())?;
 
-        let mut lexicon_data = vec![];
-        lexicon_rdr.read_to_end(&mut lexicon_data)?;
-        let lex_entries = Lexicon::parse_csv(&lexicon_data, "lex.csv")?;
+        let lexicon_data = encoding.read_to_utf8(lexicon_rdr, "lex.csv")?;
+        let (lex_entries, _) = Lexicon::parse_csv(&lexicon_data, "lex.csv", CostWidth::Bits16)?;
         let connector = MatrixConnector::from_reader(b"1 1\n0 0 0".as_slice())?;
-        let char_prop = CharProperty::from_reader(char_prop_rdr)?;
-        let unk_handler = UnkHandler::from_reader(unk_handler_rdr, &char_prop)?;
+        let char_prop_data = encoding.read_to_utf8(char_prop_rdr, "char.def")?;
+        let char_prop = CharProperty::from_reader(char_prop_data.as_slice())?;
+        let unk_handler_data = encoding.read_to_utf8(unk_handler_rdr, "unk.def")?;
+        let unk_handler =
+            UnkHandler::from_reader(unk_handler_data.as_slice(), &char_prop, CostWidth::Bits16)?;
 
-        let dict = SystemDictionaryBuilder::build(
+        let dict = SystemDictionaryBuilder::build_from_entries(
             &lex_entries,
             ConnectorWrapper::Matrix(connector),
             char_prop,
             unk_handler,
+            Folding::default(),
+            false,
+            CostWidth::Bits16,
         )?;
 
         let surfaces = lex_entries.into_iter().map(|e| e.surface).collect();
@@ -83,7 +83,7 @@ impl Corpus {
 
         let mut examples = vec![];
         let mut tokens = vec![];
-        for line in buf.lines() {
+        for (i, line) in buf.lines().enumerate() {
             let line = line?;
             let mut spl = line.split('\t');
             let surface = spl.next();
@@ -109,8 +109,10 @@ impl Corpus {
                     tokens = vec![];
                 }
                 _ => {
-                    return Err(VibratoError::invalid_format(
+                    return Err(VibratoError::invalid_format_at(
                         "rdr",
+                        i + 1,
+                        None,
                         "Each line must be a pair of a surface and features or `EOS`",
                     ))
                 }
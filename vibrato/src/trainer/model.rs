@@ -7,7 +7,7 @@ use hashbrown::HashMap;
 use crate::common;
 use crate::dictionary::lexicon::Lexicon;
 use crate::dictionary::word_idx::WordIdx;
-use crate::dictionary::{LexType, WordParam};
+use crate::dictionary::{CostWidth, LexType, SourceEncoding, WordParam};
 use crate::errors::Result;
 pub use crate::trainer::config::TrainerConfig;
 use crate::trainer::corpus::Word;
@@ -29,9 +29,46 @@ pub struct Model {
     pub(crate) merged_model: Option<rucrf::MergedModel>,
 
     pub(crate) user_entries: Vec<(Word, WordParam, NonZeroU32)>,
+
+    pub(crate) cost_width: CostWidth,
 }
 
 impl Model {
+    /// Sets the width of the costs in the output dictionary.
+    /// The default value is [`CostWidth::Bits16`].
+    ///
+    /// Weights are scaled to fill the range of the width, so [`CostWidth::Bits32`] keeps
+    /// the precision of models trained with large weights.
+    /// This must be called before reading the user-defined lexicon file,
+    /// whose costs are parsed with the width.
+    pub fn set_cost_width(&mut self, cost_width: CostWidth) {
+        self.merged_model = None;
+        self.cost_width = cost_width;
+    }
+
+    /// Gets the width of the costs in the output dictionary.
+    pub const fn cost_width(&self) -> CostWidth {
+        self.cost_width
+    }
+
+    /// Computes the factor to scale weights into the range of the cost width.
+    fn weight_scale_factor(&self, merged_model: &rucrf::MergedModel) -> f64 {
+        let mut weight_abs_max = 0f64;
+        for feature_set in &merged_model.feature_sets {
+            weight_abs_max = weight_abs_max.max(feature_set.weight.abs());
+        }
+        for hm in &merged_model.matrix {
+            for &w in hm.values() {
+                weight_abs_max = weight_abs_max.max(w.abs());
+            }
+        }
+        let cost_max = match self.cost_width {
+            CostWidth::Bits16 => f64::from(i16::MAX),
+            CostWidth::Bits32 => f64::from(i32::MAX),
+        };
+        cost_max / weight_abs_max
+    }
+
     /// Reads the user-defined lexicon file.
     ///
     /// If you want to assign parameters to the user-defined lexicon file, you need to call this
@@ -45,15 +82,39 @@ impl Model {
     /// # Errors
     ///
     /// [`VibratoError`](crate::errors::VibratoError) is returned when the reading fails.
-    pub fn read_user_lexicon<R>(&mut self, mut rdr: R) -> Result<()>
+    pub fn read_user_lexicon<R>(&mut self, rdr: R) -> Result<()>
     where
         R: Read,
     {
-        let mut bytes = vec![];
-        rdr.read_to_end(&mut bytes)?;
+        self.read_user_lexicon_with_encoding(rdr, SourceEncoding::Utf8)
+    }
+
+    /// Reads the user-defined lexicon file in the given character encoding.
+    ///
+    /// The file is transcoded into UTF-8 on read.
+    /// See [`read_user_lexicon()`](Self::read_user_lexicon) for the details.
+    ///
+    /// # Arguments
+    ///
+    /// * `rdr` - Read sink of the user-defined lexicon file.
+    /// * `encoding` - Character encoding of the file.
+    ///
+    /// # Errors
+    ///
+    /// [`VibratoError`](crate::errors::VibratoError) is returned when the reading fails
+    /// or when the file includes an invalid byte sequence.
+    pub fn read_user_lexicon_with_encoding<R>(
+        &mut self,
+        rdr: R,
+        encoding: SourceEncoding,
+    ) -> Result<()>
+    where
+        R: Read,
+    {
+        let bytes = encoding.read_to_utf8(rdr, "user.csv")?;
 
         self.merged_model = None;
-        let entries = Lexicon::parse_csv(&bytes, "user.csv")?;
+        let (entries, _) = Lexicon::parse_csv(&bytes, "user.csv", self.cost_width)?;
         for entry in entries {
             let first_char = entry.surface.chars().next().unwrap();
             let cate_id = self
@@ -118,16 +179,7 @@ impl Model {
         let merged_model = self.merged_model.as_ref().unwrap();
 
         // scales weights.
-        let mut weight_abs_max = 0f64;
-        for feature_set in &merged_model.feature_sets {
-            weight_abs_max = weight_abs_max.max(feature_set.weight.abs());
-        }
-        for hm in &merged_model.matrix {
-            for &w in hm.values() {
-                weight_abs_max = weight_abs_max.max(w.abs());
-            }
-        }
-        let weight_scale_factor = f64::from(i16::MAX) / weight_abs_max;
+        let weight_scale_factor = self.weight_scale_factor(merged_model);
 
         let feature_extractor = &self.data.config.feature_extractor;
 
@@ -237,17 +289,8 @@ impl Model {
         let mut connector_wtr = BufWriter::new(connector_wtr);
         let mut user_lexicon_wtr = BufWriter::new(user_lexicon_wtr);
 
-        // scales weights to represent them in i16.
-        let mut weight_abs_max = 0f64;
-        for feature_set in &merged_model.feature_sets {
-            weight_abs_max = weight_abs_max.max(feature_set.weight.abs());
-        }
-        for hm in &merged_model.matrix {
-            for &w in hm.values() {
-                weight_abs_max = weight_abs_max.max(w.abs());
-            }
-        }
-        let weight_scale_factor = f64::from(i16::MAX) / weight_abs_max;
+        // scales weights to represent them in the cost width.
+        let weight_scale_factor = self.weight_scale_factor(merged_model);
 
         let config = &self.data.config;
 
@@ -265,7 +308,7 @@ impl Model {
                 ",{},{},{},{}",
                 feature_set.left_id,
                 feature_set.right_id,
-                (-feature_set.weight * weight_scale_factor) as i16,
+                (-feature_set.weight * weight_scale_factor) as i32,
                 feature,
             )?;
         }
@@ -286,7 +329,7 @@ impl Model {
                 cate_string,
                 feature_set.left_id,
                 feature_set.right_id,
-                (-feature_set.weight * weight_scale_factor) as i16,
+                (-feature_set.weight * weight_scale_factor) as i32,
                 feature,
             )?;
         }
@@ -306,7 +349,7 @@ impl Model {
                     "{} {} {}",
                     right_conn_id,
                     left_conn_id,
-                    (-w * weight_scale_factor) as i16
+                    (-w * weight_scale_factor) as i32
                 )?;
             }
         }
@@ -324,7 +367,7 @@ impl Model {
                     ",{},{},{},{}",
                     feature_set.left_id,
                     feature_set.right_id,
-                    (-feature_set.weight * weight_scale_factor) as i16,
+                    (-feature_set.weight * weight_scale_factor) as i32,
                     word.feature(),
                 )?;
             } else {
@@ -370,6 +413,7 @@ impl Model {
             data,
             merged_model: None,
             user_entries: vec![],
+            cost_width: CostWidth::Bits16,
         })
     }
 }
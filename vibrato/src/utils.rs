@@ -1,4 +1,3 @@
-#[cfg(feature = "train")]
 use std::io::Write;
 
 use csv_core::ReadFieldResult;
@@ -17,7 +16,6 @@ impl FromU32 for usize {
     }
 }
 
-#[cfg(feature = "train")]
 pub fn quote_csv_cell<W>(mut wtr: W, mut data: &[u8]) -> std::io::Result<()>
 where
     W: Write,
@@ -60,6 +58,80 @@ pub fn parse_csv_row(row: &str) -> Vec<String> {
     features
 }
 
+/// Builds a CSV row from the cells of `row` at the given column indices.
+///
+/// Columns can be reordered or repeated, and a column missing in `row` is filled with `*`.
+/// Cells are copied as they are, including their quotes.
+pub fn select_csv_cells(row: &str, columns: &[usize]) -> String {
+    let cells = split_csv_row(row);
+    let mut selected = String::new();
+    for (i, &col) in columns.iter().enumerate() {
+        if i != 0 {
+            selected.push(',');
+        }
+        selected.push_str(cells.get(col).copied().unwrap_or("*"));
+    }
+    selected
+}
+
+/// Splits a CSV row into cells without unquoting them.
+fn split_csv_row(row: &str) -> Vec<&str> {
+    let mut cells = vec![];
+    let mut start = 0;
+    // Whether the current cell begins with a quote, and whether the position is in quotes.
+    let mut quoted_cell = false;
+    let mut quoted = false;
+    for (i, b) in row.bytes().enumerate() {
+        match b {
+            // A quote is special only in a cell beginning with a quote.
+            b'"' if quoted_cell || i == start => {
+                quoted_cell = true;
+                quoted = !quoted;
+            }
+            b',' if !quoted => {
+                cells.push(&row[start..i]);
+                start = i + 1;
+                quoted_cell = false;
+            }
+            _ => {}
+        }
+    }
+    cells.push(&row[start..]);
+    cells
+}
+
+/// Converts a katakana character into the hiragana one, or returns it as it is.
+#[inline(always)]
+pub fn katakana_to_hiragana(c: char) -> char {
+    match c {
+        '\u{30A1}'..='\u{30F6}' | '\u{30FD}' | '\u{30FE}' => {
+            char::from_u32(u32::from(c) - 0x60).unwrap()
+        }
+        _ => c,
+    }
+}
+
+/// Converts a hiragana character into the katakana one, or returns it as it is.
+#[inline(always)]
+pub fn hiragana_to_katakana(c: char) -> char {
+    match c {
+        '\u{3041}'..='\u{3096}' | '\u{309D}' | '\u{309E}' => {
+            char::from_u32(u32::from(c) + 0x60).unwrap()
+        }
+        _ => c,
+    }
+}
+
+/// Converts katakana characters into hiragana ones.
+pub fn to_hiragana(s: &str) -> String {
+    s.chars().map(katakana_to_hiragana).collect()
+}
+
+/// Converts hiragana characters into katakana ones.
+pub fn to_katakana(s: &str) -> String {
+    s.chars().map(hiragana_to_katakana).collect()
+}
+
 #[cfg(test)]
 macro_rules! hashmap {
     ( $($k:expr => $v:expr,)* ) => {
@@ -99,4 +171,41 @@ mod tests {
             parse_csv_row("名詞,\"1,2-ジクロロエタン\"").as_slice()
         );
     }
+
+    #[test]
+    fn test_select_csv_cells() {
+        assert_eq!(
+            "トスカーナ,名詞,*",
+            select_csv_cells("名詞,固有名詞,トスカーナ", &[2, 0, 5])
+        );
+    }
+
+    #[test]
+    fn test_select_csv_cells_with_quote() {
+        assert_eq!(
+            "\"1,2-ジクロロエタン\",名詞",
+            select_csv_cells("名詞,\"1,2-ジクロロエタン\"", &[1, 0])
+        );
+    }
+
+    #[test]
+    fn test_select_csv_cells_with_inner_quote() {
+        assert_eq!(
+            "\"a\"\"b,c\",x\"y",
+            select_csv_cells("x\"y,\"a\"\"b,c\"", &[1, 0])
+        );
+    }
+
+    #[test]
+    fn test_select_csv_cells_empty() {
+        assert_eq!("", select_csv_cells("名詞,トスカーナ", &[]));
+    }
+
+    #[test]
+    fn test_kana_conversion() {
+        assert_eq!("ゔぁいぶらーとゝ", to_hiragana("ヴァイブラートヽ"));
+        assert_eq!("ヴァイブラートヽ", to_katakana("ゔぁいぶらーとゝ"));
+        assert_eq!("ABCあいう", to_hiragana("ABCアいウ"));
+        assert_eq!("ABCアイウ", to_katakana("ABCアいウ"));
+    }
 }